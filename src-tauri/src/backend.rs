@@ -0,0 +1,1551 @@
+use async_trait::async_trait;
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+pub const DEFAULT_OLLAMA_URL: &str = "http://localhost:11434";
+/// Port Ollama listens on when an address doesn't name one.
+const DEFAULT_OLLAMA_PORT: u16 = 11434;
+/// Environment variable Ollama itself reads its address from.
+pub const OLLAMA_HOST_ENV: &str = "OLLAMA_HOST";
+pub const DEFAULT_OPENAI_URL: &str = "http://localhost:8080/v1";
+
+/// Which kind of local server the enhancement requests are sent to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    #[default]
+    Ollama,
+    /// Any server speaking the OpenAI `/v1/chat/completions` protocol
+    /// (llama.cpp `llama-server`, vLLM, LM Studio, ...).
+    OpenAi,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackendConfig {
+    pub kind: BackendKind,
+    /// Ollama server address; empty means `OLLAMA_HOST` or the default.
+    pub ollama_url: String,
+    /// Base URL including the `/v1` prefix, e.g. `http://localhost:8080/v1`.
+    pub openai_url: String,
+    /// Sent as a bearer token when non-empty (vLLM `--api-key`, etc.).
+    pub openai_api_key: String,
+}
+
+impl Default for BackendConfig {
+    fn default() -> Self {
+        Self {
+            kind: BackendKind::Ollama,
+            ollama_url: "".to_string(),
+            openai_url: DEFAULT_OPENAI_URL.to_string(),
+            openai_api_key: "".to_string(),
+        }
+    }
+}
+
+/// Whether the selected model is loaded ahead of time, and how long Ollama
+/// keeps models in memory.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModelLoadingSettings {
+    /// Load the selected model at startup and whenever it changes, so the
+    /// first enhancement doesn't wait for it
+    pub preload: bool,
+    /// How long Ollama keeps a model loaded after a request: a duration such
+    /// as `10m` or `1h30m`, a number of seconds, `0` to unload right away or
+    /// `-1` to keep it forever. Empty leaves the server's default (5 minutes).
+    pub keep_alive: String,
+}
+
+impl ModelLoadingSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        let keep_alive = self.keep_alive.trim();
+        if keep_alive.is_empty() || keep_alive.parse::<f64>().is_ok_and(f64::is_finite) || is_duration(keep_alive) {
+            return Ok(());
+        }
+        Err(format!("Keep alive must be a duration like 10m or 1h, or a number of seconds, not \"{}\"", keep_alive))
+    }
+
+    /// `keep_alive` as Ollama expects it: numbers are seconds, strings are
+    /// durations.
+    pub fn keep_alive(&self) -> Option<serde_json::Value> {
+        let keep_alive = self.keep_alive.trim();
+        if keep_alive.is_empty() {
+            return None;
+        }
+        Some(match keep_alive.parse::<f64>().ok().and_then(serde_json::Number::from_f64) {
+            Some(seconds) => serde_json::Value::Number(seconds),
+            None => serde_json::Value::String(keep_alive.to_string()),
+        })
+    }
+}
+
+/// Whether `text` is a Go duration (what Ollama parses `keep_alive` strings
+/// with): an optional sign, then numbers each followed by a unit.
+fn is_duration(text: &str) -> bool {
+    const UNITS: [&str; 7] = ["ns", "us", "µs", "ms", "s", "m", "h"];
+
+    let mut rest = text.strip_prefix(['-', '+']).unwrap_or(text);
+    if rest.is_empty() {
+        return false;
+    }
+    while !rest.is_empty() {
+        let number_len = rest.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(rest.len());
+        if rest[..number_len].parse::<f64>().is_err() {
+            return false;
+        }
+        rest = &rest[number_len..];
+        // Longest unit first, so "ms" isn't read as "m"
+        let Some(unit) = UNITS.iter().filter(|unit| rest.starts_with(**unit)).max_by_key(|unit| unit.len()) else {
+            return false;
+        };
+        rest = &rest[unit.len()..];
+    }
+    true
+}
+
+/// Turn an Ollama address into a base URL, reading it the way Ollama reads
+/// `OLLAMA_HOST`: `host`, `host:port`, `:port` and full URLs (optionally with
+/// a path prefix, for reverse proxies) are all accepted. Without a scheme the
+/// address is plain `http` on port 11434, and an unspecified address such as
+/// `0.0.0.0` (what the server binds to) means this machine.
+pub fn normalize_ollama_url(address: &str) -> Result<String, String> {
+    let address = address.trim();
+    if address.is_empty() {
+        return Err("Ollama URL is empty".to_string());
+    }
+
+    let has_scheme = address.contains("://");
+    let full = if has_scheme {
+        address.to_string()
+    } else if address.starts_with(':') {
+        format!("http://127.0.0.1{}", address)
+    } else {
+        format!("http://{}", address)
+    };
+
+    let mut url = reqwest::Url::parse(&full)
+        .map_err(|e| format!("Invalid Ollama URL '{}': {}", address, e))?;
+
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(format!("Ollama URL '{}' must use http or https", address));
+    }
+    if url.host_str().is_none_or(str::is_empty) {
+        return Err(format!("Ollama URL '{}' has no host", address));
+    }
+    if url.query().is_some() || url.fragment().is_some() {
+        return Err(format!("Ollama URL '{}' must not contain a query or fragment", address));
+    }
+
+    if !has_scheme && url.port().is_none() {
+        // Can't fail: the URL is http(s) with a host
+        let _ = url.set_port(Some(DEFAULT_OLLAMA_PORT));
+    }
+    if matches!(url.host_str(), Some("0.0.0.0" | "[::]")) {
+        // Can't fail for the same reason
+        let _ = url.set_host(Some("127.0.0.1"));
+    }
+
+    Ok(url.as_str().trim_end_matches('/').to_string())
+}
+
+/// How the system prompt and the user's text are sent to the model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PromptMode {
+    /// Separate system/user (and few-shot assistant) messages through the
+    /// chat endpoint, so the input can't pose as instructions.
+    #[default]
+    Chat,
+    /// Everything concatenated into one prompt through the completion
+    /// endpoint, for raw completion models without a chat template.
+    Generate,
+}
+
+/// An example input/output pair shown to the model before the real input.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FewShotExample {
+    pub input: String,
+    pub output: String,
+}
+
+/// Sampling and length settings for a model. `None` leaves the server's
+/// default in place.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GenerationOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    /// Context window size in tokens (Ollama only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_ctx: Option<u32>,
+    /// Maximum number of tokens to generate; -1 means no limit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_predict: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+}
+
+impl GenerationOptions {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(temperature) = self.temperature {
+            if !(0.0..=2.0).contains(&temperature) {
+                return Err("Temperature must be between 0 and 2".to_string());
+            }
+        }
+        if self.num_ctx == Some(0) {
+            return Err("Context size must be greater than 0".to_string());
+        }
+        if let Some(num_predict) = self.num_predict {
+            if num_predict == 0 || num_predict < -1 {
+                return Err("Max tokens must be -1 (unlimited) or greater than 0".to_string());
+            }
+        }
+        if self.stop.iter().any(|s| s.is_empty()) {
+            return Err("Stop sequences must not be empty".to_string());
+        }
+        Ok(())
+    }
+
+    /// `max_tokens` for OpenAI-style requests, which have no "unlimited" value.
+    fn max_tokens(&self) -> Option<i32> {
+        self.num_predict.filter(|n| *n > 0)
+    }
+}
+
+/// Timing and token counts a server reported for one generation. Fields the
+/// server didn't report are `None`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GenerationStats {
+    pub total_duration_ms: Option<u64>,
+    /// Time spent loading the model into memory
+    pub load_duration_ms: Option<u64>,
+    pub prompt_eval_count: Option<u32>,
+    pub prompt_eval_duration_ms: Option<u64>,
+    /// Number of tokens generated
+    pub eval_count: Option<u32>,
+    pub eval_duration_ms: Option<u64>,
+}
+
+/// The result of one enhancement.
+#[derive(Debug, Clone)]
+pub struct Generation {
+    pub text: String,
+    pub stats: GenerationStats,
+}
+
+/// Why a generation failed.
+#[derive(Debug, Clone)]
+pub enum BackendError {
+    /// The server couldn't be reached, timed out, failed on its side (5xx)
+    /// or broke off mid-response
+    Unavailable(String),
+    /// The server answered but couldn't do what was asked, e.g. the model is
+    /// missing or an option is wrong
+    Rejected(String),
+}
+
+impl BackendError {
+    /// The error for a response with an error `status`.
+    fn from_status(status: reqwest::StatusCode, message: String) -> Self {
+        if status.is_server_error() {
+            Self::Unavailable(message)
+        } else {
+            Self::Rejected(message)
+        }
+    }
+}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unavailable(e) | Self::Rejected(e) => f.write_str(e),
+        }
+    }
+}
+
+/// What a model server lists about one of its models. Fields it didn't
+/// report are `None`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ModelInfo {
+    pub name: String,
+    /// Size on disk in bytes
+    pub size: Option<u64>,
+    /// When the model was last downloaded or changed (RFC 3339)
+    pub modified_at: Option<String>,
+    pub family: Option<String>,
+    /// e.g. "7.2B"
+    pub parameter_size: Option<String>,
+    /// e.g. "Q4_K_M"
+    pub quantization_level: Option<String>,
+}
+
+impl ModelInfo {
+    fn named(name: String) -> Self {
+        Self { name, ..Self::default() }
+    }
+}
+
+/// Everything a model server says about one model when asked about it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ModelDetails {
+    #[serde(flatten)]
+    pub info: ModelInfo,
+    /// The longest context the model was trained for, in tokens
+    pub context_length: Option<u32>,
+    /// The prompt template the server wraps messages in
+    pub template: Option<String>,
+    /// e.g. "completion", "tools", "vision"
+    pub capabilities: Vec<String>,
+}
+
+/// Callback receiving each piece of streamed text.
+pub type OnDelta<'a> = dyn Fn(&str) + Send + Sync + 'a;
+
+/// One progress report while a model downloads.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PullProgress {
+    /// What the server is doing: "pulling manifest", "pulling <digest>",
+    /// "verifying sha256 digest", "writing manifest", "success"
+    #[serde(default)]
+    pub status: String,
+    /// The layer being downloaded
+    pub digest: Option<String>,
+    /// Size of that layer in bytes
+    pub total: Option<u64>,
+    /// Bytes of that layer downloaded so far
+    pub completed: Option<u64>,
+}
+
+/// Called with each progress report of a model download.
+pub type OnPullProgress<'a> = dyn Fn(&PullProgress) + Send + Sync + 'a;
+
+/// Everything a backend needs to run one enhancement.
+#[derive(Debug, Clone)]
+pub struct EnhanceRequest<'a> {
+    pub model: &'a str,
+    pub system_prompt: &'a str,
+    pub input: &'a str,
+    pub mode: PromptMode,
+    pub examples: &'a [FewShotExample],
+    pub options: &'a GenerationOptions,
+    /// How long Ollama keeps the model loaded afterwards (see
+    /// [`ModelLoadingSettings::keep_alive`])
+    pub keep_alive: Option<&'a serde_json::Value>,
+}
+
+impl EnhanceRequest<'_> {
+    /// Messages for [`PromptMode::Chat`]: the system prompt, each example as a
+    /// user/assistant turn, then the input as the final user message.
+    fn chat_messages(&self) -> Vec<ChatMessage> {
+        let mut messages = vec![ChatMessage::new("system", self.system_prompt)];
+        for example in self.examples {
+            messages.push(ChatMessage::new("user", &example.input));
+            messages.push(ChatMessage::new("assistant", &example.output));
+        }
+        messages.push(ChatMessage::new("user", self.input));
+        messages
+    }
+
+    /// Single prompt for [`PromptMode::Generate`].
+    fn completion_prompt(&self) -> String {
+        let mut prompt = format!("{}\n\n", self.system_prompt);
+        for example in self.examples {
+            prompt.push_str(&format!("User input: {}\n\nEnhanced prompt: {}\n\n", example.input, example.output));
+        }
+        prompt.push_str(&format!("User input: {}\n\nEnhanced prompt:", self.input));
+        prompt
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ChatMessage {
+    role: String,
+    content: String,
+}
+
+impl ChatMessage {
+    fn new(role: &str, content: &str) -> Self {
+        Self { role: role.to_string(), content: content.to_string() }
+    }
+}
+
+/// The interface the command layer uses to talk to a model server.
+#[async_trait]
+pub trait LlmBackend: Send + Sync {
+    /// Human readable name used in logs and error messages.
+    fn name(&self) -> &'static str;
+
+    async fn test_connection(&self) -> Result<bool, String>;
+
+    async fn list_models(&self) -> Result<Vec<String>, String>;
+
+    async fn enhance(&self, request: &EnhanceRequest<'_>) -> Result<Generation, BackendError>;
+
+    /// Like [`LlmBackend::enhance`], but calls `on_delta` with each piece of
+    /// text as soon as the server produces it. Returns the accumulated text.
+    async fn enhance_stream(&self, request: &EnhanceRequest<'_>, on_delta: &OnDelta<'_>) -> Result<Generation, BackendError>;
+
+    /// Load `model` into memory without generating anything, keeping it for
+    /// `keep_alive`. Servers that manage this themselves do nothing.
+    async fn load_model(&self, _model: &str, _keep_alive: Option<&serde_json::Value>) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Unload every model the server holds in memory, returning their names.
+    async fn unload_models(&self) -> Result<Vec<String>, String> {
+        Err(format!("{} can't unload models, use the server's own tools", self.name()))
+    }
+
+    /// [`LlmBackend::list_models`] with whatever else the server says about
+    /// each model.
+    async fn list_model_info(&self) -> Result<Vec<ModelInfo>, String> {
+        Ok(self.list_models().await?.into_iter().map(ModelInfo::named).collect())
+    }
+
+    /// Look up one model's details. Servers that can't describe their models
+    /// only fill in the name.
+    async fn show_model(&self, model: &str) -> Result<ModelDetails, String> {
+        Ok(ModelDetails { info: ModelInfo::named(model.to_string()), ..ModelDetails::default() })
+    }
+
+    /// Download `model` onto the server, calling `on_progress` as it goes.
+    async fn pull_model(&self, _model: &str, _on_progress: &OnPullProgress<'_>) -> Result<(), String> {
+        Err(format!("{} can't download models, use the server's own tools", self.name()))
+    }
+
+    /// Remove `model` from the server.
+    async fn delete_model(&self, _model: &str) -> Result<(), String> {
+        Err(format!("{} can't delete models, use the server's own tools", self.name()))
+    }
+}
+
+/// Build the backend selected in `config`.
+pub fn create_backend(config: &BackendConfig, ollama_url: &str) -> Box<dyn LlmBackend> {
+    match config.kind {
+        BackendKind::Ollama => Box::new(OllamaBackend::new(ollama_url)),
+        BackendKind::OpenAi => Box::new(OpenAiBackend::new(&config.openai_url, &config.openai_api_key)),
+    }
+}
+
+fn trim_base_url(url: &str) -> String {
+    url.trim().trim_end_matches('/').to_string()
+}
+
+/// Splits a chunked response body into lines. Works on bytes so a multi-byte
+/// character split across two chunks is decoded intact.
+#[derive(Default)]
+struct LineBuffer {
+    pending: Vec<u8>,
+}
+
+impl LineBuffer {
+    fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.pending.extend_from_slice(bytes);
+
+        let mut lines = Vec::new();
+        while let Some(pos) = self.pending.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line).trim().to_string();
+            if !line.is_empty() {
+                lines.push(line);
+            }
+        }
+        lines
+    }
+
+    fn finish(&mut self) -> Option<String> {
+        let line = String::from_utf8_lossy(&self.pending).trim().to_string();
+        self.pending.clear();
+        if line.is_empty() { None } else { Some(line) }
+    }
+}
+
+/// Handles one line of a streamed generation, adding its text and calling
+/// `on_delta` with it. Returns `true` on the server's terminal line, having
+/// filled in the statistics.
+type StreamLineHandler = fn(&str, &mut String, &mut GenerationStats, &OnDelta<'_>) -> Result<bool, String>;
+
+/// Error for a stream that ended before the server said it was done, so the
+/// text is cut short.
+const STREAM_CLOSED_ERROR: &str = "The server closed the stream before finishing";
+
+/// Collects a streamed generation from the chunks of a response body.
+struct StreamedGeneration {
+    lines: LineBuffer,
+    text: String,
+    stats: GenerationStats,
+    handle_line: StreamLineHandler,
+}
+
+impl StreamedGeneration {
+    fn new(handle_line: StreamLineHandler) -> Self {
+        Self { lines: LineBuffer::default(), text: String::new(), stats: GenerationStats::default(), handle_line }
+    }
+
+    /// Handle the next chunk of the body, returning the generation once the
+    /// server sends its terminal line.
+    fn push(&mut self, bytes: &[u8], on_delta: &OnDelta<'_>) -> Result<Option<Generation>, BackendError> {
+        for line in self.lines.push(bytes) {
+            if self.handle(&line, on_delta)? {
+                return Ok(Some(self.take()));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Handle the end of the body. Unless its last line was the terminal one
+    /// the text is incomplete, which is an error rather than a result.
+    fn finish(mut self, on_delta: &OnDelta<'_>) -> Result<Generation, BackendError> {
+        if let Some(line) = self.lines.finish() {
+            if self.handle(&line, on_delta)? {
+                return Ok(self.take());
+            }
+        }
+        error!("Stream ended after {} chars without the server finishing", self.text.chars().count());
+        Err(BackendError::Unavailable(STREAM_CLOSED_ERROR.to_string()))
+    }
+
+    fn handle(&mut self, line: &str, on_delta: &OnDelta<'_>) -> Result<bool, BackendError> {
+        (self.handle_line)(line, &mut self.text, &mut self.stats, on_delta).map_err(BackendError::Rejected)
+    }
+
+    fn take(&mut self) -> Generation {
+        debug!("Stream finished ({} chars)", self.text.chars().count());
+        Generation { text: std::mem::take(&mut self.text), stats: std::mem::take(&mut self.stats) }
+    }
+
+    /// Read `response` to the end of the generation.
+    async fn read(mut self, mut response: reqwest::Response, on_delta: &OnDelta<'_>) -> Result<Generation, BackendError> {
+        while let Some(bytes) = response.chunk().await.map_err(|e| {
+            error!("Failed to read response stream: {}", e);
+            BackendError::Unavailable(format!("Failed to read response: {}", e))
+        })? {
+            if let Some(generation) = self.push(&bytes, on_delta)? {
+                return Ok(generation);
+            }
+        }
+        self.finish(on_delta)
+    }
+}
+
+/// The message in an error response, or the status if it has none. Ollama
+/// sends `{"error": "..."}`, OpenAI-style servers `{"error": {"message": "..."}}`.
+async fn error_message(response: reqwest::Response) -> String {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum ErrorBody {
+        Message(String),
+        Object { message: String },
+    }
+
+    #[derive(Deserialize)]
+    struct ErrorResponse {
+        error: ErrorBody,
+    }
+
+    let status = response.status();
+    match response.json::<ErrorResponse>().await {
+        Ok(ErrorResponse { error: ErrorBody::Message(message) | ErrorBody::Object { message } }) => message,
+        Err(_) => status.to_string(),
+    }
+}
+
+// Ollama implementation
+
+/// Response of both `/api/generate` (text in `response`) and `/api/chat`
+/// (text in `message`).
+#[derive(Debug, Serialize, Deserialize)]
+struct OllamaResponse {
+    model: String,
+    created_at: String,
+    #[serde(default)]
+    response: String,
+    message: Option<ChatMessage>,
+    done: bool,
+    done_reason: Option<String>,
+    context: Option<Vec<i32>>,
+    #[serde(flatten)]
+    stats: OllamaStats,
+}
+
+impl OllamaResponse {
+    fn into_generation(self) -> Generation {
+        let text = match self.message {
+            Some(message) => message.content,
+            None => self.response,
+        };
+        Generation { text, stats: self.stats.into() }
+    }
+}
+
+/// Statistics Ollama adds to its final response. Durations are in nanoseconds.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct OllamaStats {
+    total_duration: Option<u64>,
+    load_duration: Option<u64>,
+    prompt_eval_count: Option<u32>,
+    prompt_eval_duration: Option<u64>,
+    eval_count: Option<u32>,
+    eval_duration: Option<u64>,
+}
+
+impl From<OllamaStats> for GenerationStats {
+    fn from(stats: OllamaStats) -> Self {
+        let ms = |ns: Option<u64>| ns.map(|ns| ns / 1_000_000);
+        Self {
+            total_duration_ms: ms(stats.total_duration),
+            load_duration_ms: ms(stats.load_duration),
+            prompt_eval_count: stats.prompt_eval_count,
+            prompt_eval_duration_ms: ms(stats.prompt_eval_duration),
+            eval_count: stats.eval_count,
+            eval_duration_ms: ms(stats.eval_duration),
+        }
+    }
+}
+
+/// One line of Ollama's NDJSON stream, from either endpoint.
+#[derive(Debug, Deserialize)]
+struct OllamaStreamChunk {
+    #[serde(default)]
+    response: String,
+    message: Option<ChatMessage>,
+    #[serde(default)]
+    done: bool,
+    error: Option<String>,
+    /// Only filled in on the final chunk
+    #[serde(flatten)]
+    stats: OllamaStats,
+}
+
+/// Body of `/api/generate`. An empty prompt only loads (or, with a
+/// `keep_alive` of 0, unloads) the model.
+#[derive(Debug, Serialize, Deserialize)]
+struct OllamaRequest {
+    model: String,
+    prompt: String,
+    stream: bool,
+    #[serde(skip_serializing_if = "GenerationOptions::is_empty")]
+    options: GenerationOptions,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keep_alive: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+struct OllamaChatRequest {
+    model: String,
+    messages: Vec<ChatMessage>,
+    stream: bool,
+    #[serde(skip_serializing_if = "GenerationOptions::is_empty")]
+    options: GenerationOptions,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keep_alive: Option<serde_json::Value>,
+}
+
+/// The `details` Ollama gives for a model in `/api/tags` and `/api/show`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct OllamaModelDetails {
+    family: Option<String>,
+    parameter_size: Option<String>,
+    quantization_level: Option<String>,
+}
+
+/// One model in `/api/tags`.
+#[derive(Debug, Deserialize)]
+struct OllamaTagsModel {
+    name: String,
+    size: Option<u64>,
+    modified_at: Option<String>,
+    #[serde(default)]
+    details: OllamaModelDetails,
+}
+
+impl OllamaTagsModel {
+    fn into_info(self) -> ModelInfo {
+        ModelInfo {
+            name: self.name,
+            size: self.size,
+            modified_at: self.modified_at,
+            family: self.details.family.filter(|f| !f.is_empty()),
+            parameter_size: self.details.parameter_size.filter(|p| !p.is_empty()),
+            quantization_level: self.details.quantization_level.filter(|q| !q.is_empty()),
+        }
+    }
+}
+
+/// Response of `/api/show`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct OllamaShowResponse {
+    template: Option<String>,
+    modified_at: Option<String>,
+    details: OllamaModelDetails,
+    /// GGUF metadata, e.g. `general.architecture` and `llama.context_length`
+    model_info: serde_json::Map<String, serde_json::Value>,
+    capabilities: Vec<String>,
+}
+
+impl OllamaShowResponse {
+    /// The trained context length, stored under the model's architecture
+    /// (`<architecture>.context_length`).
+    fn context_length(&self) -> Option<u32> {
+        let key = match self.model_info.get("general.architecture").and_then(|a| a.as_str()) {
+            Some(architecture) => format!("{}.context_length", architecture),
+            None => self.model_info.keys().find(|key| key.ends_with(".context_length"))?.clone(),
+        };
+        self.model_info.get(&key)?.as_u64().and_then(|length| u32::try_from(length).ok())
+    }
+
+    fn into_details(self, name: &str) -> ModelDetails {
+        ModelDetails {
+            context_length: self.context_length(),
+            info: ModelInfo {
+                name: name.to_string(),
+                size: None,
+                modified_at: self.modified_at,
+                family: self.details.family.filter(|f| !f.is_empty()),
+                parameter_size: self.details.parameter_size.filter(|p| !p.is_empty()),
+                quantization_level: self.details.quantization_level.filter(|q| !q.is_empty()),
+            },
+            template: self.template.filter(|t| !t.is_empty()),
+            capabilities: self.capabilities,
+        }
+    }
+}
+
+/// Body of `/api/show`, `/api/pull` and `/api/delete`.
+#[derive(Debug, Serialize)]
+struct OllamaModelRequest<'a> {
+    model: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream: Option<bool>,
+}
+
+/// One line of `/api/pull`'s NDJSON stream.
+#[derive(Debug, Deserialize)]
+struct OllamaPullChunk {
+    error: Option<String>,
+    #[serde(flatten)]
+    progress: PullProgress,
+}
+
+impl OllamaPullChunk {
+    /// The status Ollama sends last when the model is ready.
+    const SUCCESS: &'static str = "success";
+}
+
+pub struct OllamaBackend {
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl OllamaBackend {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base_url: trim_base_url(base_url),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Send the request to `/api/chat` or `/api/generate` depending on its mode.
+    async fn send(&self, request: &EnhanceRequest<'_>, stream: bool) -> Result<reqwest::Response, BackendError> {
+        let builder = match request.mode {
+            PromptMode::Chat => {
+                debug!("Sending request to Ollama: {}/api/chat (stream: {})", self.base_url, stream);
+                self.client
+                    .post(format!("{}/api/chat", self.base_url))
+                    .json(&OllamaChatRequest {
+                        model: request.model.to_string(),
+                        messages: request.chat_messages(),
+                        stream,
+                        options: request.options.clone(),
+                        keep_alive: request.keep_alive.cloned(),
+                    })
+            },
+            PromptMode::Generate => {
+                debug!("Sending request to Ollama: {}/api/generate (stream: {})", self.base_url, stream);
+                self.client
+                    .post(format!("{}/api/generate", self.base_url))
+                    .json(&OllamaRequest {
+                        model: request.model.to_string(),
+                        prompt: request.completion_prompt(),
+                        stream,
+                        options: request.options.clone(),
+                        keep_alive: request.keep_alive.cloned(),
+                    })
+            },
+        };
+
+        let response = builder
+            .send()
+            .await
+            .map_err(|e| {
+                error!("Failed to send request to Ollama: {}", e);
+                BackendError::Unavailable(format!("Failed to send request: {}", e))
+            })?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let message = error_message(response).await;
+            error!("Ollama API returned error status {}: {}", status, message);
+            return Err(BackendError::from_status(status, format!("Ollama API error: {}", message)));
+        }
+
+        Ok(response)
+    }
+
+    /// Send an empty-prompt generate for `model`, which loads it and keeps
+    /// it for `keep_alive` (0 unloads it).
+    async fn set_loaded(&self, model: &str, keep_alive: Option<serde_json::Value>) -> Result<(), String> {
+        let response = self.client
+            .post(format!("{}/api/generate", self.base_url))
+            .json(&OllamaRequest {
+                model: model.to_string(),
+                prompt: String::new(),
+                stream: false,
+                options: GenerationOptions::default(),
+                keep_alive,
+            })
+            .send()
+            .await
+            .map_err(|e| format!("Failed to send request: {}", e))?;
+
+        if !response.status().is_success() {
+            return Err(error_message(response).await);
+        }
+        Ok(())
+    }
+
+    /// Handle one line of a pull, returning whether the model is ready.
+    fn handle_pull_line(line: &str, on_progress: &OnPullProgress<'_>) -> Result<bool, String> {
+        let chunk: OllamaPullChunk = serde_json::from_str(line).map_err(|e| {
+            error!("Failed to parse pull progress: {} ({})", e, line);
+            format!("Failed to parse response: {}", e)
+        })?;
+
+        if let Some(e) = chunk.error {
+            error!("Ollama failed to pull the model: {}", e);
+            return Err(format!("Failed to download model: {}", e));
+        }
+
+        on_progress(&chunk.progress);
+        Ok(chunk.progress.status == OllamaPullChunk::SUCCESS)
+    }
+
+    /// Handle one NDJSON line, returning `true` with the final statistics
+    /// once Ollama reports it is done.
+    fn handle_stream_line(line: &str, text: &mut String, stats: &mut GenerationStats, on_delta: &OnDelta<'_>) -> Result<bool, String> {
+        let chunk: OllamaStreamChunk = serde_json::from_str(line).map_err(|e| {
+            error!("Failed to parse stream chunk: {} ({})", e, line);
+            format!("Failed to parse response: {}", e)
+        })?;
+
+        if let Some(e) = chunk.error {
+            error!("Ollama reported an error mid-stream: {}", e);
+            return Err(format!("Ollama API error: {}", e));
+        }
+
+        let delta = match chunk.message {
+            Some(message) => message.content,
+            None => chunk.response,
+        };
+        if !delta.is_empty() {
+            text.push_str(&delta);
+            on_delta(&delta);
+        }
+
+        if chunk.done {
+            *stats = chunk.stats.into();
+        }
+        Ok(chunk.done)
+    }
+}
+
+#[async_trait]
+impl LlmBackend for OllamaBackend {
+    fn name(&self) -> &'static str {
+        "Ollama"
+    }
+
+    async fn test_connection(&self) -> Result<bool, String> {
+        debug!("Testing Ollama connection at: {}/api/tags", self.base_url);
+
+        match self.client.get(format!("{}/api/tags", self.base_url)).send().await {
+            Ok(response) => {
+                debug!("Connection test response status: {}", response.status());
+                Ok(true)
+            },
+            Err(e) => {
+                error!("Connection test failed: {}", e);
+                Err(format!("Connection failed: {}", e))
+            },
+        }
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, String> {
+        Ok(self.list_model_info().await?.into_iter().map(|m| m.name).collect())
+    }
+
+    async fn list_model_info(&self) -> Result<Vec<ModelInfo>, String> {
+        debug!("Getting available models from: {}/api/tags", self.base_url);
+
+        let response = self.client
+            .get(format!("{}/api/tags", self.base_url))
+            .send()
+            .await
+            .map_err(|e| format!("Failed to get models: {}", e))?;
+
+        debug!("Models response status: {}", response.status());
+
+        #[derive(Deserialize)]
+        struct ModelsResponse {
+            models: Vec<OllamaTagsModel>,
+        }
+
+        let response_text = response.text().await
+            .map_err(|e| format!("Failed to get models response text: {}", e))?;
+
+        debug!("Models response text: {}", response_text);
+
+        let models_response: ModelsResponse = serde_json::from_str(&response_text)
+            .map_err(|e| format!("Failed to parse models: {} (response: {})", e, response_text))?;
+
+        debug!("Parsed models: {:?}", models_response.models);
+
+        Ok(models_response.models.into_iter().map(OllamaTagsModel::into_info).collect())
+    }
+
+    async fn show_model(&self, model: &str) -> Result<ModelDetails, String> {
+        debug!("Getting details of {} from: {}/api/show", model, self.base_url);
+
+        let response = self.client
+            .post(format!("{}/api/show", self.base_url))
+            .json(&OllamaModelRequest { model, stream: None })
+            .send()
+            .await
+            .map_err(|e| format!("Failed to get model details: {}", e))?;
+
+        if !response.status().is_success() {
+            let message = error_message(response).await;
+            error!("Ollama couldn't describe {}: {}", model, message);
+            return Err(format!("Failed to get model details: {}", message));
+        }
+
+        let show: OllamaShowResponse = response.json().await
+            .map_err(|e| format!("Failed to parse model details: {}", e))?;
+        Ok(show.into_details(model))
+    }
+
+    async fn enhance(&self, request: &EnhanceRequest<'_>) -> Result<Generation, BackendError> {
+        let response = self.send(request, false).await?;
+
+        let response_text = response.text().await.map_err(|e| {
+            error!("Failed to read response text: {}", e);
+            BackendError::Unavailable(format!("Failed to read response: {}", e))
+        })?;
+
+        debug!("Raw Ollama response: {}", response_text);
+
+        let ollama_response: OllamaResponse = serde_json::from_str(&response_text)
+            .map_err(|e| {
+                error!("Failed to parse response: {}", e);
+                BackendError::Rejected(format!("Failed to parse response: {}", e))
+            })?;
+
+        debug!("Parsed Ollama response: {:?}", ollama_response);
+
+        Ok(ollama_response.into_generation())
+    }
+
+    async fn enhance_stream(&self, request: &EnhanceRequest<'_>, on_delta: &OnDelta<'_>) -> Result<Generation, BackendError> {
+        let response = self.send(request, true).await?;
+        StreamedGeneration::new(Self::handle_stream_line).read(response, on_delta).await
+    }
+
+    async fn load_model(&self, model: &str, keep_alive: Option<&serde_json::Value>) -> Result<(), String> {
+        debug!("Loading {} via {}/api/generate", model, self.base_url);
+        self.set_loaded(model, keep_alive.cloned()).await
+            .map_err(|e| format!("Failed to load model: {}", e))
+    }
+
+    async fn unload_models(&self) -> Result<Vec<String>, String> {
+        debug!("Getting loaded models from: {}/api/ps", self.base_url);
+
+        #[derive(Deserialize)]
+        struct RunningResponse {
+            models: Vec<RunningModel>,
+        }
+
+        #[derive(Deserialize)]
+        struct RunningModel {
+            name: String,
+        }
+
+        let response = self.client
+            .get(format!("{}/api/ps", self.base_url))
+            .send()
+            .await
+            .map_err(|e| format!("Failed to get loaded models: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!("Failed to get loaded models: {}", error_message(response).await));
+        }
+        let running: RunningResponse = response.json().await
+            .map_err(|e| format!("Failed to parse loaded models: {}", e))?;
+
+        let mut unloaded = Vec::new();
+        for model in running.models {
+            debug!("Unloading {}", model.name);
+            self.set_loaded(&model.name, Some(serde_json::Value::from(0))).await
+                .map_err(|e| format!("Failed to unload {}: {}", model.name, e))?;
+            unloaded.push(model.name);
+        }
+        Ok(unloaded)
+    }
+
+    async fn pull_model(&self, model: &str, on_progress: &OnPullProgress<'_>) -> Result<(), String> {
+        debug!("Pulling {} via {}/api/pull", model, self.base_url);
+
+        let mut response = self.client
+            .post(format!("{}/api/pull", self.base_url))
+            .json(&OllamaModelRequest { model, stream: Some(true) })
+            .send()
+            .await
+            .map_err(|e| format!("Failed to download model: {}", e))?;
+
+        if !response.status().is_success() {
+            let message = error_message(response).await;
+            error!("Ollama refused to pull {}: {}", model, message);
+            return Err(format!("Failed to download model: {}", message));
+        }
+
+        let mut lines = LineBuffer::default();
+        while let Some(bytes) = response.chunk().await
+            .map_err(|e| format!("Failed to download model: {}", e))?
+        {
+            for line in lines.push(&bytes) {
+                if Self::handle_pull_line(&line, on_progress)? {
+                    return Ok(());
+                }
+            }
+        }
+
+        match lines.finish() {
+            Some(line) if Self::handle_pull_line(&line, on_progress)? => Ok(()),
+            _ => Err("Failed to download model: the server stopped before it finished".to_string()),
+        }
+    }
+
+    async fn delete_model(&self, model: &str) -> Result<(), String> {
+        debug!("Deleting {} via {}/api/delete", model, self.base_url);
+
+        let response = self.client
+            .delete(format!("{}/api/delete", self.base_url))
+            .json(&OllamaModelRequest { model, stream: None })
+            .send()
+            .await
+            .map_err(|e| format!("Failed to delete model: {}", e))?;
+
+        if !response.status().is_success() {
+            let message = error_message(response).await;
+            error!("Ollama refused to delete {}: {}", model, message);
+            return Err(format!("Failed to delete model: {}", message));
+        }
+        Ok(())
+    }
+}
+
+// OpenAI-compatible implementation
+
+/// The generation options OpenAI-style APIs understand. `num_ctx` has no
+/// equivalent there; the server decides the context size.
+#[derive(Debug, Default, Serialize)]
+struct OpenAiSampling {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop: Vec<String>,
+}
+
+impl From<&GenerationOptions> for OpenAiSampling {
+    fn from(options: &GenerationOptions) -> Self {
+        Self {
+            temperature: options.temperature,
+            max_tokens: options.max_tokens(),
+            seed: options.seed,
+            stop: options.stop.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ChatCompletionRequest {
+    model: String,
+    messages: Vec<ChatMessage>,
+    stream: bool,
+    #[serde(flatten)]
+    sampling: OpenAiSampling,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatCompletionResponse {
+    choices: Vec<ChatCompletionChoice>,
+    usage: Option<Usage>,
+}
+
+/// Token counts reported by OpenAI-style servers.
+#[derive(Debug, Deserialize)]
+struct Usage {
+    prompt_tokens: Option<u32>,
+    completion_tokens: Option<u32>,
+}
+
+impl From<Usage> for GenerationStats {
+    fn from(usage: Usage) -> Self {
+        Self {
+            prompt_eval_count: usage.prompt_tokens,
+            eval_count: usage.completion_tokens,
+            ..Self::default()
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatCompletionChoice {
+    message: ChatMessage,
+}
+
+/// Streamed chunk of either `/chat/completions` (text in `delta`) or
+/// `/completions` (text in `text`).
+#[derive(Debug, Deserialize)]
+struct ChatCompletionChunk {
+    choices: Vec<ChatCompletionChunkChoice>,
+    /// Sent in the last chunk by servers that support it
+    usage: Option<Usage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatCompletionChunkChoice {
+    #[serde(default)]
+    delta: ChatCompletionDelta,
+    text: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct CompletionRequest {
+    model: String,
+    prompt: String,
+    stream: bool,
+    #[serde(flatten)]
+    sampling: OpenAiSampling,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompletionResponse {
+    choices: Vec<CompletionChoice>,
+    usage: Option<Usage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompletionChoice {
+    text: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ChatCompletionDelta {
+    content: Option<String>,
+}
+
+pub struct OpenAiBackend {
+    base_url: String,
+    api_key: String,
+    client: reqwest::Client,
+}
+
+impl OpenAiBackend {
+    pub fn new(base_url: &str, api_key: &str) -> Self {
+        Self {
+            base_url: trim_base_url(base_url),
+            api_key: api_key.trim().to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    fn get(&self, path: &str) -> reqwest::RequestBuilder {
+        self.authorize(self.client.get(format!("{}{}", self.base_url, path)))
+    }
+
+    fn post(&self, path: &str) -> reqwest::RequestBuilder {
+        self.authorize(self.client.post(format!("{}{}", self.base_url, path)))
+    }
+
+    fn authorize(&self, builder: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        if self.api_key.is_empty() {
+            builder
+        } else {
+            builder.bearer_auth(&self.api_key)
+        }
+    }
+
+    /// Send the request to `/chat/completions` or `/completions` depending on its mode.
+    async fn send(&self, request: &EnhanceRequest<'_>, stream: bool) -> Result<reqwest::Response, BackendError> {
+        let builder = match request.mode {
+            PromptMode::Chat => {
+                debug!("Sending request to OpenAI-compatible server: {}/chat/completions (stream: {})", self.base_url, stream);
+                self.post("/chat/completions")
+                    .json(&ChatCompletionRequest {
+                        model: request.model.to_string(),
+                        messages: request.chat_messages(),
+                        stream,
+                        sampling: request.options.into(),
+                    })
+            },
+            PromptMode::Generate => {
+                debug!("Sending request to OpenAI-compatible server: {}/completions (stream: {})", self.base_url, stream);
+                self.post("/completions")
+                    .json(&CompletionRequest {
+                        model: request.model.to_string(),
+                        prompt: request.completion_prompt(),
+                        stream,
+                        sampling: request.options.into(),
+                    })
+            },
+        };
+
+        let response = builder
+            .send()
+            .await
+            .map_err(|e| {
+                error!("Failed to send request to OpenAI-compatible server: {}", e);
+                BackendError::Unavailable(format!("Failed to send request: {}", e))
+            })?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let message = error_message(response).await;
+            error!("OpenAI-compatible API returned error status {}: {}", status, message);
+            return Err(BackendError::from_status(status, format!("OpenAI-compatible API error: {}", message)));
+        }
+
+        Ok(response)
+    }
+
+    /// Handle one server-sent event line, returning `true` on `data: [DONE]`.
+    fn handle_stream_line(line: &str, text: &mut String, stats: &mut GenerationStats, on_delta: &OnDelta<'_>) -> Result<bool, String> {
+        // Ignore comments, `event:` lines and anything else that isn't data
+        let Some(data) = line.strip_prefix("data:") else {
+            return Ok(false);
+        };
+        let data = data.trim();
+
+        if data == "[DONE]" {
+            return Ok(true);
+        }
+
+        let chunk: ChatCompletionChunk = serde_json::from_str(data).map_err(|e| {
+            error!("Failed to parse stream chunk: {} ({})", e, data);
+            format!("Failed to parse response: {}", e)
+        })?;
+
+        if let Some(usage) = chunk.usage {
+            *stats = usage.into();
+        }
+
+        if let Some(content) = chunk.choices.into_iter().next().and_then(|choice| choice.delta.content.or(choice.text)) {
+            if !content.is_empty() {
+                text.push_str(&content);
+                on_delta(&content);
+            }
+        }
+
+        Ok(false)
+    }
+}
+
+#[async_trait]
+impl LlmBackend for OpenAiBackend {
+    fn name(&self) -> &'static str {
+        "OpenAI-compatible server"
+    }
+
+    async fn test_connection(&self) -> Result<bool, String> {
+        debug!("Testing OpenAI-compatible connection at: {}/models", self.base_url);
+
+        match self.get("/models").send().await {
+            Ok(response) => {
+                debug!("Connection test response status: {}", response.status());
+                Ok(true)
+            },
+            Err(e) => {
+                error!("Connection test failed: {}", e);
+                Err(format!("Connection failed: {}", e))
+            },
+        }
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, String> {
+        debug!("Getting available models from: {}/models", self.base_url);
+
+        let response = self.get("/models")
+            .send()
+            .await
+            .map_err(|e| format!("Failed to get models: {}", e))?;
+
+        debug!("Models response status: {}", response.status());
+
+        #[derive(Deserialize)]
+        struct ModelsResponse {
+            data: Vec<ModelInfo>,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct ModelInfo {
+            id: String,
+        }
+
+        let response_text = response.text().await
+            .map_err(|e| format!("Failed to get models response text: {}", e))?;
+
+        debug!("Models response text: {}", response_text);
+
+        let models_response: ModelsResponse = serde_json::from_str(&response_text)
+            .map_err(|e| format!("Failed to parse models: {} (response: {})", e, response_text))?;
+
+        Ok(models_response.data.into_iter().map(|m| m.id).collect())
+    }
+
+    async fn enhance(&self, request: &EnhanceRequest<'_>) -> Result<Generation, BackendError> {
+        let response = self.send(request, false).await?;
+
+        let response_text = response.text().await.map_err(|e| {
+            error!("Failed to read response text: {}", e);
+            BackendError::Unavailable(format!("Failed to read response: {}", e))
+        })?;
+
+        debug!("Raw completion response: {}", response_text);
+
+        let parse_error = |e: serde_json::Error| {
+            error!("Failed to parse response: {}", e);
+            BackendError::Rejected(format!("Failed to parse response: {}", e))
+        };
+
+        let (text, usage) = match request.mode {
+            PromptMode::Chat => {
+                let response = serde_json::from_str::<ChatCompletionResponse>(&response_text).map_err(parse_error)?;
+                (response.choices.into_iter().next().map(|choice| choice.message.content), response.usage)
+            },
+            PromptMode::Generate => {
+                let response = serde_json::from_str::<CompletionResponse>(&response_text).map_err(parse_error)?;
+                (response.choices.into_iter().next().map(|choice| choice.text), response.usage)
+            },
+        };
+
+        let text = text.ok_or_else(|| BackendError::Rejected("Server returned no choices".to_string()))?;
+        Ok(Generation { text, stats: usage.map(Into::into).unwrap_or_default() })
+    }
+
+    async fn enhance_stream(&self, request: &EnhanceRequest<'_>, on_delta: &OnDelta<'_>) -> Result<Generation, BackendError> {
+        let response = self.send(request, true).await?;
+        StreamedGeneration::new(Self::handle_stream_line).read(response, on_delta).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Feed `chunks` through a stream as if they came off the network,
+    /// returning the result and the deltas passed to `on_delta`.
+    fn read_chunks(handle_line: StreamLineHandler, chunks: &[&str]) -> (Result<Generation, BackendError>, Vec<String>) {
+        let deltas = Mutex::new(Vec::new());
+        let on_delta = |delta: &str| deltas.lock().unwrap().push(delta.to_string());
+        let mut stream = StreamedGeneration::new(handle_line);
+        let mut result = None;
+        for chunk in chunks {
+            if let Some(generation) = stream.push(chunk.as_bytes(), &on_delta).transpose() {
+                result = Some(generation);
+                break;
+            }
+        }
+        let result = result.unwrap_or_else(|| stream.finish(&on_delta));
+        (result, deltas.into_inner().unwrap())
+    }
+
+    #[test]
+    fn line_buffer_joins_lines_split_across_chunks() {
+        let mut lines = LineBuffer::default();
+        assert!(lines.push(b"{\"done\":").is_empty());
+        assert_eq!(lines.push(b"false}\n\n{\"done\""), ["{\"done\":false}"]);
+        assert_eq!(lines.push(b":true}\r\n"), ["{\"done\":true}"]);
+        assert_eq!(lines.finish(), None);
+    }
+
+    #[test]
+    fn line_buffer_keeps_characters_split_across_chunks() {
+        let mut lines = LineBuffer::default();
+        let text = "café\n".as_bytes();
+        let (first, second) = text.split_at(4);
+        assert!(lines.push(first).is_empty());
+        assert_eq!(lines.push(second), ["café"]);
+    }
+
+    #[test]
+    fn line_buffer_returns_a_trailing_line_without_newline() {
+        let mut lines = LineBuffer::default();
+        assert_eq!(lines.push(b"one\ntwo"), ["one"]);
+        assert_eq!(lines.finish().as_deref(), Some("two"));
+        assert_eq!(lines.finish(), None);
+    }
+
+    #[test]
+    fn ollama_stream_collects_text_and_final_stats() {
+        let (result, deltas) = read_chunks(OllamaBackend::handle_stream_line, &[
+            "{\"message\":{\"role\":\"assistant\",\"content\":\"Hel\"},\"done\":false}\n{\"message\":",
+            "{\"role\":\"assistant\",\"content\":\"lo\"},\"done\":false}\n",
+            "{\"response\":\"\",\"done\":true,\"eval_count\":2,\"eval_duration\":4000000}",
+        ]);
+
+        let generation = result.unwrap();
+        assert_eq!(generation.text, "Hello");
+        assert_eq!(generation.stats.eval_count, Some(2));
+        assert_eq!(generation.stats.eval_duration_ms, Some(4));
+        assert_eq!(deltas, ["Hel", "lo"]);
+    }
+
+    #[test]
+    fn ollama_stream_reads_generate_responses() {
+        let (result, _) = read_chunks(OllamaBackend::handle_stream_line, &[
+            "{\"response\":\"Hi\",\"done\":false}\n{\"response\":\"!\",\"done\":true}\n",
+        ]);
+        assert_eq!(result.unwrap().text, "Hi!");
+    }
+
+    #[test]
+    fn ollama_stream_without_done_is_unavailable() {
+        let (result, deltas) = read_chunks(OllamaBackend::handle_stream_line, &[
+            "{\"response\":\"Half a\",\"done\":false}\n",
+            "{\"response\":\" sentence\",\"done\":false}",
+        ]);
+        assert!(matches!(result, Err(BackendError::Unavailable(e)) if e == STREAM_CLOSED_ERROR));
+        assert_eq!(deltas, ["Half a", " sentence"]);
+    }
+
+    #[test]
+    fn ollama_error_chunk_is_rejected() {
+        let (result, _) = read_chunks(OllamaBackend::handle_stream_line, &[
+            "{\"response\":\"Hi\",\"done\":false}\n{\"error\":\"model requires more system memory\"}\n",
+        ]);
+        assert!(matches!(result, Err(BackendError::Rejected(e)) if e == "Ollama API error: model requires more system memory"));
+    }
+
+    #[test]
+    fn openai_stream_reads_data_lines_until_done() {
+        let (result, deltas) = read_chunks(OpenAiBackend::handle_stream_line, &[
+            ": keep-alive\n\ndata: {\"choices\":[{\"delta\":{\"role\":\"assistant\"}}]}\n\n",
+            "data:{\"choices\":[{\"delta\":{\"content\":\"Hel\"}}]}\n\nevent: message\ndata: {\"choices\":[{\"delta\":{\"content\":\"lo\"}}]}\n\n",
+            "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":7,\"completion_tokens\":2}}\n\ndata: [DONE]\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\"ignored\"}}]}\n\n",
+        ]);
+
+        let generation = result.unwrap();
+        assert_eq!(generation.text, "Hello");
+        assert_eq!(generation.stats.prompt_eval_count, Some(7));
+        assert_eq!(generation.stats.eval_count, Some(2));
+        assert_eq!(deltas, ["Hel", "lo"]);
+    }
+
+    #[test]
+    fn openai_stream_reads_completions_and_a_trailing_done() {
+        let (result, _) = read_chunks(OpenAiBackend::handle_stream_line, &[
+            "data: {\"choices\":[{\"text\":\"Hi\"}]}\n\ndata: [DONE]",
+        ]);
+        assert_eq!(result.unwrap().text, "Hi");
+    }
+
+    #[test]
+    fn openai_stream_without_done_is_unavailable() {
+        let (result, _) = read_chunks(OpenAiBackend::handle_stream_line, &[
+            "data: {\"choices\":[{\"delta\":{\"content\":\"Half\"}}]}\n\n",
+        ]);
+        assert!(matches!(result, Err(BackendError::Unavailable(e)) if e == STREAM_CLOSED_ERROR));
+    }
+
+    #[test]
+    fn openai_unparsable_data_is_rejected() {
+        let (result, _) = read_chunks(OpenAiBackend::handle_stream_line, &["data: {\"error\":\n\n"]);
+        assert!(matches!(result, Err(BackendError::Rejected(_))));
+    }
+
+    #[test]
+    fn ollama_url_defaults_scheme_and_port() {
+        assert_eq!(normalize_ollama_url("localhost").unwrap(), "http://localhost:11434");
+        assert_eq!(normalize_ollama_url(" gpu-box ").unwrap(), "http://gpu-box:11434");
+        assert_eq!(normalize_ollama_url("gpu-box:8080").unwrap(), "http://gpu-box:8080");
+        assert_eq!(normalize_ollama_url(":8080").unwrap(), "http://127.0.0.1:8080");
+    }
+
+    #[test]
+    fn ollama_url_connects_to_unspecified_addresses_locally() {
+        assert_eq!(normalize_ollama_url("0.0.0.0").unwrap(), "http://127.0.0.1:11434");
+        assert_eq!(normalize_ollama_url("0.0.0.0:8080").unwrap(), "http://127.0.0.1:8080");
+        assert_eq!(normalize_ollama_url("http://[::]:11434").unwrap(), "http://127.0.0.1:11434");
+    }
+
+    #[test]
+    fn ollama_url_keeps_full_urls() {
+        assert_eq!(normalize_ollama_url("http://localhost:11434/").unwrap(), "http://localhost:11434");
+        assert_eq!(normalize_ollama_url("https://ollama.example.com").unwrap(), "https://ollama.example.com");
+        assert_eq!(normalize_ollama_url("https://example.com/ollama/").unwrap(), "https://example.com/ollama");
+    }
+
+    #[test]
+    fn ollama_url_rejects_garbage() {
+        for address in ["", "   ", "ftp://example.com", "http://", "http://host:port", "localhost?x=1", "local host"] {
+            assert!(normalize_ollama_url(address).is_err(), "{:?} was accepted", address);
+        }
+    }
+    fn loading(keep_alive: &str) -> ModelLoadingSettings {
+        ModelLoadingSettings { keep_alive: keep_alive.to_string(), ..ModelLoadingSettings::default() }
+    }
+
+    #[test]
+    fn durations_follow_go_syntax() {
+        for text in ["5m", "1h30m", "1.5h", "300ms", "10us", "2µs", "-1m", "+90s"] {
+            assert!(is_duration(text), "{:?} was rejected", text);
+        }
+        for text in ["", "-", "5", "-1", "m", "1h30", "5 m", "10x", "forever"] {
+            assert!(!is_duration(text), "{:?} was accepted", text);
+        }
+    }
+
+    #[test]
+    fn keep_alive_accepts_durations_and_seconds() {
+        for keep_alive in ["", "5m", " 1h30m ", "-1", "0", "300", "2.5"] {
+            assert!(loading(keep_alive).validate().is_ok(), "{:?} was rejected", keep_alive);
+        }
+        for keep_alive in ["soon", "5 minutes", "inf", "NaN"] {
+            assert!(loading(keep_alive).validate().is_err(), "{:?} was accepted", keep_alive);
+        }
+    }
+
+    #[test]
+    fn keep_alive_sends_numbers_as_seconds() {
+        assert_eq!(loading("").keep_alive(), None);
+        assert_eq!(loading("5m").keep_alive(), Some(serde_json::json!("5m")));
+        assert_eq!(loading(" 1h30m ").keep_alive(), Some(serde_json::json!("1h30m")));
+        assert_eq!(loading("-1").keep_alive(), Some(serde_json::json!(-1.0)));
+        assert_eq!(loading("0").keep_alive(), Some(serde_json::json!(0.0)));
+    }
+}
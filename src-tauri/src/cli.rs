@@ -0,0 +1,358 @@
+use std::io::{IsTerminal, Read, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::enhancer::{self, Enhancer};
+use crate::history::{self, HistoryStore};
+use crate::settings::{self, Settings};
+
+const USAGE: &str = "Usage: clip-prompt <command> [options]
+
+Commands:
+  enhance [FILE]    Enhance the text in FILE (or stdin) and print the result
+  presets           List the saved presets
+  models            List the models the configured server offers
+  help              Show this help
+
+Options for enhance:
+  -p, --preset <NAME>    Use a preset, by name or id
+  -m, --model <MODEL>    Use this model instead of the selected one
+      --stream           Print the text as it is generated
+      --no-history       Don't record the enhancement in the history
+
+Global options (before or after the command):
+      --settings <PATH>  Read settings from PATH instead of the app's settings file
+
+Use -- to end the options, e.g. to enhance a file whose name starts with -.
+
+Without a command, the tray app starts as usual.";
+
+const EXIT_OK: i32 = 0;
+const EXIT_ERROR: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+
+#[derive(Debug, PartialEq, Eq)]
+enum CliCommand {
+    Enhance,
+    Presets,
+    Models,
+    Help,
+}
+
+impl CliCommand {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "enhance" => Some(Self::Enhance),
+            "presets" => Some(Self::Presets),
+            "models" => Some(Self::Models),
+            "help" | "--help" | "-h" => Some(Self::Help),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct EnhanceArgs {
+    /// `None` or `-` reads stdin
+    input: Option<String>,
+    preset: Option<String>,
+    model: Option<String>,
+    stream: bool,
+    no_history: bool,
+}
+
+/// Whether the command line (without the program name) asks for one of the
+/// headless commands rather than the tray app.
+pub fn is_command(args: &[String]) -> bool {
+    find_command(args).is_some()
+}
+
+/// The command and where it is in the command line, after any global
+/// options given before it.
+fn find_command(args: &[String]) -> Option<(usize, CliCommand)> {
+    let mut index = 0;
+    while args.get(index).is_some_and(|arg| arg == "--settings") {
+        index += 2;
+    }
+    args.get(index)
+        .and_then(|arg| CliCommand::parse(arg))
+        .map(|command| (index, command))
+}
+
+/// Release builds on Windows are GUI programs without a console, so output
+/// would go nowhere. Write to the console of the shell that started us
+/// instead; without one (e.g. started from Explorer) this does nothing.
+#[cfg(windows)]
+fn attach_parent_console() {
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn AttachConsole(process_id: u32) -> i32;
+    }
+    // SAFETY: AttachConsole takes a plain process id and only fails if there
+    // is no parent console or one is already attached
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+/// Run a headless command, returning the process exit code.
+pub fn run(args: Vec<String>) -> i32 {
+    #[cfg(windows)]
+    attach_parent_console();
+
+    let Some((index, command)) = find_command(&args) else {
+        eprintln!("{}", USAGE);
+        return EXIT_USAGE;
+    };
+    let mut global_options = args;
+    let options = global_options.split_off(index + 1);
+    global_options.truncate(index);
+
+    let (settings_path, enhance_args) = match parse_options(&command, global_options.into_iter().chain(options)) {
+        Ok(Some(options)) => options,
+        Ok(None) => return run_help(),
+        Err(e) => {
+            eprintln!("clip-prompt: {}\n\n{}", e, USAGE);
+            return EXIT_USAGE;
+        }
+    };
+
+    if command == CliCommand::Help {
+        return run_help();
+    }
+
+    let Some(settings_path) = settings_path.or_else(settings::settings_path) else {
+        eprintln!("clip-prompt: No config directory available, pass --settings <PATH>");
+        return EXIT_ERROR;
+    };
+    let settings = match Settings::read(&settings_path) {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("clip-prompt: {}", e);
+            return EXIT_ERROR;
+        }
+    };
+    let enhancer = Enhancer::new(settings, enhancer::ollama_host_from_env(), Arc::default(), Arc::default());
+
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("clip-prompt: Failed to start async runtime: {}", e);
+            return EXIT_ERROR;
+        }
+    };
+
+    let result = runtime.block_on(async {
+        match command {
+            CliCommand::Enhance => run_enhance(&enhancer, enhance_args).await,
+            CliCommand::Presets => run_presets(&enhancer),
+            CliCommand::Models => run_models(&enhancer).await,
+            CliCommand::Help => unreachable!(),
+        }
+    });
+
+    match result {
+        Ok(()) => EXIT_OK,
+        Err(e) => {
+            eprintln!("clip-prompt: {}", e);
+            EXIT_ERROR
+        }
+    }
+}
+
+/// Parse the options given with the command. `None` means help was asked
+/// for.
+fn parse_options(command: &CliCommand, mut args: impl Iterator<Item = String>) -> Result<Option<(Option<PathBuf>, EnhanceArgs)>, String> {
+    let mut settings_path = None;
+    let mut enhance_args = EnhanceArgs::default();
+    let enhance = *command == CliCommand::Enhance;
+    let mut end_of_options = false;
+
+    while let Some(arg) = args.next() {
+        if end_of_options || arg == "-" || !arg.starts_with('-') {
+            if !enhance || enhance_args.input.is_some() {
+                return Err(format!("Unexpected argument '{}'", arg));
+            }
+            enhance_args.input = Some(arg);
+            continue;
+        }
+
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--" => end_of_options = true,
+            "--settings" => settings_path = Some(PathBuf::from(value()?)),
+            "-p" | "--preset" if enhance => enhance_args.preset = Some(value()?),
+            "-m" | "--model" if enhance => enhance_args.model = Some(value()?),
+            "--stream" if enhance => enhance_args.stream = true,
+            "--no-history" if enhance => enhance_args.no_history = true,
+            "-h" | "--help" => return Ok(None),
+            _ => return Err(format!("Unexpected argument '{}'", arg)),
+        }
+    }
+    Ok(Some((settings_path, enhance_args)))
+}
+
+fn run_help() -> i32 {
+    println!("{}", USAGE);
+    EXIT_OK
+}
+
+fn read_input(path: Option<&str>) -> Result<String, String> {
+    match path {
+        Some(path) if path != "-" => std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path, e)),
+        _ => {
+            let mut stdin = std::io::stdin();
+            if stdin.is_terminal() {
+                eprintln!("Reading text to enhance from stdin, end it with Ctrl+D (Ctrl+Z on Windows)");
+            }
+            let mut input = String::new();
+            stdin.read_to_string(&mut input)
+                .map_err(|e| format!("Failed to read stdin: {}", e))?;
+            Ok(input)
+        }
+    }
+}
+
+async fn run_enhance(enhancer: &Enhancer, args: EnhanceArgs) -> Result<(), String> {
+    let input = read_input(args.input.as_deref())?;
+    let input = input.trim();
+    if input.is_empty() {
+        return Err("Nothing to enhance, the input is empty".to_string());
+    }
+
+    let preset = args.preset.map(|name| enhancer.find_preset(&name)).transpose()?;
+
+    let enhancement = if args.stream {
+        let on_delta = |delta: &str| {
+            let mut stdout = std::io::stdout().lock();
+            let _ = stdout.write_all(delta.as_bytes());
+            let _ = stdout.flush();
+        };
+        let enhancement = enhancer.enhance_stream(input, preset.as_ref(), args.model, &on_delta).await?;
+        println!();
+        enhancement
+    } else {
+        let enhancement = enhancer.enhance(input, preset.as_ref(), args.model).await?;
+        println!("{}", enhancement.generation.text);
+        enhancement
+    };
+    if enhancement.fallback {
+        eprintln!("clip-prompt: Served by {} on {} (fallback)", enhancement.model, enhancement.target);
+    }
+
+    let history_settings = &enhancer.settings().history;
+    if history_settings.enabled && !args.no_history {
+        let recorded = history::history_path()
+            .ok_or_else(|| "No data directory available".to_string())
+            .and_then(|path| HistoryStore::open(&path))
+            .and_then(|store| {
+                store.add(&enhancement.history_entry("cli", input, preset.as_ref()))?;
+                store.prune(history_settings)
+            });
+        // The text is already printed, so don't fail the command over this
+        if let Err(e) = recorded {
+            eprintln!("clip-prompt: Failed to record enhancement in history: {}", e);
+        }
+    }
+    Ok(())
+}
+
+fn run_presets(enhancer: &Enhancer) -> Result<(), String> {
+    let presets = &enhancer.settings().presets;
+    if presets.is_empty() {
+        eprintln!("No presets saved yet, add them in the Clip Prompt window");
+    }
+    for preset in presets {
+        let model = if preset.model.is_empty() { "current model" } else { &preset.model };
+        println!("{}\t{}\t({})", preset.id, preset.name, model);
+    }
+    Ok(())
+}
+
+async fn run_models(enhancer: &Enhancer) -> Result<(), String> {
+    let selected = &enhancer.settings().model;
+    for model in enhancer.backend().list_model_info().await? {
+        let marker = if &model.name == selected { "*" } else { " " };
+        let details: Vec<String> = [
+            model.parameter_size,
+            model.quantization_level,
+            model.size.map(|bytes| format!("{:.1} GB", bytes as f64 / 1e9)),
+        ].into_iter().flatten().collect();
+        if details.is_empty() {
+            println!("{} {}", marker, model.name);
+        } else {
+            println!("{} {}\t{}", marker, model.name, details.join(", "));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn parse(command: CliCommand, options: &[&str]) -> Result<Option<(Option<PathBuf>, EnhanceArgs)>, String> {
+        parse_options(&command, args(options).into_iter())
+    }
+
+    #[test]
+    fn command_may_follow_global_options() {
+        assert!(is_command(&args(&["enhance"])));
+        assert!(is_command(&args(&["--settings", "work.json", "enhance", "notes.txt"])));
+        assert_eq!(find_command(&args(&["--settings", "work.json", "models"])), Some((2, CliCommand::Models)));
+        assert!(!is_command(&args(&[])));
+        assert!(!is_command(&args(&["--settings", "work.json"])));
+        assert!(!is_command(&args(&["--settings", "enhance"])));
+        assert!(!is_command(&args(&["--minimized"])));
+    }
+
+    #[test]
+    fn enhance_options_are_parsed() {
+        let (settings_path, enhance_args) = parse(CliCommand::Enhance, &[
+            "--settings", "work.json", "-p", "Grammar", "--model", "llama3", "--stream", "--no-history", "notes.txt",
+        ]).unwrap().unwrap();
+
+        assert_eq!(settings_path, Some(PathBuf::from("work.json")));
+        assert_eq!(enhance_args.preset.as_deref(), Some("Grammar"));
+        assert_eq!(enhance_args.model.as_deref(), Some("llama3"));
+        assert!(enhance_args.stream && enhance_args.no_history);
+        assert_eq!(enhance_args.input.as_deref(), Some("notes.txt"));
+    }
+
+    #[test]
+    fn option_without_value_is_an_error() {
+        assert_eq!(parse(CliCommand::Enhance, &["--preset"]).unwrap_err(), "--preset needs a value");
+        assert_eq!(parse(CliCommand::Models, &["--settings"]).unwrap_err(), "--settings needs a value");
+    }
+
+    #[test]
+    fn unknown_and_misplaced_arguments_are_errors() {
+        assert_eq!(parse(CliCommand::Enhance, &["--verbose"]).unwrap_err(), "Unexpected argument '--verbose'");
+        assert_eq!(parse(CliCommand::Presets, &["--stream"]).unwrap_err(), "Unexpected argument '--stream'");
+        assert_eq!(parse(CliCommand::Models, &["extra"]).unwrap_err(), "Unexpected argument 'extra'");
+        assert_eq!(parse(CliCommand::Enhance, &["a.txt", "b.txt"]).unwrap_err(), "Unexpected argument 'b.txt'");
+    }
+
+    #[test]
+    fn double_dash_ends_the_options() {
+        let (_, enhance_args) = parse(CliCommand::Enhance, &["--stream", "--", "--draft.txt"]).unwrap().unwrap();
+        assert!(enhance_args.stream);
+        assert_eq!(enhance_args.input.as_deref(), Some("--draft.txt"));
+
+        let (_, enhance_args) = parse(CliCommand::Enhance, &["-"]).unwrap().unwrap();
+        assert_eq!(enhance_args.input.as_deref(), Some("-"));
+
+        assert_eq!(parse(CliCommand::Enhance, &["--", "a.txt", "--stream"]).unwrap_err(), "Unexpected argument '--stream'");
+    }
+
+    #[test]
+    fn help_stops_parsing() {
+        assert!(parse(CliCommand::Enhance, &["--help", "--verbose"]).unwrap().is_none());
+    }
+}
@@ -0,0 +1,186 @@
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+use std::process::Stdio;
+use tauri_plugin_clipboard_manager::ClipboardExt;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+/// Where the clipboard enhancement reads its text from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClipboardSource {
+    /// The regular clipboard (Ctrl+C)
+    #[default]
+    Clipboard,
+    /// The highlighted text (X11/Wayland PRIMARY selection), Linux only
+    Primary,
+}
+
+/// How the clipboard is accessed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ClipboardBackend {
+    /// wl-clipboard on Wayland, xclip for PRIMARY on X11, else the plugin
+    #[default]
+    Auto,
+    /// Tauri's clipboard plugin
+    Plugin,
+    /// `wl-paste`/`wl-copy`
+    WlClipboard,
+    /// `xclip`, used for reading; writes go through the plugin
+    Xclip,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClipboardSettings {
+    pub source: ClipboardSource,
+    pub backend: ClipboardBackend,
+}
+
+/// Whether `program` can be found on `PATH`. On Windows the suffixes in
+/// `PATHEXT` are tried too, so `powershell` finds `powershell.exe`.
+pub fn command_exists(program: &str) -> bool {
+    let extensions: Vec<String> = if cfg!(windows) {
+        std::env::var("PATHEXT").unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_string())
+            .split(';')
+            .filter(|extension| !extension.is_empty())
+            .map(|extension| format!("{}{}", program, extension.to_lowercase()))
+            .collect()
+    } else {
+        Vec::new()
+    };
+    std::env::var_os("PATH").is_some_and(|paths| {
+        std::env::split_paths(&paths).any(|dir| {
+            dir.join(program).is_file() || extensions.iter().any(|name| dir.join(name).is_file())
+        })
+    })
+}
+
+/// Keep a helper process from opening a console window. Release builds on
+/// Windows have no console of their own, so each helper would otherwise
+/// flash one, which can take the focus away from the app being typed into.
+pub fn hide_console(command: &mut Command) -> &mut Command {
+    #[cfg(windows)]
+    {
+        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+        command.creation_flags(CREATE_NO_WINDOW);
+    }
+    command
+}
+
+/// Whether this is a Wayland session.
+pub fn is_wayland() -> bool {
+    std::env::var_os("WAYLAND_DISPLAY").is_some_and(|display| !display.is_empty())
+}
+
+/// The backend `Auto` stands for on this machine, or the configured one.
+pub fn resolve_backend(settings: &ClipboardSettings) -> ClipboardBackend {
+    if settings.backend != ClipboardBackend::Auto {
+        return settings.backend;
+    }
+    if !cfg!(target_os = "linux") {
+        return ClipboardBackend::Plugin;
+    }
+    if is_wayland() && command_exists("wl-paste") && command_exists("wl-copy") {
+        ClipboardBackend::WlClipboard
+    } else if settings.source == ClipboardSource::Primary && command_exists("xclip") {
+        ClipboardBackend::Xclip
+    } else {
+        ClipboardBackend::Plugin
+    }
+}
+
+/// Read the text the settings say to enhance: the clipboard or the selection.
+pub async fn read_source(app_handle: &tauri::AppHandle, settings: &ClipboardSettings) -> Result<String, String> {
+    read(app_handle, settings, settings.source).await
+}
+
+/// Read the regular clipboard, whatever the source setting.
+pub async fn read_clipboard(app_handle: &tauri::AppHandle, settings: &ClipboardSettings) -> Result<String, String> {
+    read(app_handle, settings, ClipboardSource::Clipboard).await
+}
+
+async fn read(app_handle: &tauri::AppHandle, settings: &ClipboardSettings, source: ClipboardSource) -> Result<String, String> {
+    let primary = source == ClipboardSource::Primary;
+    let result = match resolve_backend(settings) {
+        ClipboardBackend::WlClipboard => {
+            let mut args = vec!["--no-newline", "--type", "text"];
+            if primary {
+                args.push("--primary");
+            }
+            run_paste("wl-paste", &args).await
+        },
+        ClipboardBackend::Xclip => {
+            let selection = if primary { "primary" } else { "clipboard" };
+            run_paste("xclip", &["-o", "-selection", selection]).await
+        },
+        ClipboardBackend::Plugin | ClipboardBackend::Auto => {
+            if primary {
+                return Err("Reading the selection needs wl-clipboard (Wayland) or xclip (X11)".to_string());
+            }
+            return read_plugin(app_handle);
+        },
+    };
+
+    // An external tool that is missing or broken shouldn't stop the hotkey
+    match result {
+        Err(e) if !primary => {
+            error!("{}, falling back to the clipboard plugin", e);
+            read_plugin(app_handle)
+        },
+        result => result,
+    }
+}
+
+fn read_plugin(app_handle: &tauri::AppHandle) -> Result<String, String> {
+    app_handle.clipboard().read_text()
+        .map_err(|e| format!("Failed to read clipboard: {}", e))
+}
+
+/// Run a paste command and return what it printed. A command that fails
+/// because there is nothing to paste gives an empty string.
+async fn run_paste(program: &str, args: &[&str]) -> Result<String, String> {
+    let output = hide_console(&mut Command::new(program)).args(args).output().await
+        .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+    if !output.status.success() {
+        debug!("{} found nothing to paste: {}", program, String::from_utf8_lossy(&output.stderr).trim());
+        return Ok(String::new());
+    }
+    String::from_utf8(output.stdout)
+        .map_err(|_| "The selection isn't valid UTF-8 text".to_string())
+}
+
+/// Put `text` on the regular clipboard, so it can be pasted with Ctrl+V.
+pub async fn write_clipboard(app_handle: &tauri::AppHandle, settings: &ClipboardSettings, text: &str) -> Result<(), String> {
+    if resolve_backend(settings) == ClipboardBackend::WlClipboard {
+        match run_copy("wl-copy", &["--type", "text/plain;charset=utf-8"], text).await {
+            Ok(()) => return Ok(()),
+            Err(e) => error!("{}, falling back to the clipboard plugin", e),
+        }
+    }
+    app_handle.clipboard().write_text(text.to_string())
+        .map_err(|e| format!("Failed to write to clipboard: {}", e))
+}
+
+/// Pipe `text` into a copy command. `wl-copy` forks to keep serving the
+/// clipboard, so this only waits for the foreground process.
+async fn run_copy(program: &str, args: &[&str], text: &str) -> Result<(), String> {
+    let mut child = hide_console(&mut Command::new(program))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes()).await
+            .map_err(|e| format!("Failed to write to {}: {}", program, e))?;
+    }
+    let status = child.wait().await.map_err(|e| format!("Failed to run {}: {}", program, e))?;
+    if !status.success() {
+        return Err(format!("{} exited with {}", program, status));
+    }
+    Ok(())
+}
@@ -0,0 +1,313 @@
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use crate::backend::GenerationStats;
+use crate::enhancer::{Enhancement, Enhancer, Variation};
+use crate::presets::Preset;
+use crate::settings::APP_IDENTIFIER;
+
+/// Most models run at the same time. Generation is CPU/GPU-bound, so more
+/// would mostly make every model look slow.
+pub const MAX_CONCURRENCY: usize = 4;
+/// Most models one comparison may include.
+pub const MAX_MODELS: usize = 16;
+const REPORTS_DIR: &str = "reports";
+
+/// Emitted with a [`ComparisonProgress`] as each model finishes.
+pub const COMPARE_PROGRESS_EVENT: &str = "compare-progress";
+
+/// How one model did on the comparison input.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComparisonResult {
+    pub model: String,
+    /// The enhanced text; `None` if the model failed
+    pub text: Option<String>,
+    pub error: Option<String>,
+    /// Wall-clock time from request to finished text
+    pub duration_ms: u64,
+    pub stats: GenerationStats,
+    /// Generated tokens per second, if the server reported the timing
+    pub tokens_per_second: Option<f64>,
+}
+
+impl ComparisonResult {
+    fn new(model: String, result: Result<Enhancement, String>) -> Self {
+        match result {
+            Ok(enhancement) => Self {
+                model,
+                tokens_per_second: tokens_per_second(&enhancement.generation.stats),
+                text: Some(enhancement.generation.text),
+                error: None,
+                duration_ms: enhancement.duration_ms,
+                stats: enhancement.generation.stats,
+            },
+            Err(e) => Self {
+                model,
+                text: None,
+                error: Some(e),
+                duration_ms: 0,
+                stats: GenerationStats::default(),
+                tokens_per_second: None,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct ComparisonProgress {
+    pub job_id: u64,
+    /// Index of the model in the requested list
+    pub index: usize,
+    pub result: ComparisonResult,
+}
+
+/// The same input run through several models.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Comparison {
+    pub input: String,
+    /// Name of the preset used, if any
+    pub preset: Option<String>,
+    /// Unix time in milliseconds
+    pub created_at: i64,
+    pub results: Vec<ComparisonResult>,
+}
+
+fn tokens_per_second(stats: &GenerationStats) -> Option<f64> {
+    let tokens = stats.eval_count?;
+    let duration_ms = stats.eval_duration_ms.filter(|ms| *ms > 0)?;
+    Some(tokens as f64 * 1000.0 / duration_ms as f64)
+}
+
+/// Check the models and concurrency a comparison was asked for.
+pub fn validate(models: &[String], concurrency: usize) -> Result<(), String> {
+    if models.is_empty() || models.len() > MAX_MODELS {
+        return Err(format!("Pick between 1 and {} models to compare", MAX_MODELS));
+    }
+    if models.iter().any(|model| model.trim().is_empty()) {
+        return Err("Model names must not be empty".to_string());
+    }
+    if concurrency == 0 || concurrency > MAX_CONCURRENCY {
+        return Err(format!("Run between 1 and {} models at a time", MAX_CONCURRENCY));
+    }
+    Ok(())
+}
+
+/// Enhance `input` with each of `models`, at most `concurrency` at a time
+/// (1 runs them one after another, which gives the fairest timings).
+/// `on_result` is called as each model finishes, with its index in `models`.
+pub async fn compare(
+    enhancer: &Enhancer,
+    input: &str,
+    preset: Option<&Preset>,
+    models: Vec<String>,
+    concurrency: usize,
+    on_result: impl Fn(usize, &ComparisonResult),
+) -> Comparison {
+    info!("Comparing {} models, {} at a time", models.len(), concurrency);
+    let created_at = now_ms();
+    let permits = Arc::new(Semaphore::new(concurrency));
+
+    // Dropping the set (e.g. when cancelled) aborts the models still running
+    let mut tasks = JoinSet::new();
+    for (index, model) in models.iter().cloned().enumerate() {
+        let enhancer = enhancer.clone();
+        let input = input.to_string();
+        let preset = preset.cloned();
+        let permits = permits.clone();
+        tasks.spawn(async move {
+            let _permit = permits.acquire_owned().await;
+            debug!("Comparison: running {}", model);
+            let result = enhancer.enhance_variation(&input, preset.as_ref(), &Variation::model(Some(model.clone())), None).await;
+            (index, ComparisonResult::new(model, result))
+        });
+    }
+
+    let mut results: Vec<Option<ComparisonResult>> = vec![None; models.len()];
+    while let Some(joined) = tasks.join_next().await {
+        let Ok((index, result)) = joined else {
+            continue;
+        };
+        on_result(index, &result);
+        results[index] = Some(result);
+    }
+
+    let results = results.into_iter().zip(models)
+        .map(|(result, model)| result.unwrap_or_else(|| ComparisonResult::new(model, Err("The comparison task failed".to_string()))))
+        .collect();
+    Comparison {
+        input: input.to_string(),
+        preset: preset.map(|p| p.name.clone()),
+        created_at,
+        results,
+    }
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+/// `2026-01-31 14:05:09 UTC` for a Unix time in milliseconds.
+fn format_utc(ms: i64) -> String {
+    let secs = ms.div_euclid(1000);
+    let (days, rest) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+
+    // Days since 1970-01-01 to a civil date (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC", year, month, day, rest / 3600, rest % 3600 / 60, rest % 60)
+}
+
+fn optional<T: std::fmt::Display>(value: Option<T>) -> String {
+    value.map_or_else(|| "–".to_string(), |value| value.to_string())
+}
+
+/// The comparison as a Markdown report: a summary table, then each output.
+pub fn to_markdown(comparison: &Comparison) -> String {
+    let mut report = String::new();
+    let _ = writeln!(report, "# Model Comparison\n");
+    let _ = writeln!(report, "- Date: {}", format_utc(comparison.created_at));
+    if let Some(preset) = &comparison.preset {
+        let _ = writeln!(report, "- Preset: {}", preset);
+    }
+    let _ = writeln!(report, "\n## Input\n\n```\n{}\n```\n", comparison.input.trim_end());
+
+    let _ = writeln!(report, "## Results\n");
+    let _ = writeln!(report, "| Model | Time (s) | Load (s) | Prompt tokens | Output tokens | Tokens/s | Result |");
+    let _ = writeln!(report, "|-------|---------:|---------:|--------------:|--------------:|---------:|--------|");
+    for result in &comparison.results {
+        let stats = &result.stats;
+        let _ = writeln!(
+            report,
+            "| {} | {} | {} | {} | {} | {} | {} |",
+            result.model,
+            if result.text.is_some() { format!("{:.1}", result.duration_ms as f64 / 1000.0) } else { "–".to_string() },
+            optional(stats.load_duration_ms.map(|ms| format!("{:.1}", ms as f64 / 1000.0))),
+            optional(stats.prompt_eval_count),
+            optional(stats.eval_count),
+            optional(result.tokens_per_second.map(|rate| format!("{:.1}", rate))),
+            if result.text.is_some() { "ok" } else { "failed" },
+        );
+    }
+
+    for result in &comparison.results {
+        let _ = writeln!(report, "\n## {}\n", result.model);
+        match (&result.text, &result.error) {
+            (Some(text), _) => {
+                let _ = writeln!(report, "```\n{}\n```", text.trim_end());
+            },
+            (None, error) => {
+                let _ = writeln!(report, "Failed: {}", error.as_deref().unwrap_or("unknown error"));
+            },
+        }
+    }
+    report
+}
+
+/// Where saved reports go, next to the history database.
+pub fn reports_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join(APP_IDENTIFIER).join(REPORTS_DIR))
+}
+
+/// Save the comparison as a Markdown report, returning its path.
+pub fn save_report(comparison: &Comparison) -> Result<PathBuf, String> {
+    let dir = reports_dir().ok_or_else(|| "No data directory available".to_string())?;
+    fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create reports directory: {}", e))?;
+
+    let name = format_utc(comparison.created_at).replace(" UTC", "").replace([' ', ':'], "-");
+    let path = dir.join(format!("comparison-{}.md", name));
+    fs::write(&path, to_markdown(comparison))
+        .map_err(|e| format!("Failed to save report: {}", e))?;
+
+    info!("Saved comparison report to {}", path.display());
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_utc_converts_civil_dates() {
+        assert_eq!(format_utc(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_utc(1_769_868_309_999), "2026-01-31 14:05:09 UTC");
+        assert_eq!(format_utc(951_782_400_000), "2000-02-29 00:00:00 UTC");
+        assert_eq!(format_utc(1_709_164_799_000), "2024-02-28 23:59:59 UTC");
+        assert_eq!(format_utc(4_102_444_799_000), "2099-12-31 23:59:59 UTC");
+    }
+
+    #[test]
+    fn format_utc_handles_times_before_1970() {
+        assert_eq!(format_utc(-1), "1969-12-31 23:59:59 UTC");
+        assert_eq!(format_utc(-31_536_000_000), "1969-01-01 00:00:00 UTC");
+    }
+
+    #[test]
+    fn tokens_per_second_needs_the_timing() {
+        let stats = GenerationStats { eval_count: Some(40), eval_duration_ms: Some(2000), ..GenerationStats::default() };
+        assert_eq!(tokens_per_second(&stats), Some(20.0));
+        assert_eq!(tokens_per_second(&GenerationStats { eval_duration_ms: Some(0), ..stats.clone() }), None);
+        assert_eq!(tokens_per_second(&GenerationStats::default()), None);
+    }
+
+    #[test]
+    fn to_markdown_tabulates_successes_and_failures() {
+        let stats = GenerationStats {
+            load_duration_ms: Some(500),
+            prompt_eval_count: Some(12),
+            eval_count: Some(40),
+            eval_duration_ms: Some(2000),
+            ..GenerationStats::default()
+        };
+        let comparison = Comparison {
+            input: "carbonara recipe\n".to_string(),
+            preset: Some("Concise".to_string()),
+            created_at: 1_769_868_309_000,
+            results: vec![
+                ComparisonResult {
+                    model: "llama3.2:3b".to_string(),
+                    text: Some("Provide a simple carbonara recipe.\n".to_string()),
+                    error: None,
+                    duration_ms: 2500,
+                    tokens_per_second: tokens_per_second(&stats),
+                    stats,
+                },
+                ComparisonResult {
+                    model: "mistral".to_string(),
+                    text: None,
+                    error: Some("model not found".to_string()),
+                    duration_ms: 0,
+                    stats: GenerationStats::default(),
+                    tokens_per_second: None,
+                },
+            ],
+        };
+
+        let report = to_markdown(&comparison);
+
+        assert!(report.starts_with("# Model Comparison\n\n- Date: 2026-01-31 14:05:09 UTC\n- Preset: Concise\n"));
+        assert!(report.contains("## Input\n\n```\ncarbonara recipe\n```\n"));
+        assert!(report.contains("| llama3.2:3b | 2.5 | 0.5 | 12 | 40 | 20.0 | ok |\n"));
+        assert!(report.contains("| mistral | – | – | – | – | – | failed |\n"));
+        assert!(report.contains("## llama3.2:3b\n\n```\nProvide a simple carbonara recipe.\n```\n"));
+        assert!(report.ends_with("## mistral\n\nFailed: model not found\n"));
+    }
+}
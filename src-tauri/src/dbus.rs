@@ -0,0 +1,118 @@
+use log::{error, info};
+use tauri::Manager;
+use zbus::fdo;
+use zbus::object_server::SignalEmitter;
+
+use crate::jobs::JobKind;
+use crate::presets::Preset;
+use crate::AppState;
+
+pub const BUS_NAME: &str = "org.clipprompt.Enhancer";
+pub const OBJECT_PATH: &str = "/org/clipprompt/Enhancer";
+
+/// The session bus service, so compositor keybindings (which work under
+/// Wayland, unlike our global shortcuts) can drive the app.
+struct EnhancerService {
+    app_handle: tauri::AppHandle,
+}
+
+impl EnhancerService {
+    /// An empty name means the active preset, like the enhance hotkey.
+    fn preset(&self, name: &str) -> fdo::Result<Option<Preset>> {
+        let enhancer = self.app_handle.state::<AppState>().enhancer();
+        if name.trim().is_empty() {
+            return Ok(enhancer.active_preset());
+        }
+        enhancer.find_preset(name).map(Some).map_err(fdo::Error::InvalidArgs)
+    }
+}
+
+#[zbus::interface(name = "org.clipprompt.Enhancer")]
+impl EnhancerService {
+    /// Enhance the clipboard exactly like the enhance hotkey, with the preset
+    /// with this name or id ("" for the active preset). Returns once the
+    /// enhancement has started; progress is shown in notifications.
+    async fn enhance_clipboard(&self, preset: &str) -> fdo::Result<()> {
+        let preset = self.preset(preset)?;
+        info!("Clipboard enhancement requested over D-Bus ({})", preset.as_ref().map_or("default prompt", |p| p.name.as_str()));
+        crate::enhance_clipboard_in_background(&self.app_handle, preset);
+        Ok(())
+    }
+
+    /// Enhance `text` with the active preset and return the result. The
+    /// reply waits for the whole text, which can take longer than the 25 s
+    /// clients wait by default, so callers should raise their timeout.
+    async fn enhance(&self, text: &str) -> fdo::Result<String> {
+        if text.trim().is_empty() {
+            return Err(fdo::Error::InvalidArgs("Nothing to enhance, the text is empty".to_string()));
+        }
+        let preset = self.preset("")?;
+        let state = self.app_handle.state::<AppState>();
+        let job = state.jobs.start(JobKind::Enhancement);
+        crate::stream_enhancement(&self.app_handle, &job, "dbus", text, preset.as_ref(), None, |_, _| {}).await
+            .map_err(fdo::Error::Failed)
+    }
+
+    /// Cancel every running enhancement, returning how many there were.
+    async fn cancel(&self) -> u32 {
+        let cancelled = self.app_handle.state::<AppState>().jobs.cancel_kind(JobKind::Enhancement);
+        info!("Cancelled {} enhancement(s) over D-Bus", cancelled);
+        cancelled as u32
+    }
+
+    /// Put back the clipboard from before the last clipboard enhancement.
+    async fn undo(&self) {
+        crate::undo_with_notification(&self.app_handle);
+    }
+
+    /// "enhancing" while an enhancement is running, else "idle". Model
+    /// downloads and comparisons don't count.
+    #[zbus(property)]
+    async fn status(&self) -> String {
+        status(&self.app_handle).to_string()
+    }
+}
+
+fn status(app_handle: &tauri::AppHandle) -> &'static str {
+    if app_handle.state::<AppState>().jobs.is_enhancing() {
+        "enhancing"
+    } else {
+        "idle"
+    }
+}
+
+async fn connect(service: EnhancerService) -> zbus::Result<zbus::Connection> {
+    zbus::connection::Builder::session()?
+        .name(BUS_NAME)?
+        .serve_at(OBJECT_PATH, service)?
+        .build()
+        .await
+}
+
+/// Claim [`BUS_NAME`] on the session bus and serve requests until the app
+/// exits. Without a session bus (or if another instance owns the name) this
+/// is logged and the app runs without D-Bus.
+pub async fn serve(app_handle: tauri::AppHandle) {
+    let service = EnhancerService { app_handle: app_handle.clone() };
+    let connection = match connect(service).await {
+        Ok(connection) => connection,
+        Err(e) => {
+            error!("Failed to start D-Bus service {}: {}", BUS_NAME, e);
+            return;
+        }
+    };
+    info!("D-Bus service {} ready at {}", BUS_NAME, OBJECT_PATH);
+
+    // Tell watchers (e.g. a status bar) when Status changes, whichever way
+    // the enhancements start and end. The connection serves requests for as
+    // long as this keeps running.
+    let mut enhancing = app_handle.state::<AppState>().jobs.watch_enhancing();
+    while enhancing.changed().await.is_ok() {
+        let Ok(service) = connection.object_server().interface::<_, EnhancerService>(OBJECT_PATH).await else {
+            continue;
+        };
+        if let Ok(emitter) = SignalEmitter::new(&connection, OBJECT_PATH) {
+            let _ = service.get().await.status_changed(&emitter).await;
+        }
+    }
+}
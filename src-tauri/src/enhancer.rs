@@ -0,0 +1,594 @@
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::backend::{create_backend, normalize_ollama_url, BackendError, EnhanceRequest, FewShotExample, Generation, GenerationOptions, LlmBackend, OnDelta, PromptMode, DEFAULT_OLLAMA_URL, OLLAMA_HOST_ENV};
+use crate::history::NewEntry;
+use crate::models::{self, ModelChoice, ModelError, ModelRule};
+use crate::presets::{self, Preset};
+use crate::settings::Settings;
+use crate::targets::{Target, TargetError, TargetHealth, TargetStatus};
+
+pub const DEFAULT_SYSTEM_PROMPT: &str = r#"<system_prompt>
+YOU ARE A LOCAL PROMPT ENHANCER RUNNING ENTIRELY ON THE USER'S MACHINE.
+
+YOUR EXCLUSIVE MISSION IS TO READ THE USER'S RAW INPUT PROMPT AND REWRITE IT INTO A MORE DETAILED, CLEAR, AND WELL‑STRUCTURED PROMPT THAT ANOTHER AI ASSISTANT COULD DIRECTLY USE TO PRODUCE THE BEST POSSIBLE OUTPUT.
+
+### CORE BEHAVIORS ###
+- DETECT THE LANGUAGE OF THE INPUT AND OUTPUT IN THE SAME LANGUAGE.
+- ANALYZE THE COMPLEXITY OF THE USER'S INPUT:
+  • IF THE INPUT IS VERY SIMPLE OR SHORT (E.G., "CARBONARA RECIPE"), ENHANCE ONLY SLIGHTLY — KEEP THE OUTPUT BRIEF, CLEAR, AND STILL SIMPLE.
+  • IF THE INPUT IS MODERATELY DETAILED, EXPAND IT WITH ADDITIONAL CONTEXT AND PARAMETERS.
+  • IF THE INPUT IS COMPLEX OR AMBIGUOUS, ADD RICH DETAILS, RELEVANT CONSTRAINTS, AND CLARIFY THE INTENT AS MUCH AS POSSIBLE.
+- ALWAYS PRESERVE THE ORIGINAL INTENT AND MEANING.
+- OUTPUT ONLY THE ENHANCED PROMPT — NOTHING ELSE.
+
+### INSTRUCTIONS ###
+- NEVER ANSWER THE PROMPT OR GIVE TIPS.
+- NEVER ADD EXPLANATIONS, NOTES, OR COMMENTS.
+- ALWAYS PRODUCE ONE SINGLE PROMPT, NO BULLET LISTS OR MULTIPLE VERSIONS.
+- IF THE ORIGINAL IS VAGUE, INFER AND ADD REASONABLE CONTEXT.
+- IF THE ORIGINAL IS ALREADY DETAILED, IMPROVE STRUCTURE AND ADD MORE ACTIONABLE PARAMETERS.
+- AVOID OVERCOMPLICATING WHEN THE INPUT IS OBVIOUSLY SIMPLE AND SELF‑CONTAINED.
+
+### CHAIN OF THOUGHTS ###
+FOLLOW THESE STEPS INTERNALLY BEFORE PRODUCING OUTPUT:
+1. **UNDERSTAND**: READ the raw input and DETECT language and intent.
+2. **BASICS**: IDENTIFY subject, domain, and goal.
+3. **BREAK DOWN**: SPLIT the intent into sub‑tasks or aspects.
+4. **ANALYZE**: DETERMINE what extra details would meaningfully improve the prompt.
+5. **ADJUST COMPLEXITY**: MATCH output detail level to input complexity.
+6. **EDGE CASES**: CHECK for ambiguous or overly broad inputs and clarify carefully.
+7. **FINAL ANSWER**: OUTPUT ONLY the rewritten prompt in the same language.
+
+### WHAT NOT TO DO ###
+- DO NOT ANSWER THE USER'S ORIGINAL PROMPT.
+- DO NOT OUTPUT IN A DIFFERENT LANGUAGE THAN THE INPUT.
+- DO NOT SAY "THE USER WANTS…" OR "HERE IS YOUR PROMPT…".
+- DO NOT OUTPUT MULTIPLE PROMPTS OR EXPLANATIONS.
+- DO NOT OVEREXPAND A SIMPLE PROMPT INTO AN UNRELATED OR EXCESSIVE TASK.
+- DO NOT OMIT KEY DETAILS FROM THE USER'S INTENT.
+- DO NOT ADD IRRELEVANT CONTEXT.
+
+### FEW‑SHOT EXAMPLES ###
+
+**Example 1 (simple)**
+Input: `carbonara recipe`  
+Output: `Provide a simple, authentic carbonara recipe with a short list of key ingredients and clear step-by-step instructions.`
+
+**Example 2 (moderate)**
+Input: `improve my resume`  
+Output: `Revise and enhance my resume by highlighting my key achievements, quantifying results wherever possible, improving clarity and impact, and ensuring it is tailored to the target industry.`
+
+**Example 3 (complex)**
+Input: `how to improve A`  
+Output: `Explain how to improve A by integrating B and optimizing C parameters, while also considering D and F to ensure scalability, accuracy, and long-term maintainability.`
+
+**Example 4 (non‑English)**
+Input: `Consejos para cultivar tomates`  
+Output: `Proporciona consejos detallados y prácticos para cultivar tomates, incluyendo condiciones de suelo, riego, fertilización, control de plagas y cuidados estacionales.`
+
+</system_prompt>"#;
+
+/// The Ollama address from `OLLAMA_HOST`, normalized. `None` if it isn't set
+/// or can't be parsed.
+pub fn ollama_host_from_env() -> Option<String> {
+    std::env::var(OLLAMA_HOST_ENV).ok()
+        .filter(|host| !host.trim().is_empty())
+        .and_then(|host| match normalize_ollama_url(&host) {
+            Ok(url) => {
+                info!("Using Ollama at {} from {}", url, OLLAMA_HOST_ENV);
+                Some(url)
+            },
+            Err(e) => {
+                error!("Ignoring {}: {}", OLLAMA_HOST_ENV, e);
+                None
+            }
+        })
+}
+
+/// Most candidates one request may ask for, so a typo can't flood the server.
+pub const MAX_CANDIDATES: usize = 5;
+/// Candidates generated when a request asks for some without saying how many.
+pub const DEFAULT_CANDIDATES: usize = 3;
+
+/// The context window Ollama gives a model unless told otherwise. It keeps
+/// only the end of a prompt that doesn't fit, so longer texts get a bigger
+/// window.
+const DEFAULT_CONTEXT_TOKENS: u32 = 2048;
+
+/// Rough token count of `text`. Tokenizers average about four characters per
+/// token in English; three errs towards a window that is big enough.
+fn estimate_tokens(text: &str) -> u32 {
+    u32::try_from(text.chars().count().div_ceil(3)).unwrap_or(u32::MAX)
+}
+
+/// What sets one candidate enhancement apart from the others. Anything left
+/// out comes from the settings, as for a single enhancement.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Variation {
+    pub model: Option<String>,
+    pub seed: Option<i64>,
+    pub temperature: Option<f32>,
+}
+
+impl Variation {
+    /// Just a model (or the usual one), as for a single enhancement.
+    pub fn model(model: Option<String>) -> Self {
+        Self { model, ..Self::default() }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        GenerationOptions { temperature: self.temperature, ..GenerationOptions::default() }.validate()
+    }
+
+    /// One variation per model in `models`, or if there are none, `count`
+    /// variations of the usual model with consecutive seeds.
+    pub fn spread(count: usize, models: &[String]) -> Result<Vec<Self>, String> {
+        if !models.is_empty() {
+            return check_candidate_count(models.iter().map(|m| Self::model(Some(m.clone()))).collect());
+        }
+        if count == 1 {
+            return Ok(vec![Self::default()]);
+        }
+
+        // A different base each time, so asking again gives new candidates
+        let base = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos() as i64).unwrap_or_default();
+        check_candidate_count((0..count as i64).map(|i| Self { seed: Some(base + i), ..Self::default() }).collect())
+    }
+}
+
+/// Check there are between 1 and [`MAX_CANDIDATES`] variations, and that each
+/// one is valid.
+pub fn check_candidate_count(variations: Vec<Variation>) -> Result<Vec<Variation>, String> {
+    if variations.is_empty() || variations.len() > MAX_CANDIDATES {
+        return Err(format!("Ask for between 1 and {} candidates", MAX_CANDIDATES));
+    }
+    for variation in &variations {
+        variation.validate()?;
+    }
+    Ok(variations)
+}
+
+/// Everything about an enhancement that comes from the settings rather than
+/// from the text being enhanced.
+pub struct ResolvedEnhancement {
+    pub system_prompt: String,
+    pub model: String,
+    pub mode: PromptMode,
+    pub examples: Vec<FewShotExample>,
+    pub options: GenerationOptions,
+    pub keep_alive: Option<serde_json::Value>,
+}
+
+impl ResolvedEnhancement {
+    pub fn request<'a>(&'a self, input: &'a str) -> EnhanceRequest<'a> {
+        EnhanceRequest {
+            model: &self.model,
+            system_prompt: &self.system_prompt,
+            input,
+            mode: self.mode,
+            examples: &self.examples,
+            options: &self.options,
+            keep_alive: self.keep_alive.as_ref(),
+        }
+    }
+}
+
+/// A finished enhancement and what produced it.
+pub struct Enhancement {
+    pub generation: Generation,
+    pub model: String,
+    /// Which rule of the model resolution policy chose `model`
+    pub model_rule: ModelRule,
+    /// The seed and temperature asked for, if any
+    pub seed: Option<i64>,
+    pub temperature: Option<f32>,
+    pub backend: &'static str,
+    /// Label of the target that served the request
+    pub target: String,
+    /// Whether a fallback target served it because the ones before it failed
+    pub fallback: bool,
+    /// Wall-clock time from request to finished text
+    pub duration_ms: u64,
+}
+
+impl Enhancement {
+    /// The history entry recording this enhancement of `original`.
+    pub fn history_entry<'a>(&'a self, source: &'a str, original: &'a str, preset: Option<&'a Preset>) -> NewEntry<'a> {
+        NewEntry {
+            source,
+            original,
+            enhanced: &self.generation.text,
+            model: &self.model,
+            preset: preset.map(|p| p.name.as_str()),
+            backend: self.backend,
+            duration_ms: self.duration_ms,
+            stats: &self.generation.stats,
+        }
+    }
+
+    /// Which model and target wrote the text, for the notification.
+    pub fn served_by(&self) -> String {
+        let fallback = if self.fallback { " (fallback)" } else { "" };
+        format!("Served by {} on {}{}", self.model, self.target, fallback)
+    }
+}
+
+/// The context lengths models were trained for, by target and model, so
+/// long texts only ask the server the first time.
+#[derive(Debug, Default)]
+pub struct ContextLengths {
+    lengths: Mutex<HashMap<(String, String), Option<u32>>>,
+}
+
+impl ContextLengths {
+    /// The context length of `model` on `target`, asking `backend` unless it
+    /// is known. `None` if the server doesn't say or can't be asked.
+    async fn get(&self, target: &Target, backend: &dyn LlmBackend, model: &str) -> Option<u32> {
+        let key = (target.key.clone(), model.to_string());
+        if let Some(length) = self.lengths.lock().unwrap().get(&key) {
+            return *length;
+        }
+
+        match backend.show_model(model).await {
+            Ok(details) => {
+                self.lengths.lock().unwrap().insert(key, details.context_length);
+                details.context_length
+            },
+            Err(e) => {
+                error!("Couldn't look up the context length of {}: {}", model, e);
+                None
+            },
+        }
+    }
+}
+
+/// The enhancement pipeline (system prompt, model selection and backend
+/// call) for one snapshot of the settings. Shared by the tray app and the
+/// command line, so both enhance text the same way.
+#[derive(Clone)]
+pub struct Enhancer {
+    settings: Settings,
+    /// Ollama address from `OLLAMA_HOST`, used when the settings don't name one
+    ollama_host: Option<String>,
+    /// Recent failures of the primary server and the fallback targets
+    health: Arc<TargetHealth>,
+    context_lengths: Arc<ContextLengths>,
+}
+
+impl Enhancer {
+    pub fn new(settings: Settings, ollama_host: Option<String>, health: Arc<TargetHealth>, context_lengths: Arc<ContextLengths>) -> Self {
+        Self { settings, ollama_host, health, context_lengths }
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// The Ollama server to use: the URL from the settings, else
+    /// `OLLAMA_HOST`, else the default.
+    pub fn ollama_url(&self) -> String {
+        if !self.settings.backend.ollama_url.is_empty() {
+            return self.settings.backend.ollama_url.clone();
+        }
+        self.fallback_ollama_url()
+    }
+
+    /// The Ollama URL used when the settings don't name one.
+    pub fn fallback_ollama_url(&self) -> String {
+        self.ollama_host.clone().unwrap_or_else(|| DEFAULT_OLLAMA_URL.to_string())
+    }
+
+    /// Build the selected backend. Cheap enough to do per request.
+    pub fn backend(&self) -> Box<dyn LlmBackend> {
+        create_backend(&self.settings.backend, &self.ollama_url())
+    }
+
+    /// The selected backend with the usual model policy.
+    fn primary_target(&self) -> Target {
+        Target::new("", self.settings.backend.clone(), self.ollama_url(), None, false)
+    }
+
+    /// The primary server, then each fallback target, in settings order.
+    pub fn targets(&self) -> Vec<Target> {
+        let fallbacks = self.settings.fallback_targets.iter().map(|target| {
+            let ollama_url = match target.backend.ollama_url.as_str() {
+                "" => self.fallback_ollama_url(),
+                url => url.to_string(),
+            };
+            let model = Some(target.model.clone()).filter(|m| !m.is_empty());
+            Target::new(&target.name, target.backend.clone(), ollama_url, model, true)
+        });
+        std::iter::once(self.primary_target()).chain(fallbacks).collect()
+    }
+
+    /// How each target has been doing, in settings order.
+    pub fn target_status(&self) -> Vec<TargetStatus> {
+        self.targets().iter().map(|target| self.health.status(target)).collect()
+    }
+
+    /// Look up a preset by id, or failing that by name (ignoring case).
+    pub fn find_preset(&self, id_or_name: &str) -> Result<Preset, String> {
+        presets::find(&self.settings.presets, id_or_name)
+            .or_else(|| self.settings.presets.iter().find(|p| p.name.eq_ignore_ascii_case(id_or_name.trim())))
+            .cloned()
+            .ok_or_else(|| format!("Unknown preset: {}", id_or_name))
+    }
+
+    /// The preset chosen as the default for the enhance hotkey and IPC
+    /// requests, if it still exists.
+    pub fn active_preset(&self) -> Option<Preset> {
+        let id = self.settings.active_preset.as_deref()?;
+        presets::find(&self.settings.presets, id).cloned()
+    }
+
+    /// Work out the system prompt, model and prompt format for an enhancement
+    /// request. A preset's prompt, model and options win over the global ones.
+    /// The model isn't checked against the installed ones, and is empty if
+    /// none is configured.
+    pub fn resolve(&self, preset: Option<&Preset>, model: Option<String>) -> ResolvedEnhancement {
+        let settings = &self.settings;
+
+        // Use the preset's prompt, the custom system prompt or the default
+        let system_prompt = if let Some(preset) = preset {
+            preset.system_prompt.clone()
+        } else if settings.system_prompt.is_empty() {
+            DEFAULT_SYSTEM_PROMPT.to_string()
+        } else {
+            settings.system_prompt.clone()
+        };
+
+        // Without an installed model to check against this can't do better;
+        // `choose_model` applies the whole policy
+        let preset_model = preset.map(|p| p.model.clone()).filter(|m| !m.is_empty());
+        let model_to_use = model.filter(|m| !m.is_empty())
+            .or(preset_model)
+            .unwrap_or_else(|| settings.model.clone());
+
+        let options = match preset {
+            Some(preset) if !preset.options.is_empty() => preset.options.clone(),
+            _ => settings.generation_options
+                .get(&model_to_use)
+                .cloned()
+                .unwrap_or_default(),
+        };
+
+        ResolvedEnhancement {
+            system_prompt,
+            model: model_to_use,
+            mode: settings.prompt_mode,
+            examples: settings.few_shot_examples.clone(),
+            options,
+            keep_alive: settings.model_loading.keep_alive(),
+        }
+    }
+
+    /// Pick the model for an enhancement: the one `requested`, else the
+    /// preset's, else the preferred model if it is installed, else the first
+    /// installed model of the fallback list, else the best installed model.
+    /// If the server can't list its models the preferred model is used
+    /// unchecked, so the enhancement reports the real problem.
+    pub async fn choose_model(&self, preset: Option<&Preset>, requested: Option<&str>) -> Result<ModelChoice, ModelError> {
+        self.choose_model_on(self.backend().as_ref(), preset, requested).await
+    }
+
+    async fn choose_model_on(&self, backend: &dyn LlmBackend, preset: Option<&Preset>, requested: Option<&str>) -> Result<ModelChoice, ModelError> {
+        if let Some(model) = requested.filter(|m| !m.trim().is_empty()) {
+            return Ok(ModelChoice { model: model.to_string(), rule: ModelRule::Requested });
+        }
+        if let Some(preset) = preset.filter(|p| !p.model.is_empty()) {
+            return Ok(ModelChoice { model: preset.model.clone(), rule: ModelRule::Preset });
+        }
+
+        let preferred = &self.settings.model;
+        match backend.list_model_info().await {
+            Ok(installed) => models::choose(preferred, &self.settings.fallback_models, &installed, models::total_memory().await),
+            Err(e) if !preferred.is_empty() => {
+                debug!("Couldn't list models ({}), trying {} anyway", e, preferred);
+                Ok(ModelChoice { model: preferred.clone(), rule: ModelRule::Preferred })
+            },
+            Err(e) => Err(ModelError::Unavailable(e)),
+        }
+    }
+
+    /// Load the model the enhance hotkey would use, so its first enhancement
+    /// doesn't wait for the server to load it.
+    pub async fn warm_up(&self) -> Result<ModelChoice, String> {
+        let backend = self.backend();
+        let choice = self.choose_model_on(backend.as_ref(), self.active_preset().as_ref(), None).await?;
+        backend.load_model(&choice.model, self.settings.model_loading.keep_alive().as_ref()).await?;
+        Ok(choice)
+    }
+
+    /// [`Enhancer::resolve`] with the seed and temperature of `variation`
+    /// on top.
+    pub fn resolve_variation(&self, preset: Option<&Preset>, variation: &Variation) -> ResolvedEnhancement {
+        let mut resolved = self.resolve(preset, variation.model.clone());
+        if variation.seed.is_some() {
+            resolved.options.seed = variation.seed;
+        }
+        if variation.temperature.is_some() {
+            resolved.options.temperature = variation.temperature;
+        }
+        resolved
+    }
+
+    /// Make sure the prompt for `input` fits the model's context window.
+    ///
+    /// Short texts are left alone. For longer ones, unless the options fix
+    /// `num_ctx`, the window is grown (to a power of two, so the server doesn't
+    /// reload the model for every length) up to what the model was trained
+    /// for. A prompt that can't fit even then is an error rather than being
+    /// cut off.
+    async fn fit_context(&self, target: &Target, backend: &dyn LlmBackend, resolved: &mut ResolvedEnhancement, input: &str) -> Result<(), String> {
+        let examples: usize = resolved.examples.iter().map(|e| e.input.chars().count() + e.output.chars().count()).sum();
+        let prompt_tokens = estimate_tokens(&resolved.system_prompt) + estimate_tokens(input)
+            + u32::try_from(examples.div_ceil(3)).unwrap_or(u32::MAX);
+        // The enhanced text is usually about as long as the input
+        let output_tokens = resolved.options.num_predict
+            .and_then(|n| u32::try_from(n).ok())
+            .unwrap_or_else(|| estimate_tokens(input).max(256));
+        let needed = prompt_tokens.saturating_add(output_tokens);
+
+        if let Some(num_ctx) = resolved.options.num_ctx {
+            if needed > num_ctx {
+                info!("The prompt (about {} tokens) may not fit the configured context of {} tokens", needed, num_ctx);
+            }
+            return Ok(());
+        }
+        if needed <= DEFAULT_CONTEXT_TOKENS {
+            return Ok(());
+        }
+
+        let Some(context_length) = self.context_lengths.get(target, backend, &resolved.model).await else {
+            return Ok(());
+        };
+
+        // The estimate is generous, so only give up when the prompt wouldn't
+        // fit at four characters per token either
+        if u64::from(prompt_tokens) * 3 / 4 >= u64::from(context_length) {
+            return Err(format!(
+                "The text is too long for {}: it is about {} tokens, and the model reads at most {}",
+                resolved.model, prompt_tokens, context_length,
+            ));
+        }
+        let num_ctx = needed.checked_next_power_of_two().unwrap_or(u32::MAX).min(context_length);
+        debug!("Using a context of {} tokens for about {} tokens of prompt and output", num_ctx, needed);
+        resolved.options.num_ctx = Some(num_ctx);
+        Ok(())
+    }
+
+    /// Enhance `input`, waiting for the whole text.
+    pub async fn enhance(&self, input: &str, preset: Option<&Preset>, model: Option<String>) -> Result<Enhancement, String> {
+        self.enhance_variation(input, preset, &Variation::model(model), None).await
+    }
+
+    /// Enhance `input`, calling `on_delta` with each piece of text as soon as
+    /// the server produces it.
+    pub async fn enhance_stream(&self, input: &str, preset: Option<&Preset>, model: Option<String>, on_delta: &OnDelta<'_>) -> Result<Enhancement, String> {
+        self.enhance_variation(input, preset, &Variation::model(model), Some(on_delta)).await
+    }
+
+    /// Enhance `input` as one candidate among several, streaming if
+    /// `on_delta` is given.
+    ///
+    /// Unless the variation names a model, the primary server and then the
+    /// fallback targets are tried until one succeeds, those that failed
+    /// recently last. Once text has streamed out, a failure is final.
+    pub async fn enhance_variation(&self, input: &str, preset: Option<&Preset>, variation: &Variation, on_delta: Option<&OnDelta<'_>>) -> Result<Enhancement, String> {
+        // A named model is what the caller wants to see (e.g. when comparing
+        // models), so it isn't swapped for another target's
+        if variation.model.is_some() {
+            return self.enhance_on(&self.primary_target(), input, preset, variation, on_delta).await
+                .map_err(|e| e.to_string());
+        }
+
+        let targets = self.health.order(self.targets());
+        let streamed = AtomicBool::new(false);
+        let forward = |delta: &str| {
+            streamed.store(true, Ordering::Relaxed);
+            if let Some(on_delta) = on_delta {
+                on_delta(delta);
+            }
+        };
+
+        let mut failures = Vec::new();
+        for target in &targets {
+            let result = self.enhance_on(target, input, preset, variation, on_delta.map(|_| &forward as &OnDelta<'_>)).await;
+            let error = match result {
+                Ok(enhancement) => {
+                    self.health.succeeded(target);
+                    return Ok(enhancement);
+                },
+                Err(TargetError::Down(e)) => {
+                    self.health.failed(target, &e);
+                    e
+                },
+                Err(TargetError::Unsuitable(e)) => e,
+            };
+            if streamed.load(Ordering::Relaxed) {
+                return Err(error);
+            }
+            failures.push((target, error));
+        }
+
+        // With just the primary server, its error is the whole story
+        if let [(_, error)] = failures.as_slice() {
+            return Err(error.clone());
+        }
+        let failures: Vec<String> = failures.iter().map(|(target, e)| format!("{}: {}", target.label, e)).collect();
+        Err(format!("Every target failed. {}", failures.join("; ")))
+    }
+
+    /// Enhance `input` on one target.
+    async fn enhance_on(&self, target: &Target, input: &str, preset: Option<&Preset>, variation: &Variation, on_delta: Option<&OnDelta<'_>>) -> Result<Enhancement, TargetError> {
+        let backend = target.backend();
+        let choice = match &target.model {
+            Some(model) => ModelChoice { model: model.clone(), rule: ModelRule::Target },
+            None => self.choose_model_on(backend.as_ref(), preset, variation.model.as_deref()).await
+                .map_err(|e| match e {
+                    // The server answered, it just has nothing to run
+                    ModelError::NoModels => TargetError::Unsuitable(e.to_string()),
+                    ModelError::Unavailable(_) => TargetError::Down(e.to_string()),
+                })?,
+        };
+        if choice.rule != ModelRule::Requested {
+            info!("Using {} ({}) on {}", choice.model, choice.rule, target.label);
+        }
+        let variation = Variation { model: Some(choice.model), ..variation.clone() };
+
+        let mut resolved = self.resolve_variation(preset, &variation);
+        debug!("Enhancing with {} using model {} ({:?} mode)", backend.name(), resolved.model, resolved.mode);
+        self.fit_context(target, backend.as_ref(), &mut resolved, input).await.map_err(TargetError::Unsuitable)?;
+
+        let started = Instant::now();
+        let request = resolved.request(input);
+        let generation = match on_delta {
+            Some(on_delta) => backend.enhance_stream(&request, on_delta).await,
+            None => backend.enhance(&request).await,
+        }.map_err(|e| match e {
+            BackendError::Unavailable(e) => TargetError::Down(e),
+            // One bad request says nothing about the server's health
+            BackendError::Rejected(e) => TargetError::Unsuitable(e),
+        })?;
+
+        Ok(Enhancement {
+            generation,
+            model: resolved.model,
+            model_rule: choice.rule,
+            seed: resolved.options.seed,
+            temperature: resolved.options.temperature,
+            backend: backend.name(),
+            target: target.label.clone(),
+            fallback: target.fallback,
+            duration_ms: started.elapsed().as_millis() as u64,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The only test that touches OLLAMA_HOST, so tests running in parallel
+    // can't see each other's value
+    #[test]
+    fn ollama_host_is_read_from_the_environment() {
+        std::env::set_var(OLLAMA_HOST_ENV, "0.0.0.0:11500");
+        assert_eq!(ollama_host_from_env().as_deref(), Some("http://127.0.0.1:11500"));
+
+        std::env::set_var(OLLAMA_HOST_ENV, "ftp://example.com");
+        assert_eq!(ollama_host_from_env(), None);
+
+        std::env::set_var(OLLAMA_HOST_ENV, " ");
+        assert_eq!(ollama_host_from_env(), None);
+
+        std::env::remove_var(OLLAMA_HOST_ENV);
+        assert_eq!(ollama_host_from_env(), None);
+    }
+}
@@ -0,0 +1,460 @@
+use log::{debug, error, info};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::backend::GenerationStats;
+use crate::settings::APP_IDENTIFIER;
+
+const HISTORY_FILE: &str = "history.sqlite3";
+
+/// `SCHEMA[n]` upgrades a version `n` database to version `n + 1`; the
+/// version is kept in SQLite's `user_version`.
+const SCHEMA: &[&str] = &[
+    "CREATE TABLE history (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        created_at INTEGER NOT NULL,
+        source TEXT NOT NULL,
+        original TEXT NOT NULL,
+        enhanced TEXT NOT NULL,
+        model TEXT NOT NULL,
+        preset TEXT,
+        backend TEXT NOT NULL,
+        duration_ms INTEGER NOT NULL,
+        total_duration_ms INTEGER,
+        load_duration_ms INTEGER,
+        prompt_eval_count INTEGER,
+        prompt_eval_duration_ms INTEGER,
+        eval_count INTEGER,
+        eval_duration_ms INTEGER,
+        pinned INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE INDEX history_created_at ON history (created_at);
+    CREATE VIRTUAL TABLE history_fts USING fts5 (
+        original, enhanced, content = 'history', content_rowid = 'id'
+    );
+    CREATE TRIGGER history_ai AFTER INSERT ON history BEGIN
+        INSERT INTO history_fts (rowid, original, enhanced) VALUES (new.id, new.original, new.enhanced);
+    END;
+    CREATE TRIGGER history_ad AFTER DELETE ON history BEGIN
+        INSERT INTO history_fts (history_fts, rowid, original, enhanced) VALUES ('delete', old.id, old.original, old.enhanced);
+    END;",
+    // Keep the index in step if an entry's text ever changes
+    "CREATE TRIGGER history_au AFTER UPDATE OF original, enhanced ON history BEGIN
+        INSERT INTO history_fts (history_fts, rowid, original, enhanced) VALUES ('delete', old.id, old.original, old.enhanced);
+        INSERT INTO history_fts (rowid, original, enhanced) VALUES (new.id, new.original, new.enhanced);
+    END;",
+];
+
+const ENTRY_COLUMNS: &str = "id, created_at, source, original, enhanced, model, preset, backend, duration_ms,
+    total_duration_ms, load_duration_ms, prompt_eval_count, prompt_eval_duration_ms, eval_count, eval_duration_ms, pinned";
+
+/// How much history to keep. Pinned entries are never pruned.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HistorySettings {
+    pub enabled: bool,
+    /// Keep at most this many unpinned entries
+    pub max_entries: Option<u32>,
+    /// Drop unpinned entries older than this
+    pub max_age_days: Option<u32>,
+}
+
+impl Default for HistorySettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_entries: Some(1000),
+            max_age_days: None,
+        }
+    }
+}
+
+/// One recorded enhancement.
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryEntry {
+    pub id: i64,
+    /// Unix time in milliseconds
+    pub created_at: i64,
+    /// Where the request came from: "ui", "hotkey", "ipc", "api", "dbus" or "cli"
+    pub source: String,
+    pub original: String,
+    pub enhanced: String,
+    pub model: String,
+    /// Name of the preset used, if any
+    pub preset: Option<String>,
+    pub backend: String,
+    /// Wall-clock time from request to finished text
+    pub duration_ms: u64,
+    #[serde(flatten)]
+    pub stats: GenerationStats,
+    pub pinned: bool,
+}
+
+impl HistoryEntry {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            created_at: row.get(1)?,
+            source: row.get(2)?,
+            original: row.get(3)?,
+            enhanced: row.get(4)?,
+            model: row.get(5)?,
+            preset: row.get(6)?,
+            backend: row.get(7)?,
+            duration_ms: row.get(8)?,
+            stats: GenerationStats {
+                total_duration_ms: row.get(9)?,
+                load_duration_ms: row.get(10)?,
+                prompt_eval_count: row.get(11)?,
+                prompt_eval_duration_ms: row.get(12)?,
+                eval_count: row.get(13)?,
+                eval_duration_ms: row.get(14)?,
+            },
+            pinned: row.get(15)?,
+        })
+    }
+}
+
+/// An enhancement about to be recorded.
+pub struct NewEntry<'a> {
+    pub source: &'a str,
+    pub original: &'a str,
+    pub enhanced: &'a str,
+    pub model: &'a str,
+    pub preset: Option<&'a str>,
+    pub backend: &'a str,
+    pub duration_ms: u64,
+    pub stats: &'a GenerationStats,
+}
+
+/// Default location of the history database.
+pub fn history_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join(APP_IDENTIFIER).join(HISTORY_FILE))
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+fn db_error(e: rusqlite::Error) -> String {
+    error!("History database error: {}", e);
+    format!("History database error: {}", e)
+}
+
+/// Turn free text into an FTS5 query matching entries that contain every
+/// word, so quotes or operators typed by the user can't cause syntax errors.
+fn fts_query(query: &str) -> String {
+    query.split_whitespace()
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Enhancement history kept in a local SQLite database.
+pub struct HistoryStore {
+    conn: Mutex<Connection>,
+}
+
+impl HistoryStore {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create history directory: {}", e))?;
+        }
+
+        let conn = Connection::open(path).map_err(db_error)?;
+        // The app and the command line may write at the same time
+        conn.busy_timeout(Duration::from_secs(5)).map_err(db_error)?;
+        let store = Self::with_connection(conn)?;
+        debug!("Opened history database at {}", path.display());
+        Ok(store)
+    }
+
+    fn with_connection(conn: Connection) -> Result<Self, String> {
+        Self::migrate(&conn)?;
+        Ok(Self { conn: Mutex::new(conn) })
+    }
+
+    fn migrate(conn: &Connection) -> Result<(), String> {
+        let version: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))
+            .map_err(db_error)?;
+
+        for (step, sql) in SCHEMA.iter().enumerate().skip(version) {
+            debug!("Migrating history database to version {}", step + 1);
+            conn.execute_batch(&format!("BEGIN; {} PRAGMA user_version = {}; COMMIT;", sql, step + 1))
+                .map_err(db_error)?;
+        }
+        Ok(())
+    }
+
+    fn conn(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap()
+    }
+
+    /// Record an enhancement, returning the new entry's id.
+    pub fn add(&self, entry: &NewEntry) -> Result<i64, String> {
+        let conn = self.conn();
+        conn.execute(
+            "INSERT INTO history (created_at, source, original, enhanced, model, preset, backend, duration_ms,
+                total_duration_ms, load_duration_ms, prompt_eval_count, prompt_eval_duration_ms, eval_count, eval_duration_ms)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            params![
+                now_ms(),
+                entry.source,
+                entry.original,
+                entry.enhanced,
+                entry.model,
+                entry.preset,
+                entry.backend,
+                entry.duration_ms,
+                entry.stats.total_duration_ms,
+                entry.stats.load_duration_ms,
+                entry.stats.prompt_eval_count,
+                entry.stats.prompt_eval_duration_ms,
+                entry.stats.eval_count,
+                entry.stats.eval_duration_ms,
+            ],
+        ).map_err(db_error)?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    pub fn get(&self, id: i64) -> Result<Option<HistoryEntry>, String> {
+        self.conn()
+            .query_row(&format!("SELECT {} FROM history WHERE id = ?1", ENTRY_COLUMNS), [id], HistoryEntry::from_row)
+            .optional()
+            .map_err(db_error)
+    }
+
+    /// Newest entries first, with pinned entries ahead of the rest.
+    pub fn list(&self, limit: u32, offset: u32) -> Result<Vec<HistoryEntry>, String> {
+        let conn = self.conn();
+        let mut statement = conn.prepare(&format!(
+            "SELECT {} FROM history ORDER BY pinned DESC, created_at DESC, id DESC LIMIT ?1 OFFSET ?2",
+            ENTRY_COLUMNS,
+        )).map_err(db_error)?;
+
+        let entries = statement.query_map(params![limit, offset], HistoryEntry::from_row)
+            .map_err(db_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(db_error)?;
+        Ok(entries)
+    }
+
+    /// Full-text search over the original and enhanced text, best matches first.
+    pub fn search(&self, query: &str, limit: u32) -> Result<Vec<HistoryEntry>, String> {
+        let query = fts_query(query);
+        if query.is_empty() {
+            return self.list(limit, 0);
+        }
+
+        let conn = self.conn();
+        let columns = ENTRY_COLUMNS.split(',')
+            .map(|column| format!("history.{}", column.trim()))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut statement = conn.prepare(&format!(
+            "SELECT {} FROM history_fts JOIN history ON history.id = history_fts.rowid
+             WHERE history_fts MATCH ?1 ORDER BY rank LIMIT ?2",
+            columns,
+        )).map_err(db_error)?;
+
+        let entries = statement.query_map(params![query, limit], HistoryEntry::from_row)
+            .map_err(db_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(db_error)?;
+        Ok(entries)
+    }
+
+    /// Returns `false` if there was no such entry.
+    pub fn delete(&self, id: i64) -> Result<bool, String> {
+        let deleted = self.conn().execute("DELETE FROM history WHERE id = ?1", [id]).map_err(db_error)?;
+        Ok(deleted > 0)
+    }
+
+    /// Delete every entry, or every unpinned entry. Returns how many went.
+    pub fn clear(&self, include_pinned: bool) -> Result<usize, String> {
+        let sql = if include_pinned {
+            "DELETE FROM history"
+        } else {
+            "DELETE FROM history WHERE pinned = 0"
+        };
+        self.conn().execute(sql, []).map_err(db_error)
+    }
+
+    /// Returns `false` if there was no such entry.
+    pub fn set_pinned(&self, id: i64, pinned: bool) -> Result<bool, String> {
+        let updated = self.conn()
+            .execute("UPDATE history SET pinned = ?1 WHERE id = ?2", params![pinned, id])
+            .map_err(db_error)?;
+        Ok(updated > 0)
+    }
+
+    /// Apply the retention limits, returning how many entries were removed.
+    pub fn prune(&self, settings: &HistorySettings) -> Result<usize, String> {
+        let conn = self.conn();
+        let mut removed = 0;
+
+        if let Some(days) = settings.max_age_days {
+            let cutoff = now_ms() - i64::from(days) * 24 * 60 * 60 * 1000;
+            removed += conn.execute("DELETE FROM history WHERE pinned = 0 AND created_at < ?1", [cutoff])
+                .map_err(db_error)?;
+        }
+
+        if let Some(max) = settings.max_entries {
+            removed += conn.execute(
+                "DELETE FROM history WHERE pinned = 0 AND id NOT IN (
+                    SELECT id FROM history WHERE pinned = 0 ORDER BY created_at DESC, id DESC LIMIT ?1
+                )",
+                [max],
+            ).map_err(db_error)?;
+        }
+
+        if removed > 0 {
+            info!("Pruned {} history entries", removed);
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+    fn store() -> HistoryStore {
+        HistoryStore::with_connection(Connection::open_in_memory().unwrap()).unwrap()
+    }
+
+    fn add(store: &HistoryStore, original: &str, enhanced: &str) -> i64 {
+        store.add(&NewEntry {
+            source: "cli",
+            original,
+            enhanced,
+            model: "llama3.2:3b",
+            preset: None,
+            backend: "Ollama",
+            duration_ms: 10,
+            stats: &GenerationStats::default(),
+        }).unwrap()
+    }
+
+    fn ids(entries: Vec<HistoryEntry>) -> Vec<i64> {
+        entries.into_iter().map(|entry| entry.id).collect()
+    }
+
+    fn set_age(store: &HistoryStore, id: i64, days: i64) {
+        store.conn().execute("UPDATE history SET created_at = ?1 WHERE id = ?2", params![now_ms() - days * DAY_MS, id]).unwrap();
+    }
+
+    #[test]
+    fn fts_query_quotes_and_prefixes_each_word() {
+        assert_eq!(fts_query("carbonara recipe"), r#""carbonara"* "recipe"*"#);
+        assert_eq!(fts_query(r#" say "hi" "#), r#""say"* """hi"""*"#);
+        assert_eq!(fts_query("  "), "");
+    }
+
+    #[test]
+    fn search_finds_words_and_prefixes_in_either_text() {
+        let store = store();
+        let recipe = add(&store, "carbonara recipe", "Provide an authentic carbonara recipe.");
+        let resume = add(&store, "improve my resume", "Revise my résumé to highlight achievements.");
+
+        assert_eq!(ids(store.search("carbo", 10).unwrap()), vec![recipe]);
+        assert_eq!(ids(store.search("authentic", 10).unwrap()), vec![recipe]);
+        assert_eq!(ids(store.search("resume improve", 10).unwrap()), vec![resume]);
+        assert!(store.search("resume carbonara", 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn search_treats_fts_syntax_as_text() {
+        let store = store();
+        let id = add(&store, r#"what does "NOT" mean in AND/OR (logic)?"#, "Explain boolean operators.");
+
+        assert_eq!(ids(store.search(r#""NOT""#, 10).unwrap()), vec![id]);
+        assert_eq!(ids(store.search("AND OR (logic", 10).unwrap()), vec![id]);
+        assert!(store.search("NEAR(x y)", 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn search_follows_updates_and_deletes() {
+        let store = store();
+        let id = add(&store, "carbonara recipe", "A carbonara recipe.");
+        let other = add(&store, "carbonara history", "The history of carbonara.");
+
+        store.conn().execute("UPDATE history SET original = 'risotto recipe', enhanced = 'A risotto recipe.' WHERE id = ?1", [id]).unwrap();
+        assert_eq!(ids(store.search("risotto", 10).unwrap()), vec![id]);
+        assert_eq!(ids(store.search("carbonara", 10).unwrap()), vec![other]);
+
+        assert!(store.set_pinned(id, true).unwrap());
+        assert_eq!(ids(store.search("risotto", 10).unwrap()), vec![id]);
+
+        assert!(store.delete(id).unwrap());
+        assert!(!store.delete(id).unwrap());
+        assert!(store.search("risotto", 10).unwrap().is_empty());
+        assert!(store.search("recipe", 10).unwrap().is_empty());
+        assert_eq!(ids(store.search("carbonara", 10).unwrap()), vec![other]);
+    }
+
+    #[test]
+    fn migrate_upgrades_from_the_stored_version() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(&format!("{} PRAGMA user_version = 1;", SCHEMA[0])).unwrap();
+        conn.execute(
+            "INSERT INTO history (created_at, source, original, enhanced, model, backend, duration_ms)
+             VALUES (1, 'ui', 'carbonara recipe', 'A carbonara recipe.', 'llama3.2:3b', 'Ollama', 10)",
+            [],
+        ).unwrap();
+
+        let store = HistoryStore::with_connection(conn).unwrap();
+        let version: usize = store.conn().pragma_query_value(None, "user_version", |row| row.get(0)).unwrap();
+        assert_eq!(version, SCHEMA.len());
+        assert_eq!(store.search("carbonara", 10).unwrap().len(), 1);
+
+        // Opening an up-to-date database again changes nothing
+        HistoryStore::migrate(&store.conn()).unwrap();
+        assert_eq!(store.list(10, 0).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn prune_keeps_the_newest_unpinned_entries() {
+        let store = store();
+        let oldest = add(&store, "one", "One.");
+        let pinned = add(&store, "two", "Two.");
+        let middle = add(&store, "three", "Three.");
+        let newest = add(&store, "four", "Four.");
+        store.set_pinned(pinned, true).unwrap();
+        set_age(&store, oldest, 3);
+        set_age(&store, pinned, 2);
+        set_age(&store, middle, 1);
+
+        let removed = store.prune(&HistorySettings { max_entries: Some(1), max_age_days: None, ..HistorySettings::default() }).unwrap();
+
+        assert_eq!(removed, 2);
+        assert_eq!(ids(store.list(10, 0).unwrap()), vec![pinned, newest]);
+        assert!(store.search("three", 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn prune_drops_unpinned_entries_past_the_age_limit() {
+        let store = store();
+        let old = add(&store, "old", "Old.");
+        let old_pinned = add(&store, "old pinned", "Old and pinned.");
+        let recent = add(&store, "recent", "Recent.");
+        store.set_pinned(old_pinned, true).unwrap();
+        set_age(&store, old, 31);
+        set_age(&store, old_pinned, 31);
+        set_age(&store, recent, 29);
+
+        let removed = store.prune(&HistorySettings { max_entries: None, max_age_days: Some(30), ..HistorySettings::default() }).unwrap();
+
+        assert_eq!(removed, 1);
+        assert_eq!(ids(store.list(10, 0).unwrap()), vec![old_pinned, recent]);
+    }
+}
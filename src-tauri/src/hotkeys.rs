@@ -0,0 +1,182 @@
+use log::{error, info};
+use serde::Serialize;
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut};
+
+use crate::presets::{self, Preset};
+use crate::settings::Settings;
+
+/// Shortcut that enhances the clipboard with the main system prompt until the
+/// user picks another one.
+pub const DEFAULT_ENHANCE_HOTKEY: &str = "CommandOrControl+Shift+E";
+
+/// Something a global shortcut can be bound to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase", tag = "type", content = "id")]
+pub enum HotkeyTarget {
+    /// Enhance the clipboard with the active preset or the main system prompt
+    Enhance,
+    /// Cancel every running enhancement
+    Cancel,
+    /// Put back the clipboard from before the last enhancement
+    Undo,
+    /// Enhance the clipboard with the preset with this id
+    Preset(String),
+}
+
+/// What pressing one of our global shortcuts should do.
+pub enum HotkeyAction {
+    Cancel,
+    Undo,
+    /// Enhance the clipboard, with a preset or the default prompt
+    Enhance(Option<Preset>),
+}
+
+/// A configured shortcut and whether the OS actually gave it to us.
+#[derive(Debug, Clone, Serialize)]
+pub struct HotkeyStatus {
+    pub target: HotkeyTarget,
+    /// Human-readable name of the target, e.g. the preset name
+    pub label: String,
+    pub shortcut: String,
+    pub registered: bool,
+}
+
+/// Parse an accelerator string such as `CommandOrControl+Shift+E`.
+pub fn parse(shortcut: &str) -> Result<Shortcut, String> {
+    shortcut.parse::<Shortcut>()
+        .map_err(|e| format!("Invalid shortcut '{}': {}", shortcut, e))
+}
+
+/// Trim a shortcut from the UI, treating an empty one as "none".
+pub fn normalize(shortcut: Option<String>) -> Option<String> {
+    shortcut.map(|s| s.trim().to_string()).filter(|s| !s.is_empty())
+}
+
+fn label(settings: &Settings, target: &HotkeyTarget) -> String {
+    match target {
+        HotkeyTarget::Enhance => "Enhance clipboard".to_string(),
+        HotkeyTarget::Cancel => "Cancel enhancement".to_string(),
+        HotkeyTarget::Undo => "Undo last enhancement".to_string(),
+        HotkeyTarget::Preset(id) => settings.presets.iter()
+            .find(|p| &p.id == id)
+            .map_or_else(|| format!("Preset {}", id), |p| format!("Preset \"{}\"", p.name)),
+    }
+}
+
+/// Every shortcut in the settings with what it is bound to, in the order
+/// presses are matched.
+pub fn bindings(settings: &Settings) -> Vec<(HotkeyTarget, String)> {
+    let mut bindings = Vec::new();
+    if let Some(shortcut) = &settings.cancel_hotkey {
+        bindings.push((HotkeyTarget::Cancel, shortcut.clone()));
+    }
+    if let Some(shortcut) = &settings.undo_hotkey {
+        bindings.push((HotkeyTarget::Undo, shortcut.clone()));
+    }
+    for preset in &settings.presets {
+        if let Some(shortcut) = &preset.hotkey {
+            bindings.push((HotkeyTarget::Preset(preset.id.clone()), shortcut.clone()));
+        }
+    }
+    if let Some(shortcut) = &settings.enhance_hotkey {
+        bindings.push((HotkeyTarget::Enhance, shortcut.clone()));
+    }
+    bindings
+}
+
+/// Check that `shortcut` is valid and not already bound to anything other
+/// than `target`.
+pub fn check_available(settings: &Settings, target: &HotkeyTarget, shortcut: &str) -> Result<(), String> {
+    let parsed = parse(shortcut)?;
+
+    for (other, other_shortcut) in bindings(settings) {
+        if &other == target {
+            continue;
+        }
+        if parse(&other_shortcut).is_ok_and(|s| s.id() == parsed.id()) {
+            return Err(format!("{} is already used for {}", shortcut, label(settings, &other)));
+        }
+    }
+    Ok(())
+}
+
+/// Move one of our global shortcuts from `previous` to `next`; either may be
+/// `None` to only register or only unregister. If `next` can't be registered
+/// (usually because another application holds it), `previous` is restored.
+pub fn rebind(app_handle: &tauri::AppHandle, previous: Option<&str>, next: Option<&str>) -> Result<(), String> {
+    let next_shortcut = next.map(parse).transpose()?;
+    let previous_shortcut = previous.and_then(|s| s.parse::<Shortcut>().ok());
+
+    let global_shortcut = app_handle.global_shortcut();
+    if let (Some(previous), Some(next)) = (&previous_shortcut, &next_shortcut) {
+        // Still retry if it never got registered (e.g. taken at startup)
+        if previous.id() == next.id() && global_shortcut.is_registered(*next) {
+            return Ok(());
+        }
+    }
+
+    if let Some(previous) = previous_shortcut {
+        if global_shortcut.is_registered(previous) {
+            if let Err(e) = global_shortcut.unregister(previous) {
+                error!("Failed to unregister hotkey {}: {}", previous, e);
+            }
+        }
+    }
+
+    if let Some(shortcut) = next_shortcut {
+        if let Err(e) = global_shortcut.register(shortcut) {
+            if let Some(previous) = previous_shortcut {
+                let _ = global_shortcut.register(previous);
+            }
+            return Err(format!("Couldn't register {}, it may already be in use by another application ({})", next.unwrap_or_default(), e));
+        }
+    }
+    Ok(())
+}
+
+/// Register every shortcut in the settings, logging the ones that fail.
+pub fn register_all(app_handle: &tauri::AppHandle, settings: &Settings) {
+    let global_shortcut = app_handle.global_shortcut();
+    for (target, shortcut) in bindings(settings) {
+        let result = parse(&shortcut).and_then(|parsed| {
+            if global_shortcut.is_registered(parsed) {
+                return Ok(());
+            }
+            global_shortcut.register(parsed).map_err(|e| e.to_string())
+        });
+        match result {
+            Ok(()) => info!("Hotkey {} registered for {}", shortcut, label(settings, &target)),
+            Err(e) => error!("Failed to register hotkey {} for {}: {}", shortcut, label(settings, &target), e),
+        }
+    }
+}
+
+/// Which of the configured shortcuts are actually registered.
+pub fn status(app_handle: &tauri::AppHandle, settings: &Settings) -> Vec<HotkeyStatus> {
+    let global_shortcut = app_handle.global_shortcut();
+    bindings(settings).into_iter()
+        .map(|(target, shortcut)| HotkeyStatus {
+            label: label(settings, &target),
+            registered: parse(&shortcut).is_ok_and(|s| global_shortcut.is_registered(s)),
+            target,
+            shortcut,
+        })
+        .collect()
+}
+
+/// Work out which action `shortcut` is bound to.
+pub fn action_for(settings: &Settings, shortcut: &Shortcut) -> Option<HotkeyAction> {
+    let (target, _) = bindings(settings).into_iter()
+        .find(|(_, bound)| parse(bound).is_ok_and(|s| s.id() == shortcut.id()))?;
+
+    match target {
+        HotkeyTarget::Cancel => Some(HotkeyAction::Cancel),
+        HotkeyTarget::Undo => Some(HotkeyAction::Undo),
+        HotkeyTarget::Enhance => Some(HotkeyAction::Enhance(
+            settings.active_preset.as_deref().and_then(|id| presets::find(&settings.presets, id)).cloned(),
+        )),
+        HotkeyTarget::Preset(id) => settings.presets.iter()
+            .find(|p| p.id == id)
+            .map(|p| HotkeyAction::Enhance(Some(p.clone()))),
+    }
+}
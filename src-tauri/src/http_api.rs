@@ -0,0 +1,248 @@
+use axum::extract::rejection::JsonRejection;
+use axum::extract::State;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::convert::Infallible;
+use std::fs;
+use std::net::Ipv4Addr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tauri::Manager;
+use tokio::sync::{mpsc, oneshot};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tokio_stream::StreamExt;
+
+use crate::jobs::JobKind;
+use crate::settings::APP_IDENTIFIER;
+use crate::AppState;
+
+pub const DEFAULT_HTTP_API_PORT: u16 = 7879;
+const TOKEN_FILE: &str = "api-token";
+
+/// The loopback HTTP API, off unless the user turns it on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HttpApiSettings {
+    pub enabled: bool,
+    /// Port on 127.0.0.1 to listen on
+    pub port: u16,
+}
+
+impl Default for HttpApiSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: DEFAULT_HTTP_API_PORT,
+        }
+    }
+}
+
+/// Where the bearer token is kept, next to the settings file.
+pub fn token_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join(APP_IDENTIFIER).join(TOKEN_FILE))
+}
+
+/// Read the bearer token, creating one if there is none yet.
+pub fn load_or_create_token() -> Result<String, String> {
+    let path = token_path().ok_or_else(|| "No config directory available".to_string())?;
+    match fs::read_to_string(&path) {
+        Ok(token) if !token.trim().is_empty() => Ok(token.trim().to_string()),
+        _ => create_token(),
+    }
+}
+
+/// Replace the bearer token with a new random one. Clients holding the old
+/// token are locked out.
+pub fn create_token() -> Result<String, String> {
+    let path = token_path().ok_or_else(|| "No config directory available".to_string())?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create settings directory: {}", e))?;
+    }
+
+    let mut bytes = [0u8; 32];
+    getrandom::getrandom(&mut bytes)
+        .map_err(|e| format!("Failed to generate API token: {}", e))?;
+    let token: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&path)
+        .map_err(|e| format!("Failed to write API token: {}", e))?;
+    // The mode above only applies to a new file, and the token grants full
+    // control, so tighten an existing one too before writing to it
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))
+            .map_err(|e| format!("Failed to restrict API token permissions: {}", e))?;
+    }
+    std::io::Write::write_all(&mut file, token.as_bytes())
+        .map_err(|e| format!("Failed to write API token: {}", e))?;
+
+    info!("Created a new HTTP API token in {}", path.display());
+    Ok(token)
+}
+
+/// A running server; dropping it shuts the server down.
+pub struct HttpApiServer {
+    pub port: u16,
+    pub token: String,
+    _shutdown: oneshot::Sender<()>,
+}
+
+#[derive(Clone)]
+struct ApiState {
+    app_handle: tauri::AppHandle,
+    token: Arc<String>,
+}
+
+/// Start serving on `127.0.0.1:port`.
+pub async fn start(app_handle: tauri::AppHandle, port: u16) -> Result<HttpApiServer, String> {
+    let token = load_or_create_token()?;
+    let listener = tokio::net::TcpListener::bind((Ipv4Addr::LOCALHOST, port)).await
+        .map_err(|e| format!("Failed to listen on 127.0.0.1:{}: {}", port, e))?;
+
+    let router = Router::new()
+        .route("/health", get(health))
+        .route("/models", get(models))
+        .route("/enhance", post(enhance))
+        .with_state(ApiState { app_handle, token: Arc::new(token.clone()) });
+
+    let (shutdown, stopped) = oneshot::channel::<()>();
+    tauri::async_runtime::spawn(async move {
+        let result = axum::serve(listener, router)
+            .with_graceful_shutdown(async { let _ = stopped.await; })
+            .await;
+        match result {
+            Ok(()) => info!("HTTP API on port {} stopped", port),
+            Err(e) => error!("HTTP API on port {} failed: {}", port, e),
+        }
+    });
+
+    info!("HTTP API listening on http://127.0.0.1:{}", port);
+    Ok(HttpApiServer { port, token, _shutdown: shutdown })
+}
+
+struct ApiError(StatusCode, String);
+
+impl ApiError {
+    fn bad_request(message: impl Into<String>) -> Self {
+        Self(StatusCode::BAD_REQUEST, message.into())
+    }
+
+    fn internal(message: String) -> Self {
+        Self(StatusCode::INTERNAL_SERVER_ERROR, message)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = Json(json!({ "error": self.1 }));
+        if self.0 == StatusCode::UNAUTHORIZED {
+            return (self.0, [(header::WWW_AUTHENTICATE, "Bearer")], body).into_response();
+        }
+        (self.0, body).into_response()
+    }
+}
+
+/// Check the `Authorization: Bearer <token>` header.
+fn authorize(api: &ApiState, headers: &HeaderMap) -> Result<(), ApiError> {
+    let provided = headers.get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default()
+        .as_bytes();
+    let expected = api.token.as_bytes();
+
+    // Compare in constant time so the token can't be guessed byte by byte
+    let matches = provided.len() == expected.len()
+        && provided.iter().zip(expected).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0;
+    if matches {
+        Ok(())
+    } else {
+        Err(ApiError(StatusCode::UNAUTHORIZED, "Missing or invalid bearer token".to_string()))
+    }
+}
+
+/// `GET /health` needs no token, so clients can check the app is running.
+async fn health() -> Json<serde_json::Value> {
+    Json(json!({ "status": "ok", "version": env!("CARGO_PKG_VERSION") }))
+}
+
+/// `GET /models`, like the `get_available_models` command.
+async fn models(State(api): State<ApiState>, headers: HeaderMap) -> Result<Json<serde_json::Value>, ApiError> {
+    authorize(&api, &headers)?;
+    let models = crate::get_available_models(api.app_handle.state::<AppState>()).await
+        .map_err(ApiError::internal)?;
+    Ok(Json(json!({ "models": models })))
+}
+
+#[derive(Deserialize)]
+struct EnhanceBody {
+    prompt: String,
+    model: Option<String>,
+    /// Preset name or id; the active preset is used if omitted
+    preset: Option<String>,
+    #[serde(default)]
+    stream: bool,
+}
+
+/// `POST /enhance`, like the `enhance_prompt` command. With `"stream": true`
+/// (or `Accept: text/event-stream`) the text is sent as server-sent events:
+/// `delta` events with each piece, then one `done` or `error` event.
+async fn enhance(State(api): State<ApiState>, headers: HeaderMap, body: Result<Json<EnhanceBody>, JsonRejection>) -> Result<Response, ApiError> {
+    authorize(&api, &headers)?;
+    let Json(body) = body.map_err(|e| ApiError::bad_request(e.body_text()))?;
+    if body.prompt.trim().is_empty() {
+        return Err(ApiError::bad_request("Nothing to enhance, the prompt is empty"));
+    }
+
+    let state = api.app_handle.state::<AppState>();
+    let preset = crate::requested_preset(&state, body.preset).map_err(ApiError::bad_request)?;
+
+    let wants_events = headers.get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/event-stream"));
+    if !body.stream && !wants_events {
+        let text = crate::run_enhancement(&api.app_handle, "api", &body.prompt, preset.as_ref(), body.model).await
+            .map_err(ApiError::internal)?;
+        return Ok(Json(json!({ "text": text })).into_response());
+    }
+
+    let (events, receiver) = mpsc::unbounded_channel::<Event>();
+    let app_handle = api.app_handle.clone();
+    tauri::async_runtime::spawn(async move {
+        let state = app_handle.state::<AppState>();
+        let job = state.jobs.start(JobKind::Enhancement);
+        let cancel = job.cancel.clone();
+        let result = crate::stream_enhancement(&app_handle, &job, "api", &body.prompt, preset.as_ref(), body.model, |delta, _| {
+            // The client went away, so stop generating for nobody
+            if events.send(sse_event("delta", json!({ "text": delta }))).is_err() {
+                cancel.cancel();
+            }
+        }).await;
+        let _ = events.send(match result {
+            Ok(text) => sse_event("done", json!({ "text": text })),
+            Err(e) => sse_event("error", json!({ "error": e })),
+        });
+    });
+
+    let stream = UnboundedReceiverStream::new(receiver).map(Ok::<_, Infallible>);
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()).into_response())
+}
+
+fn sse_event(name: &str, data: serde_json::Value) -> Event {
+    Event::default().event(name).data(data.to_string())
+}
@@ -0,0 +1,174 @@
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+use crate::clipboard::{command_exists, hide_console, is_wayland};
+
+/// Longest delay the settings accept, so a typo can't hang the hotkey.
+const MAX_DELAY_MS: u64 = 5000;
+
+/// A tool that can type the copy and paste shortcuts into the focused app.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InputBackend {
+    /// Try the tools available on this platform in turn
+    #[default]
+    Auto,
+    /// X11, through the XTest extension
+    Xdotool,
+    /// Wayland compositors with the virtual keyboard protocol (wlroots)
+    Wtype,
+    /// Any Linux session, through uinput; needs `ydotoold` running
+    Ydotool,
+    /// macOS System Events; needs the Accessibility permission
+    Osascript,
+    /// Windows `SendKeys`
+    Powershell,
+}
+
+/// Select → hotkey → the selection is replaced, without copying or pasting
+/// by hand.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutoPasteSettings {
+    pub enabled: bool,
+    pub backend: InputBackend,
+    /// How long to wait for the focused app after each synthesized
+    /// shortcut (and for the hotkey's keys to be released)
+    pub delay_ms: u64,
+    /// Put back what was on the clipboard before, once the result is pasted
+    pub restore_clipboard: bool,
+}
+
+impl Default for AutoPasteSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            backend: InputBackend::Auto,
+            delay_ms: 150,
+            restore_clipboard: true,
+        }
+    }
+}
+
+impl AutoPasteSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.delay_ms > MAX_DELAY_MS {
+            return Err(format!("Delay must be at most {} ms", MAX_DELAY_MS));
+        }
+        Ok(())
+    }
+
+    pub fn delay(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.delay_ms)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Shortcut {
+    Copy,
+    Paste,
+}
+
+/// The tools that suit `backend` on this system, best first, whether they
+/// are installed or not.
+fn preferred(backend: InputBackend) -> Vec<InputBackend> {
+    if backend != InputBackend::Auto {
+        return vec![backend];
+    }
+
+    match std::env::consts::OS {
+        "macos" => vec![InputBackend::Osascript],
+        "windows" => vec![InputBackend::Powershell],
+        _ if is_wayland() => vec![InputBackend::Wtype, InputBackend::Ydotool],
+        _ => vec![InputBackend::Xdotool, InputBackend::Ydotool],
+    }
+}
+
+/// The tools to try for `backend`, best first, keeping only installed ones.
+pub fn candidates(backend: InputBackend) -> Vec<InputBackend> {
+    if backend != InputBackend::Auto {
+        return vec![backend];
+    }
+    preferred(backend).into_iter().filter(|b| command_exists(program(*b))).collect()
+}
+
+fn program(backend: InputBackend) -> &'static str {
+    match backend {
+        InputBackend::Auto => "",
+        InputBackend::Xdotool => "xdotool",
+        InputBackend::Wtype => "wtype",
+        InputBackend::Ydotool => "ydotool",
+        InputBackend::Osascript => "osascript",
+        InputBackend::Powershell => "powershell",
+    }
+}
+
+fn command(backend: InputBackend, shortcut: Shortcut) -> Command {
+    let (key, linux_keycode, sendkeys) = match shortcut {
+        Shortcut::Copy => ("c", "46", "^c"),
+        Shortcut::Paste => ("v", "47", "^v"),
+    };
+
+    let mut command = Command::new(program(backend));
+    hide_console(&mut command);
+    match backend {
+        InputBackend::Xdotool => {
+            // Release the hotkey's modifiers first, or Ctrl+C becomes Ctrl+Shift+C
+            command.args(["key", "--clearmodifiers", &format!("ctrl+{}", key)]);
+        },
+        InputBackend::Wtype => {
+            command.args(["-M", "ctrl", "-k", key, "-m", "ctrl"]);
+        },
+        InputBackend::Ydotool => {
+            // Linux input event codes: 29 is left Ctrl
+            command.args(["key", "29:1", &format!("{}:1", linux_keycode), &format!("{}:0", linux_keycode), "29:0"]);
+        },
+        InputBackend::Osascript => {
+            command.args(["-e", &format!("tell application \"System Events\" to keystroke \"{}\" using command down", key)]);
+        },
+        InputBackend::Powershell => {
+            command.args([
+                "-NoProfile",
+                "-Command",
+                &format!("Add-Type -AssemblyName System.Windows.Forms; [System.Windows.Forms.SendKeys]::SendWait('{}')", sendkeys),
+            ]);
+        },
+        InputBackend::Auto => {},
+    }
+    command
+}
+
+async fn send(settings: &AutoPasteSettings, shortcut: Shortcut) -> Result<(), String> {
+    let candidates = candidates(settings.backend);
+    if candidates.is_empty() {
+        let looked_for: Vec<&str> = preferred(settings.backend).into_iter().map(program).collect();
+        return Err(format!("No tool found to send keystrokes, looked for {}", looked_for.join(" and ")));
+    }
+
+    let mut last_error = String::new();
+    for backend in candidates {
+        match command(backend, shortcut).output().await {
+            Ok(output) if output.status.success() => {
+                debug!("Sent keystroke with {}", program(backend));
+                return Ok(());
+            },
+            Ok(output) => {
+                last_error = format!("{} failed: {}", program(backend), String::from_utf8_lossy(&output.stderr).trim());
+            },
+            Err(e) => last_error = format!("Failed to run {}: {}", program(backend), e),
+        }
+        error!("{}", last_error);
+    }
+    Err(last_error)
+}
+
+/// Press the copy shortcut in the focused app.
+pub async fn send_copy(settings: &AutoPasteSettings) -> Result<(), String> {
+    send(settings, Shortcut::Copy).await
+}
+
+/// Press the paste shortcut in the focused app.
+pub async fn send_paste(settings: &AutoPasteSettings) -> Result<(), String> {
+    send(settings, Shortcut::Paste).await
+}
@@ -0,0 +1,195 @@
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+
+/// Requests longer than this are rejected and the connection closed.
+const MAX_REQUEST_BYTES: u64 = 4 * 1024 * 1024;
+
+/// Something another program asked the running app to do.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum IpcCommand {
+    /// Enhance `text` and return the result. Without a preset the active
+    /// preset (or the system prompt) is used.
+    Enhance {
+        text: String,
+        preset: Option<String>,
+        model: Option<String>,
+    },
+    /// Select the model used when a request doesn't name one
+    SetModel { model: String },
+    /// Make a preset (by id or name) the default, or clear it with `null`
+    SetPreset { preset: Option<String> },
+    /// Report the current model, preset, backend and running enhancements
+    Status,
+}
+
+/// One line of the protocol: `{"id": ..., "command": "...", ...}`. The
+/// optional `id` is echoed back so clients can match up responses.
+#[derive(Debug, Deserialize)]
+struct IpcRequest {
+    #[serde(default)]
+    id: Value,
+    #[serde(flatten)]
+    command: IpcCommand,
+}
+
+#[derive(Debug, Serialize)]
+struct IpcResponse {
+    id: Value,
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl IpcResponse {
+    fn new(id: Value, result: Result<Value, String>) -> Self {
+        match result {
+            Ok(result) => Self { id, ok: true, result: Some(result), error: None },
+            Err(error) => Self { id, ok: false, result: None, error: Some(error) },
+        }
+    }
+}
+
+/// Where the socket lives: the runtime directory if there is one (it is
+/// private to the user), else the app's data directory.
+#[cfg(unix)]
+pub fn endpoint() -> Option<String> {
+    let dir = dirs::runtime_dir()
+        .or_else(|| dirs::data_dir().map(|dir| dir.join(crate::settings::APP_IDENTIFIER)))?;
+    Some(dir.join("clip-prompt.sock").to_string_lossy().into_owned())
+}
+
+/// The named pipe, one per user so sessions on a shared machine don't clash.
+#[cfg(windows)]
+pub fn endpoint() -> Option<String> {
+    let user = std::env::var("USERNAME").unwrap_or_default();
+    Some(format!(r"\\.\pipe\clip-prompt-{}", user))
+}
+
+/// Serve IPC requests until the app exits. Failing to listen (e.g. because
+/// another instance already is) is logged, not fatal.
+pub async fn serve(app_handle: tauri::AppHandle) {
+    let Some(endpoint) = endpoint() else {
+        error!("No runtime or data directory available, IPC is disabled");
+        return;
+    };
+    if let Err(e) = listen(app_handle, &endpoint).await {
+        error!("IPC server on {} stopped: {}", endpoint, e);
+    }
+}
+
+#[cfg(unix)]
+async fn listen(app_handle: tauri::AppHandle, endpoint: &str) -> Result<(), String> {
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+    use tokio::net::{UnixListener, UnixStream};
+
+    let path = Path::new(endpoint);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create socket directory: {}", e))?;
+    }
+
+    // A socket nobody answers on is left over from a crash
+    if path.exists() {
+        if UnixStream::connect(path).await.is_ok() {
+            return Err("Another instance is already listening".to_string());
+        }
+        std::fs::remove_file(path)
+            .map_err(|e| format!("Failed to remove stale socket: {}", e))?;
+    }
+
+    let listener = UnixListener::bind(path)
+        .map_err(|e| format!("Failed to bind socket: {}", e))?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+        .map_err(|e| format!("Failed to restrict socket permissions: {}", e))?;
+    info!("Listening for IPC requests on {}", endpoint);
+
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tauri::async_runtime::spawn(serve_connection(app_handle.clone(), stream));
+            },
+            Err(e) => error!("Failed to accept IPC connection: {}", e),
+        }
+    }
+}
+
+#[cfg(windows)]
+async fn listen(app_handle: tauri::AppHandle, endpoint: &str) -> Result<(), String> {
+    use tokio::net::windows::named_pipe::ServerOptions;
+
+    let mut server = ServerOptions::new()
+        .first_pipe_instance(true)
+        .create(endpoint)
+        .map_err(|e| format!("Failed to create named pipe (is another instance running?): {}", e))?;
+    info!("Listening for IPC requests on {}", endpoint);
+
+    loop {
+        server.connect().await
+            .map_err(|e| format!("Failed to accept IPC connection: {}", e))?;
+        // Have the next instance ready before handing this one off
+        let client = std::mem::replace(
+            &mut server,
+            ServerOptions::new().create(endpoint).map_err(|e| format!("Failed to create named pipe: {}", e))?,
+        );
+        tauri::async_runtime::spawn(serve_connection(app_handle.clone(), client));
+    }
+}
+
+/// Answer newline-delimited JSON requests on one connection, in order, until
+/// the client hangs up.
+async fn serve_connection<S: AsyncRead + AsyncWrite + Send + 'static>(app_handle: tauri::AppHandle, stream: S) {
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut reader = BufReader::new(reader);
+    let mut line = String::new();
+    debug!("IPC client connected");
+
+    loop {
+        line.clear();
+        match (&mut reader).take(MAX_REQUEST_BYTES).read_line(&mut line).await {
+            Ok(0) => break,
+            Ok(_) => {},
+            Err(e) => {
+                debug!("Failed to read IPC request: {}", e);
+                break;
+            }
+        }
+
+        let too_long = !line.ends_with('\n') && line.len() as u64 >= MAX_REQUEST_BYTES;
+        let response = if too_long {
+            IpcResponse::new(Value::Null, Err("Request is too large".to_string()))
+        } else if line.trim().is_empty() {
+            continue;
+        } else {
+            handle_line(&app_handle, &line).await
+        };
+
+        let mut output = serde_json::to_string(&response).unwrap_or_default();
+        output.push('\n');
+        if writer.write_all(output.as_bytes()).await.is_err() || too_long {
+            break;
+        }
+    }
+    debug!("IPC client disconnected");
+}
+
+async fn handle_line(app_handle: &tauri::AppHandle, line: &str) -> IpcResponse {
+    let value: Value = match serde_json::from_str(line) {
+        Ok(value) => value,
+        Err(e) => return IpcResponse::new(Value::Null, Err(format!("Invalid JSON: {}", e))),
+    };
+    let id = value.get("id").cloned().unwrap_or(Value::Null);
+
+    match serde_json::from_value::<IpcRequest>(value) {
+        Ok(request) => {
+            debug!("IPC request {:?}", request.command);
+            IpcResponse::new(request.id, crate::handle_ipc_command(app_handle, request.command).await)
+        },
+        Err(e) => IpcResponse::new(id, Err(format!("Invalid request: {}", e))),
+    }
+}
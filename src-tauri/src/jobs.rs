@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{watch, Notify};
+
+/// Error returned by an enhancement that was cancelled before it finished.
+pub const CANCELLED_ERROR: &str = "Enhancement cancelled";
+
+/// Shared flag that lets one task ask another to stop.
+#[derive(Clone, Default)]
+pub struct CancelToken {
+    inner: Arc<CancelInner>,
+}
+
+#[derive(Default)]
+struct CancelInner {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+        self.inner.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Resolves once `cancel` has been called.
+    pub async fn cancelled(&self) {
+        loop {
+            // Register interest before checking the flag so a concurrent
+            // `cancel` can't slip in between the check and the wait
+            let notified = self.inner.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// Run `future` until it completes or the token is cancelled, whichever
+    /// happens first. Dropping the future aborts any request it has in flight.
+    pub async fn run<T>(&self, future: impl Future<Output = Result<T, String>>) -> Result<T, String> {
+        tokio::select! {
+            result = future => result,
+            _ = self.cancelled() => Err(CANCELLED_ERROR.to_string()),
+        }
+    }
+}
+
+/// What a job is doing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobKind {
+    /// Enhancing one text, from any of the ways in
+    Enhancement,
+    /// Running the same text through several models
+    Comparison,
+    /// Downloading a model
+    Pull,
+}
+
+struct RunningJob {
+    kind: JobKind,
+    cancel: CancelToken,
+}
+
+/// The running jobs, shared by the registry and every [`Job`].
+#[derive(Default)]
+struct Running {
+    jobs: Mutex<HashMap<u64, RunningJob>>,
+    /// Whether any enhancement is running
+    enhancing: watch::Sender<bool>,
+}
+
+impl Running {
+    /// Apply `change` to the jobs, telling [`JobRegistry::watch_enhancing`]
+    /// watchers if that starts or ends a spell of enhancing.
+    fn change<T>(&self, change: impl FnOnce(&mut HashMap<u64, RunningJob>) -> T) -> T {
+        let mut jobs = self.jobs.lock().unwrap();
+        let result = change(&mut jobs);
+        let enhancing = jobs.values().any(|job| job.kind == JobKind::Enhancement);
+        self.enhancing.send_if_modified(|was_enhancing| std::mem::replace(was_enhancing, enhancing) != enhancing);
+        result
+    }
+}
+
+/// A registered job: its id plus the token used to cancel it. Dropping it
+/// removes the job from the registry, so a caller that goes away before
+/// settling (e.g. an HTTP client that disconnects) doesn't leave it behind.
+pub struct Job {
+    pub id: u64,
+    pub cancel: CancelToken,
+    running: Arc<Running>,
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        self.running.change(|jobs| jobs.remove(&self.id));
+    }
+}
+
+/// Registry of the enhancements, comparisons and model downloads that are
+/// currently running.
+pub struct JobRegistry {
+    next_id: AtomicU64,
+    running: Arc<Running>,
+}
+
+impl Default for JobRegistry {
+    fn default() -> Self {
+        Self {
+            next_id: AtomicU64::new(1),
+            running: Arc::default(),
+        }
+    }
+}
+
+impl JobRegistry {
+    /// Register a new job.
+    pub fn start(&self, kind: JobKind) -> Job {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let cancel = CancelToken::default();
+        self.running.change(|jobs| jobs.insert(id, RunningJob { kind, cancel: cancel.clone() }));
+        Job { id, cancel, running: self.running.clone() }
+    }
+
+    /// Remove a job from the registry. Returns `false` if it was cancelled, in
+    /// which case the caller must discard its result. Once this returns `true`
+    /// the job can no longer be cancelled.
+    pub fn finish(&self, id: u64) -> bool {
+        match self.running.change(|jobs| jobs.remove(&id)) {
+            Some(job) => !job.cancel.is_cancelled(),
+            None => false,
+        }
+    }
+
+    /// Finish a job with its result, turning a success into
+    /// [`CANCELLED_ERROR`] if the job was cancelled in the meantime.
+    pub fn settle<T>(&self, id: u64, result: Result<T, String>) -> Result<T, String> {
+        let finished = self.finish(id);
+        match result {
+            Ok(_) if !finished => Err(CANCELLED_ERROR.to_string()),
+            result => result,
+        }
+    }
+
+    /// Cancel one job. Returns `false` if it was not running.
+    pub fn cancel(&self, id: u64) -> bool {
+        match self.running.change(|jobs| jobs.remove(&id)) {
+            Some(job) => {
+                job.cancel.cancel();
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Cancel every running job, returning how many were cancelled.
+    pub fn cancel_all(&self) -> usize {
+        self.running.change(|jobs| {
+            for job in jobs.values() {
+                job.cancel.cancel();
+            }
+            let count = jobs.len();
+            jobs.clear();
+            count
+        })
+    }
+
+    /// Cancel every running job of one kind, leaving the others alone.
+    /// Returns how many were cancelled.
+    pub fn cancel_kind(&self, kind: JobKind) -> usize {
+        self.running.change(|jobs| {
+            let before = jobs.len();
+            jobs.retain(|_, job| {
+                if job.kind != kind {
+                    return true;
+                }
+                job.cancel.cancel();
+                false
+            });
+            before - jobs.len()
+        })
+    }
+
+    pub fn running_ids(&self) -> Vec<u64> {
+        let mut ids: Vec<u64> = self.running.jobs.lock().unwrap().keys().copied().collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// Whether any enhancement is running. Comparisons and downloads don't
+    /// count.
+    pub fn is_enhancing(&self) -> bool {
+        *self.running.enhancing.borrow()
+    }
+
+    /// Follow [`JobRegistry::is_enhancing`]. The receiver sees a change when
+    /// the first enhancement starts and when the last one ends, however it
+    /// ends.
+    pub fn watch_enhancing(&self) -> watch::Receiver<bool> {
+        self.running.enhancing.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn dropped_job_leaves_the_registry() {
+        let jobs = JobRegistry::default();
+        let work = async {
+            let job = jobs.start(JobKind::Enhancement);
+            let result = job.cancel.run(std::future::pending::<Result<(), String>>()).await;
+            jobs.settle(job.id, result)
+        };
+
+        // Give up halfway, the way axum drops a handler whose client went away
+        assert!(tokio::time::timeout(Duration::from_millis(10), work).await.is_err());
+        assert!(jobs.running_ids().is_empty());
+        assert_eq!(jobs.cancel_all(), 0);
+    }
+
+    #[tokio::test]
+    async fn job_is_registered_until_settled() {
+        let jobs = JobRegistry::default();
+        let job = jobs.start(JobKind::Enhancement);
+        assert_eq!(jobs.running_ids(), vec![job.id]);
+        assert_eq!(jobs.settle(job.id, Ok(1)), Ok(1));
+        assert!(jobs.running_ids().is_empty());
+    }
+
+    #[tokio::test]
+    async fn enhancing_follows_the_first_and_last_enhancement() {
+        let jobs = JobRegistry::default();
+        let mut busy = jobs.watch_enhancing();
+        assert!(!*busy.borrow_and_update());
+
+        let first = jobs.start(JobKind::Enhancement);
+        let second = jobs.start(JobKind::Enhancement);
+        assert!(busy.has_changed().unwrap());
+        assert!(*busy.borrow_and_update());
+
+        jobs.finish(first.id);
+        assert!(!busy.has_changed().unwrap());
+
+        drop(second);
+        assert!(busy.has_changed().unwrap());
+        assert!(!*busy.borrow_and_update());
+    }
+
+    #[tokio::test]
+    async fn downloads_and_comparisons_are_not_enhancing() {
+        let jobs = JobRegistry::default();
+        let mut busy = jobs.watch_enhancing();
+        let pull = jobs.start(JobKind::Pull);
+        let comparison = jobs.start(JobKind::Comparison);
+        assert!(!busy.has_changed().unwrap());
+        assert!(!jobs.is_enhancing());
+        assert_eq!(jobs.running_ids(), vec![pull.id, comparison.id]);
+
+        let enhancement = jobs.start(JobKind::Enhancement);
+        assert!(jobs.is_enhancing());
+        drop(enhancement);
+        assert!(!jobs.is_enhancing());
+    }
+
+    #[tokio::test]
+    async fn cancelled_job_settles_as_cancelled() {
+        let jobs = JobRegistry::default();
+        let job = jobs.start(JobKind::Enhancement);
+        assert!(jobs.cancel(job.id));
+        assert_eq!(jobs.settle(job.id, Ok(1)), Err(CANCELLED_ERROR.to_string()));
+    }
+
+    #[tokio::test]
+    async fn cancelling_enhancements_spares_other_jobs() {
+        let jobs = JobRegistry::default();
+        let pull = jobs.start(JobKind::Pull);
+        let comparison = jobs.start(JobKind::Comparison);
+        let first = jobs.start(JobKind::Enhancement);
+        let second = jobs.start(JobKind::Enhancement);
+
+        assert_eq!(jobs.cancel_kind(JobKind::Enhancement), 2);
+        assert!(first.cancel.is_cancelled() && second.cancel.is_cancelled());
+        assert!(!pull.cancel.is_cancelled() && !comparison.cancel.is_cancelled());
+        assert!(!jobs.is_enhancing());
+        assert_eq!(jobs.running_ids(), vec![pull.id, comparison.id]);
+
+        assert_eq!(jobs.cancel_all(), 2);
+        assert!(pull.cancel.is_cancelled() && comparison.cancel.is_cancelled());
+    }
+}
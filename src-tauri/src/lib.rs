@@ -1,397 +1,1363 @@
-use tauri::Manager;
+use tauri::{Emitter, Manager};
 use tauri_plugin_notification::NotificationExt;
 use tauri::{menu::{Menu, MenuItem}, tray::TrayIconBuilder, WindowEvent};
-use serde::{Deserialize, Serialize};
 use std::process::Command;
 use std::path::PathBuf;
 use std::fs;
-use std::sync::Mutex;
-use reqwest;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use serde::Serialize;
 use anyhow::Result;
 use log::{info, error, debug};
-use tauri_plugin_clipboard_manager::ClipboardExt;
-
-const DEFAULT_SYSTEM_PROMPT: &str = r#"<system_prompt>
-YOU ARE A LOCAL PROMPT ENHANCER RUNNING ENTIRELY ON THE USER'S MACHINE.
-
-YOUR EXCLUSIVE MISSION IS TO READ THE USER'S RAW INPUT PROMPT AND REWRITE IT INTO A MORE DETAILED, CLEAR, AND WELL‑STRUCTURED PROMPT THAT ANOTHER AI ASSISTANT COULD DIRECTLY USE TO PRODUCE THE BEST POSSIBLE OUTPUT.
-
-### CORE BEHAVIORS ###
-- DETECT THE LANGUAGE OF THE INPUT AND OUTPUT IN THE SAME LANGUAGE.
-- ANALYZE THE COMPLEXITY OF THE USER'S INPUT:
-  • IF THE INPUT IS VERY SIMPLE OR SHORT (E.G., "CARBONARA RECIPE"), ENHANCE ONLY SLIGHTLY — KEEP THE OUTPUT BRIEF, CLEAR, AND STILL SIMPLE.
-  • IF THE INPUT IS MODERATELY DETAILED, EXPAND IT WITH ADDITIONAL CONTEXT AND PARAMETERS.
-  • IF THE INPUT IS COMPLEX OR AMBIGUOUS, ADD RICH DETAILS, RELEVANT CONSTRAINTS, AND CLARIFY THE INTENT AS MUCH AS POSSIBLE.
-- ALWAYS PRESERVE THE ORIGINAL INTENT AND MEANING.
-- OUTPUT ONLY THE ENHANCED PROMPT — NOTHING ELSE.
-
-### INSTRUCTIONS ###
-- NEVER ANSWER THE PROMPT OR GIVE TIPS.
-- NEVER ADD EXPLANATIONS, NOTES, OR COMMENTS.
-- ALWAYS PRODUCE ONE SINGLE PROMPT, NO BULLET LISTS OR MULTIPLE VERSIONS.
-- IF THE ORIGINAL IS VAGUE, INFER AND ADD REASONABLE CONTEXT.
-- IF THE ORIGINAL IS ALREADY DETAILED, IMPROVE STRUCTURE AND ADD MORE ACTIONABLE PARAMETERS.
-- AVOID OVERCOMPLICATING WHEN THE INPUT IS OBVIOUSLY SIMPLE AND SELF‑CONTAINED.
-
-### CHAIN OF THOUGHTS ###
-FOLLOW THESE STEPS INTERNALLY BEFORE PRODUCING OUTPUT:
-1. **UNDERSTAND**: READ the raw input and DETECT language and intent.
-2. **BASICS**: IDENTIFY subject, domain, and goal.
-3. **BREAK DOWN**: SPLIT the intent into sub‑tasks or aspects.
-4. **ANALYZE**: DETERMINE what extra details would meaningfully improve the prompt.
-5. **ADJUST COMPLEXITY**: MATCH output detail level to input complexity.
-6. **EDGE CASES**: CHECK for ambiguous or overly broad inputs and clarify carefully.
-7. **FINAL ANSWER**: OUTPUT ONLY the rewritten prompt in the same language.
-
-### WHAT NOT TO DO ###
-- DO NOT ANSWER THE USER'S ORIGINAL PROMPT.
-- DO NOT OUTPUT IN A DIFFERENT LANGUAGE THAN THE INPUT.
-- DO NOT SAY "THE USER WANTS…" OR "HERE IS YOUR PROMPT…".
-- DO NOT OUTPUT MULTIPLE PROMPTS OR EXPLANATIONS.
-- DO NOT OVEREXPAND A SIMPLE PROMPT INTO AN UNRELATED OR EXCESSIVE TASK.
-- DO NOT OMIT KEY DETAILS FROM THE USER'S INTENT.
-- DO NOT ADD IRRELEVANT CONTEXT.
-
-### FEW‑SHOT EXAMPLES ###
-
-**Example 1 (simple)**
-Input: `carbonara recipe`  
-Output: `Provide a simple, authentic carbonara recipe with a short list of key ingredients and clear step-by-step instructions.`
-
-**Example 2 (moderate)**
-Input: `improve my resume`  
-Output: `Revise and enhance my resume by highlighting my key achievements, quantifying results wherever possible, improving clarity and impact, and ensuring it is tailored to the target industry.`
-
-**Example 3 (complex)**
-Input: `how to improve A`  
-Output: `Explain how to improve A by integrating B and optimizing C parameters, while also considering D and F to ensure scalability, accuracy, and long-term maintainability.`
-
-**Example 4 (non‑English)**
-Input: `Consejos para cultivar tomates`  
-Output: `Proporciona consejos detallados y prácticos para cultivar tomates, incluyendo condiciones de suelo, riego, fertilización, control de plagas y cuidados estacionales.`
-
-</system_prompt>"#;
-
-#[derive(Debug, Serialize, Deserialize)]
-struct OllamaResponse {
+use tauri_plugin_global_shortcut::ShortcutState;
+
+mod backend;
+mod cli;
+mod clipboard;
+mod compare;
+#[cfg(target_os = "linux")]
+mod dbus;
+mod enhancer;
+mod history;
+mod hotkeys;
+mod http_api;
+mod input;
+mod ipc;
+mod jobs;
+mod models;
+mod presets;
+mod review;
+mod settings;
+mod targets;
+
+use backend::{normalize_ollama_url, BackendConfig, FewShotExample, GenerationOptions, ModelDetails, ModelInfo, ModelLoadingSettings, PromptMode, PullProgress};
+use clipboard::{ClipboardBackend, ClipboardSettings, ClipboardSource};
+use compare::{Comparison, ComparisonProgress};
+use enhancer::{ContextLengths, Enhancement, Enhancer, Variation, DEFAULT_SYSTEM_PROMPT};
+use history::{HistoryEntry, HistorySettings, HistoryStore, NewEntry};
+use hotkeys::{HotkeyAction, HotkeyStatus, HotkeyTarget};
+use http_api::{HttpApiServer, HttpApiSettings};
+use input::{AutoPasteSettings, InputBackend};
+use ipc::IpcCommand;
+use jobs::{Job, JobKind, JobRegistry, CANCELLED_ERROR};
+use models::{ModelChoice, ModelRule};
+use presets::Preset;
+use review::{PendingReview, ReviewSettings, ReviewState};
+use settings::Settings;
+use targets::{FallbackTarget, TargetHealth, TargetStatus, MAX_TARGETS};
+
+const TRAY_TOOLTIP: &str = "Clip Prompt - AI Text Enhancer";
+
+struct AppState {
+    /// Ollama address from `OLLAMA_HOST`, used when the settings don't name one
+    ollama_host: Option<String>,
+    settings: Mutex<Settings>,
+    /// Where settings are saved; `None` if the platform has no config dir
+    settings_path: Option<PathBuf>,
+    jobs: JobRegistry,
+    /// `None` if the history database couldn't be opened
+    history: Option<HistoryStore>,
+    /// Clipboard text from before the last hotkey enhancement, for undo
+    last_enhancement: Mutex<Option<ClipboardUndo>>,
+    /// The loopback HTTP API, while it is running
+    http_api: tokio::sync::Mutex<Option<HttpApiServer>>,
+    /// The hotkey enhancement shown in the review window, if any
+    review: Mutex<Option<PendingReview>>,
+    /// Recent failures of the primary server and the fallback targets
+    target_health: Arc<TargetHealth>,
+    /// Context lengths of the models enhancements have asked about
+    context_lengths: Arc<ContextLengths>,
+}
+
+/// What the clipboard held before and after a hotkey enhancement.
+struct ClipboardUndo {
+    original: String,
+    enhanced: String,
+}
+
+impl AppState {
+    /// Build the state from the settings file, so the tray app is fully
+    /// configured before any window has loaded.
+    fn load() -> Self {
+        let settings_path = settings::settings_path();
+        let settings = match &settings_path {
+            Some(path) => Settings::load(path),
+            None => {
+                error!("No config directory available, settings will not be saved");
+                Settings::default()
+            }
+        };
+
+        let ollama_host = enhancer::ollama_host_from_env();
+
+        Self {
+            ollama_host,
+            settings: Mutex::new(settings),
+            settings_path,
+            jobs: JobRegistry::default(),
+            history: open_history(),
+            last_enhancement: Mutex::new(None),
+            http_api: tokio::sync::Mutex::new(None),
+            review: Mutex::new(None),
+            target_health: Arc::default(),
+            context_lengths: Arc::default(),
+        }
+    }
+
+    /// Snapshot of the current settings.
+    fn settings(&self) -> Settings {
+        self.settings.lock().unwrap().clone()
+    }
+
+    /// Apply `update` to the settings and save them to disk. If saving fails
+    /// the settings stay as they were, so the app and the file agree.
+    fn update_settings<T>(&self, update: impl FnOnce(&mut Settings) -> T) -> Result<T, String> {
+        let mut settings = self.settings.lock().map_err(|e| {
+            error!("Failed to lock settings mutex: {}", e);
+            "Failed to update settings".to_string()
+        })?;
+
+        let mut updated = settings.clone();
+        let result = update(&mut updated);
+
+        if let Some(path) = &self.settings_path {
+            updated.save(path).map_err(|e| {
+                error!("Failed to save settings: {}", e);
+                e
+            })?;
+        }
+
+        *settings = updated;
+        Ok(result)
+    }
+
+    /// The enhancement pipeline for the current settings. Built per request,
+    /// so setting changes apply without a restart.
+    fn enhancer(&self) -> Enhancer {
+        Enhancer::new(self.settings(), self.ollama_host.clone(), self.target_health.clone(), self.context_lengths.clone())
+    }
+}
+
+fn open_history() -> Option<HistoryStore> {
+    let Some(path) = history::history_path() else {
+        error!("No data directory available, history is disabled");
+        return None;
+    };
+    match HistoryStore::open(&path) {
+        Ok(store) => Some(store),
+        Err(e) => {
+            error!("Failed to open history database {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Emitted when a streaming enhancement starts, so listeners can follow its job id.
+const ENHANCE_STARTED_EVENT: &str = "enhance-started";
+/// Emitted for every piece of text a streaming enhancement produces.
+const ENHANCE_DELTA_EVENT: &str = "enhance-delta";
+/// Emitted once with the full text when a streaming enhancement finishes.
+const ENHANCE_DONE_EVENT: &str = "enhance-done";
+/// Emitted when a streaming enhancement fails.
+const ENHANCE_ERROR_EVENT: &str = "enhance-error";
+/// Emitted when a streaming enhancement is cancelled.
+const ENHANCE_CANCELLED_EVENT: &str = "enhance-cancelled";
+
+#[derive(Clone, Serialize)]
+struct EnhanceStartedPayload {
+    job_id: u64,
+    /// Where the request came from: "ui", "hotkey", "ipc", "api" or "dbus"
+    source: &'static str,
+    /// Id of the preset being run, if any
+    preset: Option<String>,
+    input: String,
+}
+
+#[derive(Clone, Serialize)]
+struct EnhanceDeltaPayload {
+    job_id: u64,
+    delta: String,
+}
+
+#[derive(Clone, Serialize)]
+struct EnhanceDonePayload {
+    job_id: u64,
+    text: String,
+    model: String,
+    /// Which rule of the model resolution policy chose `model`
+    model_rule: ModelRule,
+    /// Label of the server that served the request
+    target: String,
+    /// Whether a fallback target served it
+    fallback: bool,
+}
+
+#[derive(Clone, Serialize)]
+struct EnhanceErrorPayload {
+    job_id: u64,
+    error: String,
+}
+
+#[derive(Clone, Serialize)]
+struct EnhanceCancelledPayload {
+    job_id: u64,
+}
+
+/// Emitted when a model download starts, so listeners can follow (and cancel) its job.
+const MODEL_PULL_STARTED_EVENT: &str = "model-pull-started";
+/// Emitted with the server's progress while a model downloads.
+const MODEL_PULL_PROGRESS_EVENT: &str = "model-pull-progress";
+/// Ollama reports download progress many times a second; listeners get at
+/// most one report per interval, plus every change of status.
+const MODEL_PULL_PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+#[derive(Clone, Serialize)]
+struct ModelPullStartedPayload {
+    job_id: u64,
     model: String,
-    created_at: String,
-    response: String,
-    done: bool,
-    done_reason: Option<String>,
-    context: Option<Vec<i32>>,
-    total_duration: Option<i64>,
-    load_duration: Option<i64>,
-    prompt_eval_count: Option<i32>,
-    prompt_eval_duration: Option<i64>,
-    eval_count: Option<i32>,
-    eval_duration: Option<i64>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct OllamaRequest {
+}
+
+#[derive(Clone, Serialize)]
+struct ModelPullProgressPayload {
+    job_id: u64,
     model: String,
+    #[serde(flatten)]
+    progress: PullProgress,
+}
+
+/// Emitted after an enhancement has been added to the history.
+const HISTORY_UPDATED_EVENT: &str = "history-updated";
+/// Emitted when the settings were changed from outside the window (over IPC).
+const SETTINGS_CHANGED_EVENT: &str = "settings-changed";
+
+/// Save a finished enhancement to the history (if enabled) and apply the
+/// retention limits.
+fn record_history(app_handle: &tauri::AppHandle, entry: NewEntry) {
+    let state = app_handle.state::<AppState>();
+    let Some(history) = &state.history else {
+        return;
+    };
+    let settings = state.settings().history;
+    if !settings.enabled {
+        return;
+    }
+
+    match history.add(&entry).and_then(|_| history.prune(&settings)) {
+        Ok(_) => {
+            let _ = app_handle.emit(HISTORY_UPDATED_EVENT, ());
+        },
+        Err(e) => error!("Failed to record enhancement in history: {}", e),
+    }
+}
+
+#[tauri::command]
+async fn enhance_prompt(prompt: String, model: Option<String>, preset: Option<String>, app_handle: tauri::AppHandle, state: tauri::State<'_, AppState>) -> Result<String, String> {
+    debug!("Enhance prompt called with: {}", prompt);
+    
+    let preset = preset.map(|id| state.enhancer().find_preset(&id)).transpose()?;
+    run_enhancement(&app_handle, "ui", &prompt, preset.as_ref(), model).await
+}
+
+/// Enhance `prompt` as a cancellable job and record it in the history,
+/// without streaming.
+async fn run_enhancement(app_handle: &tauri::AppHandle, source: &'static str, prompt: &str, preset: Option<&Preset>, model: Option<String>) -> Result<String, String> {
+    let state = app_handle.state::<AppState>();
+    let enhancer = state.enhancer();
+
+    let job = state.jobs.start(JobKind::Enhancement);
+    let result = job.cancel.run(enhancer.enhance(prompt, preset, model)).await;
+    let enhancement = state.jobs.settle(job.id, result)?;
+
+    record_history(app_handle, enhancement.history_entry(source, prompt, preset));
+    Ok(enhancement.generation.text)
+}
+
+/// One of several alternative enhancements of the same text.
+#[derive(Debug, Clone, Serialize)]
+struct Candidate {
+    /// The enhanced text; `None` if this candidate failed
+    text: Option<String>,
+    error: Option<String>,
+    model: String,
+    seed: Option<i64>,
+    temperature: Option<f32>,
+    duration_ms: u64,
+}
+
+impl Candidate {
+    fn new(enhancer: &Enhancer, preset: Option<&Preset>, variation: &Variation, result: Result<Enhancement, String>) -> Self {
+        match result {
+            Ok(enhancement) => Self {
+                text: Some(enhancement.generation.text),
+                error: None,
+                model: enhancement.model,
+                seed: enhancement.seed,
+                temperature: enhancement.temperature,
+                duration_ms: enhancement.duration_ms,
+            },
+            Err(e) => {
+                let resolved = enhancer.resolve_variation(preset, variation);
+                Self {
+                    text: None,
+                    error: Some(e),
+                    model: resolved.model,
+                    seed: resolved.options.seed,
+                    temperature: resolved.options.temperature,
+                    duration_ms: 0,
+                }
+            }
+        }
+    }
+}
+
+/// Enhance `prompt` once per variation, all at the same time. Each candidate
+/// is its own job, recorded in the history if it succeeds; one failing
+/// doesn't stop the others.
+async fn run_candidates(app_handle: &tauri::AppHandle, source: &'static str, prompt: &str, preset: Option<&Preset>, variations: Vec<Variation>) -> Vec<Candidate> {
+    let state = app_handle.state::<AppState>();
+    let enhancer = state.enhancer();
+    info!("Generating {} candidates", variations.len());
+
+    let tasks: Vec<_> = variations.into_iter().map(|variation| {
+        let app_handle = app_handle.clone();
+        let prompt = prompt.to_string();
+        let preset = preset.cloned();
+        tauri::async_runtime::spawn(async move {
+            let state = app_handle.state::<AppState>();
+            let enhancer = state.enhancer();
+            let job = state.jobs.start(JobKind::Enhancement);
+            let result = job.cancel.run(enhancer.enhance_variation(&prompt, preset.as_ref(), &variation, None)).await;
+            let result = state.jobs.settle(job.id, result).inspect(|enhancement| {
+                record_history(&app_handle, enhancement.history_entry(source, &prompt, preset.as_ref()));
+            });
+            (variation, result)
+        })
+    }).collect();
+
+    let mut candidates = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        let (variation, result) = task.await
+            .unwrap_or_else(|e| (Variation::default(), Err(format!("Candidate failed: {}", e))));
+        candidates.push(Candidate::new(&enhancer, preset, &variation, result));
+    }
+    candidates
+}
+
+/// Enhance `prompt` several ways at once and return every candidate, so the
+/// user can pick one. Either `count` candidates of the same model with
+/// different seeds, one per model in `models`, or exactly the given
+/// `variations`.
+#[tauri::command]
+async fn enhance_prompt_candidates(
+    prompt: String,
+    count: Option<usize>,
+    models: Option<Vec<String>>,
+    variations: Option<Vec<Variation>>,
+    preset: Option<String>,
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<Candidate>, String> {
+    debug!("Enhance prompt candidates called with: {}", prompt);
+
+    let preset = preset.map(|id| state.enhancer().find_preset(&id)).transpose()?;
+    let variations = match variations {
+        Some(variations) => enhancer::check_candidate_count(variations)?,
+        None => Variation::spread(count.unwrap_or(enhancer::DEFAULT_CANDIDATES), &models.unwrap_or_default())?,
+    };
+    Ok(run_candidates(&app_handle, "ui", &prompt, preset.as_ref(), variations).await)
+}
+
+/// Run `prompt` through each of `models` and collect their output, timings
+/// and token counts. Emits `compare-progress` as each model finishes. The
+/// comparison is one job, so `cancel_enhancement` stops it; it isn't
+/// recorded in the history.
+#[tauri::command]
+async fn compare_models(
     prompt: String,
-    stream: bool,
+    models: Vec<String>,
+    preset: Option<String>,
+    concurrency: Option<usize>,
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<Comparison, String> {
+    debug!("Compare models called with: {:?}", models);
+
+    if prompt.trim().is_empty() {
+        return Err("Nothing to compare, the input is empty".to_string());
+    }
+    let concurrency = concurrency.unwrap_or(1);
+    compare::validate(&models, concurrency)?;
+    let preset = preset.map(|id| state.enhancer().find_preset(&id)).transpose()?;
+    let enhancer = state.enhancer();
+
+    let job = state.jobs.start(JobKind::Comparison);
+    let comparison = compare::compare(&enhancer, &prompt, preset.as_ref(), models, concurrency, |index, result| {
+        let _ = app_handle.emit(compare::COMPARE_PROGRESS_EVENT, ComparisonProgress {
+            job_id: job.id,
+            index,
+            result: result.clone(),
+        });
+    });
+    let result = job.cancel.run(async { Ok(comparison.await) }).await;
+    state.jobs.settle(job.id, result)
+}
+
+/// A comparison as a Markdown report, for copying.
+#[tauri::command]
+async fn format_comparison_report(comparison: Comparison) -> Result<String, String> {
+    Ok(compare::to_markdown(&comparison))
+}
+
+/// Save a comparison as a Markdown report in the app's data directory and
+/// return the file's path.
+#[tauri::command]
+async fn save_comparison_report(comparison: Comparison) -> Result<String, String> {
+    compare::save_report(&comparison).map(|path| path.display().to_string())
+}
+
+/// The preset another program asked for by id or name, or the active preset
+/// if it didn't name one.
+fn requested_preset(state: &AppState, preset: Option<String>) -> Result<Option<Preset>, String> {
+    let enhancer = state.enhancer();
+    match preset {
+        Some(preset) => enhancer.find_preset(&preset).map(Some),
+        None => Ok(enhancer.active_preset()),
+    }
+}
+
+/// Stream an enhancement, emitting delta/done/error events to the webview.
+/// `on_delta` receives each piece of text and the number of characters
+/// generated so far.
+///
+/// The job is finished before this returns: an `Ok` result can no longer be
+/// cancelled, and a cancelled job always comes back as [`CANCELLED_ERROR`].
+async fn stream_enhancement(
+    app_handle: &tauri::AppHandle,
+    job: &Job,
+    source: &'static str,
+    prompt: &str,
+    preset: Option<&Preset>,
+    model: Option<String>,
+    on_delta: impl Fn(&str, usize) + Send + Sync,
+) -> Result<String, String> {
+    stream_variation(app_handle, job, source, prompt, preset, &Variation::model(model), on_delta).await
+        .map(|enhancement| enhancement.generation.text)
+}
+
+/// [`stream_enhancement`] for one candidate of several, returning the whole
+/// [`Enhancement`] so callers can report what produced it.
+async fn stream_variation(
+    app_handle: &tauri::AppHandle,
+    job: &Job,
+    source: &'static str,
+    prompt: &str,
+    preset: Option<&Preset>,
+    variation: &Variation,
+    on_delta: impl Fn(&str, usize) + Send + Sync,
+) -> Result<Enhancement, String> {
+    let job_id = job.id;
+    let _ = app_handle.emit(ENHANCE_STARTED_EVENT, EnhanceStartedPayload {
+        job_id,
+        source,
+        preset: preset.map(|p| p.id.clone()),
+        input: prompt.to_string(),
+    });
+
+    let state = app_handle.state::<AppState>();
+    let enhancer = state.enhancer();
+    debug!("Streaming enhancement {}", job_id);
+
+    let generated = AtomicUsize::new(0);
+    let emit_delta = |delta: &str| {
+        let _ = app_handle.emit(ENHANCE_DELTA_EVENT, EnhanceDeltaPayload {
+            job_id,
+            delta: delta.to_string(),
+        });
+        let count = delta.chars().count();
+        on_delta(delta, generated.fetch_add(count, Ordering::Relaxed) + count);
+    };
+
+    let result = job.cancel.run(enhancer.enhance_variation(prompt, preset, variation, Some(&emit_delta))).await;
+    let result = state.jobs.settle(job_id, result).inspect(|enhancement| {
+        record_history(app_handle, enhancement.history_entry(source, prompt, preset));
+    });
+
+    match &result {
+        Ok(enhancement) => {
+            let _ = app_handle.emit(ENHANCE_DONE_EVENT, EnhanceDonePayload {
+                job_id,
+                text: enhancement.generation.text.clone(),
+                model: enhancement.model.clone(),
+                model_rule: enhancement.model_rule,
+                target: enhancement.target.clone(),
+                fallback: enhancement.fallback,
+            });
+        },
+        Err(e) if e == CANCELLED_ERROR => {
+            debug!("Enhancement {} was cancelled", job_id);
+            let _ = app_handle.emit(ENHANCE_CANCELLED_EVENT, EnhanceCancelledPayload { job_id });
+        },
+        Err(e) => {
+            let _ = app_handle.emit(ENHANCE_ERROR_EVENT, EnhanceErrorPayload { job_id, error: e.clone() });
+        },
+    }
+
+    result
+}
+
+/// Streaming variant of `enhance_prompt`. Emits `enhance-*` events while the
+/// text is generated and returns the final text once it is complete.
+#[tauri::command]
+async fn enhance_prompt_stream(prompt: String, model: Option<String>, preset: Option<String>, app_handle: tauri::AppHandle) -> Result<String, String> {
+    debug!("Streaming enhance prompt called with: {}", prompt);
+
+    let state = app_handle.state::<AppState>();
+    let preset = preset.map(|id| state.enhancer().find_preset(&id)).transpose()?;
+    let job = state.jobs.start(JobKind::Enhancement);
+    stream_enhancement(&app_handle, &job, "ui", &prompt, preset.as_ref(), model, |_, _| {}).await
+}
+
+/// Cancel one running job, or every running enhancement when `job_id` is
+/// omitted. Returns how many jobs were cancelled.
+#[tauri::command]
+async fn cancel_enhancement(job_id: Option<u64>, state: tauri::State<'_, AppState>) -> Result<usize, String> {
+    let cancelled = match job_id {
+        Some(id) => usize::from(state.jobs.cancel(id)),
+        None => state.jobs.cancel_kind(JobKind::Enhancement),
+    };
+    info!("Cancelled {} running enhancement(s)", cancelled);
+    Ok(cancelled)
+}
+
+/// List the ids of the enhancements that are still running.
+#[tauri::command]
+async fn get_running_enhancements(state: tauri::State<'_, AppState>) -> Result<Vec<u64>, String> {
+    Ok(state.jobs.running_ids())
+}
+
+/// Save `shortcut` in the settings `field`, then move the OS binding to it.
+/// If the shortcut can't be registered the setting is put back, so the
+/// settings always name the shortcut the app actually holds.
+fn set_hotkey(app_handle: &tauri::AppHandle, state: &AppState, field: fn(&mut Settings) -> &mut Option<String>, shortcut: Option<String>) -> Result<(), String> {
+    let previous = state.update_settings(|settings| std::mem::replace(field(settings), shortcut.clone()))?;
+
+    if let Err(e) = hotkeys::rebind(app_handle, previous.as_deref(), shortcut.as_deref()) {
+        if let Err(e) = state.update_settings(|settings| *field(settings) = previous) {
+            error!("Failed to put back the previous hotkey: {}", e);
+        }
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Set (or clear, with `None`) the global shortcut that cancels running enhancements.
+#[tauri::command]
+async fn set_cancel_hotkey(shortcut: Option<String>, app_handle: tauri::AppHandle, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    let shortcut = hotkeys::normalize(shortcut);
+    debug!("Setting cancel hotkey to: {:?}", shortcut);
+
+    if let Some(shortcut) = &shortcut {
+        hotkeys::check_available(&state.settings(), &HotkeyTarget::Cancel, shortcut)?;
+    }
+    set_hotkey(&app_handle, &state, |settings| &mut settings.cancel_hotkey, shortcut)
 }
 
-struct AppState {
-    ollama_url: String,
-    model_name: Mutex<String>,
-    system_prompt: Mutex<String>,
-}
+/// Set (or clear, with `None`) the global shortcut that undoes the last enhancement.
+#[tauri::command]
+async fn set_undo_hotkey(shortcut: Option<String>, app_handle: tauri::AppHandle, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    let shortcut = hotkeys::normalize(shortcut);
+    debug!("Setting undo hotkey to: {:?}", shortcut);
+
+    if let Some(shortcut) = &shortcut {
+        hotkeys::check_available(&state.settings(), &HotkeyTarget::Undo, shortcut)?;
+    }
+    set_hotkey(&app_handle, &state, |settings| &mut settings.undo_hotkey, shortcut)
+}
+
+/// Set (or disable, with `None`) the global shortcut that enhances the clipboard.
+#[tauri::command]
+async fn set_enhance_hotkey(shortcut: Option<String>, app_handle: tauri::AppHandle, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    let shortcut = hotkeys::normalize(shortcut);
+    debug!("Setting enhance hotkey to: {:?}", shortcut);
+
+    if let Some(shortcut) = &shortcut {
+        hotkeys::check_available(&state.settings(), &HotkeyTarget::Enhance, shortcut)?;
+    }
+    set_hotkey(&app_handle, &state, |settings| &mut settings.enhance_hotkey, shortcut)
+}
+
+/// Check that an accelerator string parses, returning it in canonical form.
+#[tauri::command]
+async fn validate_hotkey(shortcut: String) -> Result<String, String> {
+    Ok(hotkeys::parse(shortcut.trim())?.to_string())
+}
+
+/// List the configured shortcuts and whether each is actually registered.
+#[tauri::command]
+async fn get_hotkey_status(app_handle: tauri::AppHandle, state: tauri::State<'_, AppState>) -> Result<Vec<HotkeyStatus>, String> {
+    Ok(hotkeys::status(&app_handle, &state.settings()))
+}
+
+/// Try again to register every configured shortcut, e.g. after the
+/// application that held one has quit.
+#[tauri::command]
+async fn register_hotkeys(app_handle: tauri::AppHandle, state: tauri::State<'_, AppState>) -> Result<Vec<HotkeyStatus>, String> {
+    let settings = state.settings();
+    hotkeys::register_all(&app_handle, &settings);
+    Ok(hotkeys::status(&app_handle, &settings))
+}
+
+/// Put the clipboard back to what it held before the last hotkey enhancement.
+/// Each enhancement can be undone once.
+async fn restore_original_clipboard(app_handle: &tauri::AppHandle) -> Result<(), String> {
+    let state = app_handle.state::<AppState>();
+    let Some((original, enhanced)) = state.last_enhancement.lock().unwrap().as_ref()
+        .map(|undo| (undo.original.clone(), undo.enhanced.clone()))
+    else {
+        return Err("Nothing to undo".to_string());
+    };
+
+    // Only worth a log line: the user asked for the original back either way
+    let clipboard_settings = state.settings().clipboard;
+    if clipboard::read_clipboard(app_handle, &clipboard_settings).await.is_ok_and(|current| current != enhanced) {
+        info!("Clipboard changed since the last enhancement, restoring the original anyway");
+    }
+
+    clipboard::write_clipboard(app_handle, &clipboard_settings, &original).await?;
+
+    // Unless a newer enhancement took its place meanwhile
+    let mut last = state.last_enhancement.lock().unwrap();
+    if last.as_ref().is_some_and(|undo| undo.original == original && undo.enhanced == enhanced) {
+        *last = None;
+    }
+
+    info!("Restored the clipboard from before the last enhancement");
+    Ok(())
+}
+
+/// Restore the clipboard from before the last hotkey enhancement, with a
+/// notification saying whether it worked. Used by the tray and the hotkey.
+fn undo_with_notification(app_handle: &tauri::AppHandle) {
+    let app_handle = app_handle.clone();
+    tauri::async_runtime::spawn(async move {
+        let body = match restore_original_clipboard(&app_handle).await {
+            Ok(()) => "↩️ Original text restored to the clipboard".to_string(),
+            Err(e) => format!("↩️ {}", e),
+        };
+        let _ = app_handle.notification()
+            .builder()
+            .title("Clip Prompt")
+            .body(body)
+            .show();
+    });
+}
+
+#[tauri::command]
+async fn undo_last_enhancement(app_handle: tauri::AppHandle) -> Result<(), String> {
+    restore_original_clipboard(&app_handle).await
+}
+
+/// Whether there is an enhancement to undo.
+#[tauri::command]
+async fn can_undo(state: tauri::State<'_, AppState>) -> Result<bool, String> {
+    Ok(state.last_enhancement.lock().unwrap().is_some())
+}
+
+#[tauri::command]
+async fn test_ollama_connection(state: tauri::State<'_, AppState>) -> Result<bool, String> {
+    state.enhancer().backend().test_connection().await
+}
+
+#[tauri::command]
+async fn get_available_models(state: tauri::State<'_, AppState>) -> Result<Vec<String>, String> {
+    state.enhancer().backend().list_models().await
+}
+
+/// The available models with what the server lists about each: size, family,
+/// parameter size and quantization.
+#[tauri::command]
+async fn get_model_info(state: tauri::State<'_, AppState>) -> Result<Vec<ModelInfo>, String> {
+    state.enhancer().backend().list_model_info().await
+}
+
+/// One model's details, including its context length, prompt template and
+/// capabilities.
+#[tauri::command]
+async fn get_model_details(model: String, state: tauri::State<'_, AppState>) -> Result<ModelDetails, String> {
+    state.enhancer().backend().show_model(&model).await
+}
+
+/// Download `model` onto the Ollama server, emitting `model-pull-*` events as
+/// it goes, and return the refreshed model list. The download is a job, so
+/// `cancel_enhancement` stops it; Ollama keeps what it already fetched.
+#[tauri::command]
+async fn pull_model(model: String, app_handle: tauri::AppHandle, state: tauri::State<'_, AppState>) -> Result<Vec<String>, String> {
+    let model = model.trim().to_string();
+    if model.is_empty() {
+        return Err("Enter the name of a model to download".to_string());
+    }
+    info!("Pulling model {}", model);
+
+    let backend = state.enhancer().backend();
+    let job = state.jobs.start(JobKind::Pull);
+    let _ = app_handle.emit(MODEL_PULL_STARTED_EVENT, ModelPullStartedPayload { job_id: job.id, model: model.clone() });
+
+    let last_report: Mutex<Option<(String, Instant)>> = Mutex::new(None);
+    let on_progress = |progress: &PullProgress| {
+        let mut last_report = last_report.lock().unwrap();
+        if let Some((status, at)) = last_report.as_ref() {
+            if *status == progress.status && at.elapsed() < MODEL_PULL_PROGRESS_INTERVAL {
+                return;
+            }
+        }
+        *last_report = Some((progress.status.clone(), Instant::now()));
+        let _ = app_handle.emit(MODEL_PULL_PROGRESS_EVENT, ModelPullProgressPayload {
+            job_id: job.id,
+            model: model.clone(),
+            progress: progress.clone(),
+        });
+    };
+
+    let result = job.cancel.run(backend.pull_model(&model, &on_progress)).await;
+    state.jobs.settle(job.id, result)?;
+    info!("Pulled model {}", model);
+    get_available_models(state).await
+}
+
+/// Remove `model` from the Ollama server and return the refreshed model list.
+#[tauri::command]
+async fn delete_model(model: String, state: tauri::State<'_, AppState>) -> Result<Vec<String>, String> {
+    info!("Deleting model {}", model);
+    state.enhancer().backend().delete_model(&model).await?;
+    get_available_models(state).await
+}
+
+#[tauri::command]
+async fn get_backend_config(state: tauri::State<'_, AppState>) -> Result<BackendConfig, String> {
+    Ok(state.settings().backend)
+}
+
+/// Save the backend configuration, returning it with the Ollama URL normalized.
+#[tauri::command]
+async fn update_backend_config(mut config: BackendConfig, state: tauri::State<'_, AppState>) -> Result<BackendConfig, String> {
+    debug!("Updating backend to: {:?}", config.kind);
+    
+    config.ollama_url = match config.ollama_url.trim() {
+        "" => "".to_string(),
+        url => normalize_ollama_url(url)?,
+    };
+    
+    state.update_settings(|settings| settings.backend = config.clone())?;
+    debug!("Backend updated successfully");
+    Ok(config)
+}
+
+/// The Ollama URL the app would fall back to with no URL in the settings
+/// (`OLLAMA_HOST` or the default), and the one actually in use.
+#[derive(Serialize)]
+struct OllamaUrlInfo {
+    fallback: String,
+    current: String,
+}
+
+#[tauri::command]
+async fn get_ollama_url(state: tauri::State<'_, AppState>) -> Result<OllamaUrlInfo, String> {
+    let enhancer = state.enhancer();
+    Ok(OllamaUrlInfo {
+        fallback: enhancer.fallback_ollama_url(),
+        current: enhancer.ollama_url(),
+    })
+}
+
+#[tauri::command]
+async fn enable_autostart(app_handle: tauri::AppHandle) -> Result<bool, String> {
+    debug!("Enabling autostart...");
+    
+    match std::env::consts::OS {
+        "macos" => enable_autostart_macos(&app_handle),
+        "windows" => enable_autostart_windows(&app_handle),
+        "linux" => enable_autostart_linux(&app_handle),
+        _ => Err("Unsupported operating system".to_string()),
+    }
+}
+
+#[tauri::command]
+async fn disable_autostart() -> Result<bool, String> {
+    debug!("Disabling autostart...");
+    
+    match std::env::consts::OS {
+        "macos" => disable_autostart_macos(),
+        "windows" => disable_autostart_windows(),
+        "linux" => disable_autostart_linux(),
+        _ => Err("Unsupported operating system".to_string()),
+    }
+}
+
+#[tauri::command]
+async fn is_autostart_enabled() -> Result<bool, String> {
+    debug!("Checking autostart status...");
+    
+    match std::env::consts::OS {
+        "macos" => is_autostart_enabled_macos(),
+        "windows" => is_autostart_enabled_windows(),
+        "linux" => is_autostart_enabled_linux(),
+        _ => Err("Unsupported operating system".to_string()),
+    }
+}
+
+#[tauri::command]
+async fn get_platform() -> Result<String, String> {
+    Ok(std::env::consts::OS.to_string())
+}
+
+#[tauri::command]
+async fn update_model(model: String, app_handle: tauri::AppHandle, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    debug!("Updating model to: {}", model);
+    
+    state.update_settings(|settings| settings.model = model.clone())?;
+    debug!("Model updated successfully to: {}", model);
+    preload_model(&app_handle);
+    Ok(())
+}
+
+/// Load the model the enhance hotkey would use in the background, if
+/// preloading is on, so the next enhancement doesn't wait for it.
+fn preload_model(app_handle: &tauri::AppHandle) {
+    let enhancer = app_handle.state::<AppState>().enhancer();
+    if !enhancer.settings().model_loading.preload {
+        return;
+    }
+    tauri::async_runtime::spawn(async move {
+        let started = Instant::now();
+        match enhancer.warm_up().await {
+            Ok(choice) => info!("Preloaded {} in {} ms", choice.model, started.elapsed().as_millis()),
+            Err(e) => error!("Failed to preload the model: {}", e),
+        }
+    });
+}
+
+#[tauri::command]
+async fn get_model_loading_settings(state: tauri::State<'_, AppState>) -> Result<ModelLoadingSettings, String> {
+    Ok(state.settings().model_loading)
+}
+
+/// Save the preload and keep-alive settings, preloading right away if that
+/// was just turned on.
+#[tauri::command]
+async fn update_model_loading_settings(settings: ModelLoadingSettings, app_handle: tauri::AppHandle, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    debug!("Updating model loading settings: {:?}", settings);
+
+    settings.validate()?;
+    state.update_settings(|s| s.model_loading = settings)?;
+    preload_model(&app_handle);
+    Ok(())
+}
+
+/// Unload every model the server holds in memory, to free RAM. Returns the
+/// names of the models unloaded.
+#[tauri::command]
+async fn unload_models(state: tauri::State<'_, AppState>) -> Result<Vec<String>, String> {
+    let unloaded = state.enhancer().backend().unload_models().await?;
+    info!("Unloaded {} model(s): {:?}", unloaded.len(), unloaded);
+    Ok(unloaded)
+}
+
+/// Unload the models from the tray and say how it went.
+fn unload_with_notification(app_handle: &tauri::AppHandle) {
+    let app_handle = app_handle.clone();
+    tauri::async_runtime::spawn(async move {
+        let body = match unload_models(app_handle.state::<AppState>()).await {
+            Ok(models) if models.is_empty() => "💤 No model was loaded".to_string(),
+            Ok(models) => format!("💤 Unloaded {}", models.join(", ")),
+            Err(e) => format!("❌ {}", e),
+        };
+        let _ = app_handle.notification()
+            .builder()
+            .title("Clip Prompt")
+            .body(body)
+            .show();
+    });
+}
+
+/// Choose a model with the resolution policy (see `Enhancer::choose_model`)
+/// and save it as the selected one. Returns the model and the rule that
+/// chose it.
+#[tauri::command]
+async fn set_initial_model(state: tauri::State<'_, AppState>) -> Result<ModelChoice, String> {
+    debug!("Setting initial model...");
+    
+    let choice = state.enhancer().choose_model(None, None).await.map_err(|e| {
+        error!("Failed to choose a model: {}", e);
+        e.to_string()
+    })?;
+    
+    state.update_settings(|settings| settings.model = choice.model.clone())?;
+    info!("Initial model set to {} ({})", choice.model, choice.rule);
+    Ok(choice)
+}
+
+/// The model an enhancement with `preset` (or none) would use right now, and
+/// the rule that chose it.
+#[tauri::command]
+async fn get_model_choice(preset: Option<String>, state: tauri::State<'_, AppState>) -> Result<ModelChoice, String> {
+    let enhancer = state.enhancer();
+    let preset = preset.map(|id| enhancer.find_preset(&id)).transpose()?;
+    Ok(enhancer.choose_model(preset.as_ref(), None).await?)
+}
+
+/// Save the models to fall back to, in order, when the selected one isn't
+/// installed. Returns the list as saved.
+#[tauri::command]
+async fn update_fallback_models(models: Vec<String>, state: tauri::State<'_, AppState>) -> Result<Vec<String>, String> {
+    let mut fallbacks: Vec<String> = Vec::new();
+    for model in models.iter().map(|m| m.trim()).filter(|m| !m.is_empty()) {
+        if !fallbacks.iter().any(|f| f == model) {
+            fallbacks.push(model.to_string());
+        }
+    }
+    debug!("Updating fallback models to: {:?}", fallbacks);
+    state.update_settings(|settings| settings.fallback_models = fallbacks.clone())?;
+    Ok(fallbacks)
+}
+
+/// The fallback targets as saved.
+#[tauri::command]
+async fn get_fallback_targets(state: tauri::State<'_, AppState>) -> Result<Vec<FallbackTarget>, String> {
+    Ok(state.settings().fallback_targets)
+}
+
+/// Save the servers and models to try, in order, when the selected backend
+/// fails. Returns them as saved, with Ollama URLs normalized.
+#[tauri::command]
+async fn update_fallback_targets(targets: Vec<FallbackTarget>, state: tauri::State<'_, AppState>) -> Result<Vec<FallbackTarget>, String> {
+    if targets.len() > MAX_TARGETS {
+        return Err(format!("Add at most {} fallback targets", MAX_TARGETS));
+    }
+    let targets = targets.into_iter().map(FallbackTarget::normalize).collect::<Result<Vec<_>, _>>()?;
+    debug!("Updating fallback targets: {} target(s)", targets.len());
+    state.update_settings(|settings| settings.fallback_targets = targets.clone())?;
+    Ok(targets)
+}
+
+/// How the primary server and each fallback target have been doing.
+#[tauri::command]
+async fn get_target_status(state: tauri::State<'_, AppState>) -> Result<Vec<TargetStatus>, String> {
+    Ok(state.enhancer().target_status())
+}
+
+#[tauri::command]
+async fn update_system_prompt(prompt: String, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    debug!("Updating system prompt...");
+    
+    state.update_settings(|settings| settings.system_prompt = prompt)?;
+    debug!("System prompt updated successfully");
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_system_prompt(state: tauri::State<'_, AppState>) -> Result<String, String> {
+    debug!("Getting system prompt...");
+    
+    let prompt = state.settings().system_prompt;
+    if prompt.is_empty() {
+        // Return default prompt if no custom prompt is set
+        debug!("No custom prompt set, returning default");
+        Ok(DEFAULT_SYSTEM_PROMPT.to_string())
+    } else {
+        debug!("Custom system prompt retrieved successfully");
+        Ok(prompt)
+    }
+}
+
+#[tauri::command]
+async fn reset_system_prompt(state: tauri::State<'_, AppState>) -> Result<(), String> {
+    debug!("Resetting system prompt to default...");
+    
+    // Empty string means use default
+    state.update_settings(|settings| settings.system_prompt = "".to_string())?;
+    debug!("System prompt reset to default successfully");
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_prompt_mode(state: tauri::State<'_, AppState>) -> Result<PromptMode, String> {
+    Ok(state.settings().prompt_mode)
+}
+
+#[tauri::command]
+async fn update_prompt_mode(mode: PromptMode, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    debug!("Updating prompt mode to: {:?}", mode);
+    
+    state.update_settings(|settings| settings.prompt_mode = mode)?;
+    debug!("Prompt mode updated successfully");
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_few_shot_examples(state: tauri::State<'_, AppState>) -> Result<Vec<FewShotExample>, String> {
+    Ok(state.settings().few_shot_examples)
+}
+
+#[tauri::command]
+async fn update_few_shot_examples(examples: Vec<FewShotExample>, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    debug!("Updating few-shot examples ({} examples)", examples.len());
+    
+    // Drop half-filled examples rather than sending empty turns to the model
+    let examples: Vec<FewShotExample> = examples.into_iter()
+        .filter(|e| !e.input.trim().is_empty() && !e.output.trim().is_empty())
+        .collect();
+    
+    state.update_settings(|settings| settings.few_shot_examples = examples)?;
+    debug!("Few-shot examples updated successfully");
+    Ok(())
+}
+
+/// Get the generation options for `model`, or for the current model when omitted.
+#[tauri::command]
+async fn get_generation_options(model: Option<String>, state: tauri::State<'_, AppState>) -> Result<GenerationOptions, String> {
+    let settings = state.settings();
+    let model = model.unwrap_or(settings.model);
+    debug!("Getting generation options for: {}", model);
+    
+    Ok(settings.generation_options.get(&model).cloned().unwrap_or_default())
+}
+
+#[tauri::command]
+async fn update_generation_options(model: String, options: GenerationOptions, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    debug!("Updating generation options for {}: {:?}", model, options);
+    
+    options.validate()?;
+    
+    state.update_settings(|settings| {
+        if options.is_empty() {
+            settings.generation_options.remove(&model);
+        } else {
+            settings.generation_options.insert(model, options);
+        }
+    })?;
+    debug!("Generation options updated successfully");
+    Ok(())
+}
+
+/// Get every persisted setting at once, for the settings window.
+#[tauri::command]
+async fn get_settings(state: tauri::State<'_, AppState>) -> Result<Settings, String> {
+    Ok(state.settings())
+}
+
+/// Import the settings the webview used to keep in localStorage. Values in
+/// `legacy` win over what is already in the settings file.
+#[tauri::command]
+async fn import_legacy_settings(legacy: serde_json::Map<String, serde_json::Value>, state: tauri::State<'_, AppState>) -> Result<Settings, String> {
+    info!("Importing {} legacy setting(s) from the webview", legacy.len());
+    
+    state.update_settings(|settings| {
+        *settings = settings.merge_document(legacy)?;
+        Ok(settings.clone())
+    })?
+}
+
+#[tauri::command]
+async fn get_presets(state: tauri::State<'_, AppState>) -> Result<Vec<Preset>, String> {
+    Ok(state.settings().presets)
+}
+
+/// Create or update a preset and (re)bind its hotkey. Returns the saved
+/// preset, with its id filled in if it is new.
+#[tauri::command]
+async fn save_preset(mut preset: Preset, app_handle: tauri::AppHandle, state: tauri::State<'_, AppState>) -> Result<Preset, String> {
+    debug!("Saving preset: {}", preset.name);
+    
+    preset.name = preset.name.trim().to_string();
+    preset.model = preset.model.trim().to_string();
+    preset.hotkey = hotkeys::normalize(preset.hotkey);
+    preset.validate()?;
+    
+    if let Some(hotkey) = &preset.hotkey {
+        hotkeys::check_available(&state.settings(), &HotkeyTarget::Preset(preset.id.clone()), hotkey)?;
+    }
+
+    let (saved, previous) = state.update_settings(|settings| {
+        let previous = presets::find(&settings.presets, &preset.id).cloned();
+        presets::upsert(&mut settings.presets, preset).map(|saved| (saved, previous))
+    })??;
 
-impl Default for AppState {
-    fn default() -> Self {
-        Self {
-            ollama_url: "http://localhost:11434".to_string(),
-            model_name: Mutex::new("".to_string()), // Will be set dynamically
-            system_prompt: Mutex::new("".to_string()), // Will be set dynamically
+    // Put the preset back as it was if its hotkey can't be registered, so
+    // the settings always name the shortcuts the app actually holds
+    let previous_hotkey = previous.as_ref().and_then(|p| p.hotkey.as_deref());
+    if let Err(e) = hotkeys::rebind(&app_handle, previous_hotkey, saved.hotkey.as_deref()) {
+        let rollback = state.update_settings(|settings| match previous {
+            Some(previous) => presets::upsert(&mut settings.presets, previous).map(|_| ()),
+            None => {
+                presets::remove(&mut settings.presets, &saved.id);
+                Ok(())
+            },
+        });
+        if let Err(e) = rollback.and_then(|result| result) {
+            error!("Failed to put back preset {}: {}", saved.id, e);
         }
+        return Err(e);
     }
+
+    info!("Preset {} ({}) saved", saved.name, saved.id);
+    Ok(saved)
 }
 
 #[tauri::command]
-async fn enhance_prompt(prompt: String, model: Option<String>, state: tauri::State<'_, AppState>) -> Result<String, String> {
-    debug!("Enhance prompt called with: {}", prompt);
+async fn delete_preset(id: String, app_handle: tauri::AppHandle, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    debug!("Deleting preset: {}", id);
     
-    // Get the system prompt from state or use default
-    let system_prompt = {
-        let custom_prompt = state.system_prompt.lock().unwrap().clone();
-        if custom_prompt.is_empty() {
-            DEFAULT_SYSTEM_PROMPT.to_string()
-        } else {
-            custom_prompt
-        }
-    };
-
-    let full_prompt = format!("{}\n\nUser input: {}\n\nEnhanced prompt:", system_prompt, prompt);
-
-    let model_to_use = model.unwrap_or_else(|| {
-        let current_model = state.model_name.lock().unwrap().clone();
-        if current_model.is_empty() {
-            // If no model is set, try to get the first available model
-            // This is a fallback for when the app starts without a model set
-            match std::panic::catch_unwind(|| {
-                // This is a bit of a hack, but we need to handle this case
-                // In a real app, you'd want to handle this more gracefully
-                "mistral:7b".to_string() // Fallback model
-            }) {
-                Ok(fallback_model) => fallback_model,
-                Err(_) => "mistral:7b".to_string() // Ultimate fallback
-            }
-        } else {
-            current_model
+    let removed = state.update_settings(|settings| {
+        if settings.active_preset.as_deref() == Some(id.as_str()) {
+            settings.active_preset = None;
         }
-    });
+        presets::remove(&mut settings.presets, &id)
+    })?
+        .ok_or_else(|| format!("Unknown preset: {}", id))?;
+    // The preset is gone either way, so a hotkey that won't let go is only
+    // worth a log line
+    if let Err(e) = hotkeys::rebind(&app_handle, removed.hotkey.as_deref(), None) {
+        error!("Failed to release the hotkey of preset {}: {}", removed.name, e);
+    }
     
-    let request = OllamaRequest {
-        model: model_to_use,
-        prompt: full_prompt,
-        stream: false,
-    };
+    info!("Preset {} deleted", removed.name);
+    Ok(())
+}
 
-    debug!("Sending request to Ollama: {}/api/generate", state.ollama_url);
-    
-    let client = reqwest::Client::new();
-    let response = client
-        .post(format!("{}/api/generate", state.ollama_url))
-        .json(&request)
-        .send()
-        .await
-        .map_err(|e| {
-            error!("Failed to send request to Ollama: {}", e);
-            format!("Failed to send request: {}", e)
-        })?;
+/// Choose the preset the enhance hotkey uses, or `None` for the system prompt.
+#[tauri::command]
+async fn set_active_preset(id: Option<String>, app_handle: tauri::AppHandle, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    debug!("Setting active preset to: {:?}", id);
 
-    if !response.status().is_success() {
-        error!("Ollama API returned error status: {}", response.status());
-        return Err(format!("Ollama API error: {}", response.status()));
+    if let Some(id) = &id {
+        state.enhancer().find_preset(id)?;
     }
+    state.update_settings(|settings| settings.active_preset = id)?;
+    // The preset may use another model
+    preload_model(&app_handle);
+    Ok(())
+}
 
-    let response_text = response.text().await.map_err(|e| {
-        error!("Failed to read response text: {}", e);
-        format!("Failed to read response: {}", e)
-    })?;
-    
-    debug!("Raw Ollama response: {}", response_text);
+/// Whether the HTTP API is on, where, and the token clients must send.
+#[derive(Serialize)]
+struct HttpApiStatus {
+    settings: HttpApiSettings,
+    running: bool,
+    /// e.g. `http://127.0.0.1:7879`, while running
+    url: Option<String>,
+    token: Option<String>,
+    token_path: Option<String>,
+}
 
-    let ollama_response: OllamaResponse = serde_json::from_str(&response_text)
-        .map_err(|e| {
-            error!("Failed to parse response: {}", e);
-            format!("Failed to parse response: {}", e)
-        })?;
+async fn http_api_status(state: &AppState) -> HttpApiStatus {
+    let server = state.http_api.lock().await;
+    HttpApiStatus {
+        settings: state.settings().http_api,
+        running: server.is_some(),
+        url: server.as_ref().map(|s| format!("http://127.0.0.1:{}", s.port)),
+        token: server.as_ref().map(|s| s.token.clone()),
+        token_path: http_api::token_path().map(|p| p.display().to_string()),
+    }
+}
 
-    debug!("Parsed Ollama response: {:?}", ollama_response);
+/// Stop the HTTP API, then start it again if it is enabled.
+async fn restart_http_api(app_handle: &tauri::AppHandle) -> Result<(), String> {
+    let state = app_handle.state::<AppState>();
+    let mut server = state.http_api.lock().await;
+    *server = None;
 
-    Ok(ollama_response.response)
+    let settings = state.settings().http_api;
+    if settings.enabled {
+        *server = Some(http_api::start(app_handle.clone(), settings.port).await?);
+    }
+    Ok(())
 }
 
 #[tauri::command]
-async fn test_ollama_connection(state: tauri::State<'_, AppState>) -> Result<bool, String> {
-    let client = reqwest::Client::new();
-    
-    debug!("Testing Ollama connection at: {}/api/tags", state.ollama_url);
-    
-    match client.get(&format!("{}/api/tags", state.ollama_url)).send().await {
-        Ok(response) => {
-            debug!("Connection test response status: {}", response.status());
-            Ok(true)
-        },
-        Err(e) => {
-            error!("Connection test failed: {}", e);
-            Err(format!("Connection failed: {}", e))
-        },
-    }
+async fn get_http_api_status(state: tauri::State<'_, AppState>) -> Result<HttpApiStatus, String> {
+    Ok(http_api_status(&state).await)
 }
 
+/// Save the HTTP API settings and start, move or stop the server to match.
 #[tauri::command]
-async fn get_available_models(state: tauri::State<'_, AppState>) -> Result<Vec<String>, String> {
-    let client = reqwest::Client::new();
-    
-    debug!("Getting available models from: {}/api/tags", state.ollama_url);
-    
-    let response = client
-        .get(&format!("{}/api/tags", state.ollama_url))
-        .send()
-        .await
-        .map_err(|e| format!("Failed to get models: {}", e))?;
+async fn update_http_api_settings(settings: HttpApiSettings, app_handle: tauri::AppHandle, state: tauri::State<'_, AppState>) -> Result<HttpApiStatus, String> {
+    debug!("Updating HTTP API settings: {:?}", settings);
 
-    debug!("Models response status: {}", response.status());
+    if settings.port == 0 {
+        return Err("Port must be between 1 and 65535".to_string());
+    }
+    let previous = state.update_settings(|s| std::mem::replace(&mut s.http_api, settings))?;
 
-    #[derive(Deserialize)]
-    struct ModelsResponse {
-        models: Vec<ModelInfo>,
+    // Keep the old settings if the new port can't be used
+    if let Err(e) = restart_http_api(&app_handle).await {
+        state.update_settings(|s| s.http_api = previous)?;
+        if let Err(e) = restart_http_api(&app_handle).await {
+            error!("Failed to restart HTTP API with the previous settings: {}", e);
+        }
+        return Err(e);
     }
+    Ok(http_api_status(&state).await)
+}
+
+/// Replace the API token, locking out clients that have the old one.
+#[tauri::command]
+async fn regenerate_http_api_token(app_handle: tauri::AppHandle, state: tauri::State<'_, AppState>) -> Result<HttpApiStatus, String> {
+    http_api::create_token()?;
+    restart_http_api(&app_handle).await?;
+    Ok(http_api_status(&state).await)
+}
+
+/// The clipboard settings and the backend `auto` currently resolves to.
+#[derive(Serialize)]
+struct ClipboardStatus {
+    settings: ClipboardSettings,
+    backend: ClipboardBackend,
+}
+
+#[tauri::command]
+async fn get_clipboard_settings(state: tauri::State<'_, AppState>) -> Result<ClipboardStatus, String> {
+    let settings = state.settings().clipboard;
+    Ok(ClipboardStatus { backend: clipboard::resolve_backend(&settings), settings })
+}
+
+#[tauri::command]
+async fn update_clipboard_settings(settings: ClipboardSettings, state: tauri::State<'_, AppState>) -> Result<ClipboardStatus, String> {
+    debug!("Updating clipboard settings: {:?}", settings);
 
-    #[derive(Debug, Deserialize)]
-    struct ModelInfo {
-        name: String,
+    if settings.source == ClipboardSource::Primary && !cfg!(target_os = "linux") {
+        return Err("Reading the selection is only supported on Linux".to_string());
     }
+    state.update_settings(|s| s.clipboard = settings.clone())?;
+    Ok(ClipboardStatus { backend: clipboard::resolve_backend(&settings), settings })
+}
 
-    let response_text = response.text().await
-        .map_err(|e| format!("Failed to get models response text: {}", e))?;
-    
-    debug!("Models response text: {}", response_text);
+/// The auto-paste settings and the keystroke tools found on this machine.
+#[derive(Serialize)]
+struct AutoPasteStatus {
+    settings: AutoPasteSettings,
+    available: Vec<InputBackend>,
+}
 
-    let models_response: ModelsResponse = serde_json::from_str(&response_text)
-        .map_err(|e| format!("Failed to parse models: {} (response: {})", e, response_text))?;
+#[tauri::command]
+async fn get_auto_paste_settings(state: tauri::State<'_, AppState>) -> Result<AutoPasteStatus, String> {
+    Ok(AutoPasteStatus { settings: state.settings().auto_paste, available: input::candidates(InputBackend::Auto) })
+}
 
-    debug!("Parsed models: {:?}", models_response.models);
+#[tauri::command]
+async fn update_auto_paste_settings(settings: AutoPasteSettings, state: tauri::State<'_, AppState>) -> Result<AutoPasteStatus, String> {
+    debug!("Updating auto-paste settings: {:?}", settings);
 
-    Ok(models_response.models.into_iter().map(|m| m.name).collect())
+    settings.validate()?;
+    state.update_settings(|s| s.auto_paste = settings.clone())?;
+    Ok(AutoPasteStatus { settings, available: input::candidates(InputBackend::Auto) })
 }
 
 #[tauri::command]
-async fn enable_autostart(app_handle: tauri::AppHandle) -> Result<bool, String> {
-    debug!("Enabling autostart...");
-    
-    match std::env::consts::OS {
-        "macos" => enable_autostart_macos(&app_handle),
-        "windows" => enable_autostart_windows(&app_handle),
-        "linux" => enable_autostart_linux(&app_handle),
-        _ => Err("Unsupported operating system".to_string()),
-    }
+async fn get_review_settings(state: tauri::State<'_, AppState>) -> Result<ReviewSettings, String> {
+    Ok(state.settings().review)
 }
 
 #[tauri::command]
-async fn disable_autostart() -> Result<bool, String> {
-    debug!("Disabling autostart...");
-    
-    match std::env::consts::OS {
-        "macos" => disable_autostart_macos(),
-        "windows" => disable_autostart_windows(),
-        "linux" => disable_autostart_linux(),
-        _ => Err("Unsupported operating system".to_string()),
-    }
+async fn update_review_settings(settings: ReviewSettings, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    debug!("Updating review settings: {:?}", settings);
+
+    settings.validate()?;
+    state.update_settings(|s| s.review = settings)
 }
 
+/// What the review window should show; `None` once the review is over.
 #[tauri::command]
-async fn is_autostart_enabled() -> Result<bool, String> {
-    debug!("Checking autostart status...");
-    
-    match std::env::consts::OS {
-        "macos" => is_autostart_enabled_macos(),
-        "windows" => is_autostart_enabled_windows(),
-        "linux" => is_autostart_enabled_linux(),
-        _ => Err("Unsupported operating system".to_string()),
-    }
+async fn get_review(app_handle: tauri::AppHandle) -> Result<Option<ReviewState>, String> {
+    Ok(review::current(&app_handle))
 }
 
+/// Accept the reviewed text, as edited in the review window from
+/// `candidate`.
 #[tauri::command]
-async fn get_platform() -> Result<String, String> {
-    Ok(std::env::consts::OS.to_string())
+async fn accept_review(text: String, candidate: usize, app_handle: tauri::AppHandle) -> Result<(), String> {
+    review::accept(&app_handle, candidate, text).await
 }
 
 #[tauri::command]
-async fn update_model(model: String, state: tauri::State<'_, AppState>) -> Result<(), String> {
-    debug!("Updating model to: {}", model);
-    
-    // Update the model in the app state
-    match state.model_name.lock() {
-        Ok(mut model_name) => {
-            *model_name = model.clone();
-            debug!("Model updated successfully to: {}", model);
-            Ok(())
-        },
-        Err(e) => {
-            error!("Failed to lock model_name mutex: {}", e);
-            Err("Failed to update model".to_string())
-        }
-    }
+async fn reject_review(app_handle: tauri::AppHandle) -> Result<(), String> {
+    review::reject(&app_handle);
+    Ok(())
 }
 
 #[tauri::command]
-async fn set_initial_model(state: tauri::State<'_, AppState>) -> Result<String, String> {
-    debug!("Setting initial model...");
-    
-    // Get available models
-    let models = match get_available_models(state.clone()).await {
-        Ok(models) => models,
-        Err(e) => {
-            error!("Failed to get available models: {}", e);
-            return Err("Failed to get available models".to_string());
-        }
-    };
-    
-    if models.is_empty() {
-        error!("No models available");
-        return Err("No models available".to_string());
-    }
-    
-    // Use the first available model
-    let first_model = models[0].clone();
-    debug!("Setting initial model to: {}", first_model);
-    
-    // Update the model in the app state
-    match state.model_name.lock() {
-        Ok(mut model_name) => {
-            *model_name = first_model.clone();
-            debug!("Initial model set successfully to: {}", first_model);
-            Ok(first_model)
-        },
-        Err(e) => {
-            error!("Failed to lock model_name mutex: {}", e);
-            Err("Failed to set initial model".to_string())
-        }
-    }
+async fn regenerate_review(app_handle: tauri::AppHandle) -> Result<(), String> {
+    review::regenerate(&app_handle)
+}
+
+fn history_store(state: &AppState) -> Result<&HistoryStore, String> {
+    state.history.as_ref().ok_or_else(|| "History is unavailable".to_string())
 }
 
+/// List history entries, pinned first and then newest first.
 #[tauri::command]
-async fn update_system_prompt(prompt: String, state: tauri::State<'_, AppState>) -> Result<(), String> {
-    debug!("Updating system prompt...");
-    
-    match state.system_prompt.lock() {
-        Ok(mut system_prompt) => {
-            *system_prompt = prompt.clone();
-            debug!("System prompt updated successfully");
-            Ok(())
-        },
-        Err(e) => {
-            error!("Failed to lock system_prompt mutex: {}", e);
-            Err("Failed to update system prompt".to_string())
-        }
+async fn get_history(limit: Option<u32>, offset: Option<u32>, state: tauri::State<'_, AppState>) -> Result<Vec<HistoryEntry>, String> {
+    history_store(&state)?.list(limit.unwrap_or(50), offset.unwrap_or(0))
+}
+
+/// Full-text search over the original and enhanced text of every entry.
+#[tauri::command]
+async fn search_history(query: String, limit: Option<u32>, state: tauri::State<'_, AppState>) -> Result<Vec<HistoryEntry>, String> {
+    debug!("Searching history for: {}", query);
+    history_store(&state)?.search(&query, limit.unwrap_or(50))
+}
+
+#[tauri::command]
+async fn delete_history_entry(id: i64, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    if !history_store(&state)?.delete(id)? {
+        return Err(format!("Unknown history entry: {}", id));
     }
+    Ok(())
 }
 
+/// Delete the whole history, keeping pinned entries unless `include_pinned`.
+/// Returns how many entries were deleted.
 #[tauri::command]
-async fn get_system_prompt(state: tauri::State<'_, AppState>) -> Result<String, String> {
-    debug!("Getting system prompt...");
-    
-    match state.system_prompt.lock() {
-        Ok(system_prompt) => {
-            let prompt = system_prompt.clone();
-            if prompt.is_empty() {
-                // Return default prompt if no custom prompt is set
-                debug!("No custom prompt set, returning default");
-                Ok(DEFAULT_SYSTEM_PROMPT.to_string())
-            } else {
-                debug!("Custom system prompt retrieved successfully");
-                Ok(prompt)
-            }
-        },
-        Err(e) => {
-            error!("Failed to lock system_prompt mutex: {}", e);
-            Err("Failed to get system prompt".to_string())
-        }
+async fn clear_history(include_pinned: Option<bool>, state: tauri::State<'_, AppState>) -> Result<usize, String> {
+    let deleted = history_store(&state)?.clear(include_pinned.unwrap_or(false))?;
+    info!("Cleared {} history entries", deleted);
+    Ok(deleted)
+}
+
+#[tauri::command]
+async fn set_history_pinned(id: i64, pinned: bool, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    if !history_store(&state)?.set_pinned(id, pinned)? {
+        return Err(format!("Unknown history entry: {}", id));
     }
+    Ok(())
 }
 
+/// Copy a history entry's enhanced text (or, with `original`, the text it was
+/// made from) back to the clipboard.
 #[tauri::command]
-async fn reset_system_prompt(state: tauri::State<'_, AppState>) -> Result<(), String> {
-    debug!("Resetting system prompt to default...");
+async fn copy_history_entry(id: i64, original: Option<bool>, app_handle: tauri::AppHandle, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    let entry = history_store(&state)?.get(id)?
+        .ok_or_else(|| format!("Unknown history entry: {}", id))?;
+    let text = if original.unwrap_or(false) { entry.original } else { entry.enhanced };
+
+    clipboard::write_clipboard(&app_handle, &state.settings().clipboard, &text).await
+}
+
+#[tauri::command]
+async fn get_history_settings(state: tauri::State<'_, AppState>) -> Result<HistorySettings, String> {
+    Ok(state.settings().history)
+}
+
+/// Save the history settings and apply the new retention limits right away.
+#[tauri::command]
+async fn update_history_settings(settings: HistorySettings, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    debug!("Updating history settings: {:?}", settings);
     
-    match state.system_prompt.lock() {
-        Ok(mut system_prompt) => {
-            *system_prompt = "".to_string(); // Empty string means use default
-            debug!("System prompt reset to default successfully");
-            Ok(())
-        },
-        Err(e) => {
-            error!("Failed to lock system_prompt mutex: {}", e);
-            Err("Failed to reset system prompt".to_string())
-        }
+    state.update_settings(|s| s.history = settings.clone())?;
+    if let Some(history) = &state.history {
+        history.prune(&settings)?;
     }
+    Ok(())
 }
 
 // macOS autostart implementation
@@ -441,7 +1407,7 @@ fn enable_autostart_macos(app_handle: &tauri::AppHandle) -> Result<bool, String>
     
     // Load the LaunchAgent
     let output = Command::new("launchctl")
-        .args(&["load", plist_path.to_string_lossy().as_ref()])
+        .args(["load", plist_path.to_string_lossy().as_ref()])
         .output()
         .map_err(|e| format!("Failed to load LaunchAgent: {}", e))?;
     
@@ -467,7 +1433,7 @@ fn disable_autostart_macos() -> Result<bool, String> {
     // Unload the LaunchAgent if it exists
     if plist_path.exists() {
         let _output = Command::new("launchctl")
-            .args(&["unload", plist_path.to_string_lossy().as_ref()])
+            .args(["unload", plist_path.to_string_lossy().as_ref()])
             .output()
             .map_err(|e| format!("Failed to unload LaunchAgent: {}", e))?;
         
@@ -509,7 +1475,7 @@ fn enable_autostart_windows(_app_handle: &tauri::AppHandle) -> Result<bool, Stri
     );
     
     let output = Command::new("powershell")
-        .args(&["-Command", &ps_command])
+        .args(["-Command", &ps_command])
         .output()
         .map_err(|e| format!("Failed to execute PowerShell command: {}", e))?;
     
@@ -527,7 +1493,7 @@ fn disable_autostart_windows() -> Result<bool, String> {
     let ps_command = r#"Remove-ItemProperty -Path "HKCU:\Software\Microsoft\Windows\CurrentVersion\Run" -Name "ClipPrompt" -Force"#;
     
     let _output = Command::new("powershell")
-        .args(&["-Command", ps_command])
+        .args(["-Command", ps_command])
         .output()
         .map_err(|e| format!("Failed to execute PowerShell command: {}", e))?;
     
@@ -540,7 +1506,7 @@ fn is_autostart_enabled_windows() -> Result<bool, String> {
     let ps_command = r#"Get-ItemProperty -Path "HKCU:\Software\Microsoft\Windows\CurrentVersion\Run" -Name "ClipPrompt" -ErrorAction SilentlyContinue"#;
     
     let output = Command::new("powershell")
-        .args(&["-Command", ps_command])
+        .args(["-Command", ps_command])
         .output()
         .map_err(|e| format!("Failed to execute PowerShell command: {}", e))?;
     
@@ -610,6 +1576,19 @@ fn is_autostart_enabled_linux() -> Result<bool, String> {
     Ok(desktop_file.exists())
 }
 
+/// Whether the command line (without the program name) asks for a headless
+/// command instead of the tray app.
+pub fn is_cli_command(args: &[String]) -> bool {
+    cli::is_command(args)
+}
+
+/// Run a headless command such as `clip-prompt enhance`, returning the
+/// process exit code.
+pub fn run_cli(args: Vec<String>) -> i32 {
+    env_logger::init();
+    cli::run(args)
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     env_logger::init();
@@ -621,58 +1600,32 @@ pub fn run() {
         .plugin(tauri_plugin_shell::init())
         .plugin(
             tauri_plugin_global_shortcut::Builder::new()
-                .with_shortcuts(["CommandOrControl+Shift+E"])
-                .expect("Failed to register global shortcuts")
                 .with_handler(|app, shortcut, event| {
-                    let shortcut_str = format!("{}", shortcut);
-                    
-                    // Check for multiple possible formats (Windows/Linux vs macOS)
-                    let is_target_hotkey = shortcut_str == "CommandOrControl+Shift+E" || 
-                                         shortcut_str == "shift+super+KeyE" ||
-                                         shortcut_str == "ctrl+shift+KeyE" ||
-                                         shortcut_str == "super+shift+KeyE";
-                    
-                    if is_target_hotkey && event.state == tauri_plugin_global_shortcut::ShortcutState::Pressed {
-                        println!("✅ HOTKEY ACTIVATED! Enhancing clipboard text...");
-                        info!("Global hotkey Ctrl+Shift+E pressed!");
-                        
-                        // Show "processing" notification
-                        let _ = app.notification()
-                            .builder()
-                            .title("Clip Prompt")
-                            .body("🤖 Enhancing your text...")
-                            .show();
-                        
-                        // Handle the hotkey press asynchronously
-                        let app_handle = app.clone();
-                        tauri::async_runtime::spawn(async move {
-                            let app_handle_clone = app_handle.clone();
-                            if let Err(e) = handle_global_hotkey(app_handle).await {
-                                println!("❌ Enhancement failed: {}", e);
-                                error!("Failed to handle global hotkey: {}", e);
-                                
-                                // Show error notification
-                                let _ = app_handle_clone.notification()
-                                    .builder()
-                                    .title("Clip Prompt")
-                                    .body(&format!("❌ Enhancement failed: {}", e))
-                                    .show();
-                            }
-                        });
+                    if event.state != ShortcutState::Pressed {
+                        return;
                     }
-                    // Only show debug info if needed
-                    else if event.state == tauri_plugin_global_shortcut::ShortcutState::Pressed {
-                        println!("🔍 DEBUG: Hotkey '{}' detected but not our target", shortcut_str);
+                    
+                    let action = hotkeys::action_for(&app.state::<AppState>().settings(), shortcut);
+                    match action {
+                        Some(HotkeyAction::Cancel) => {
+                            info!("Cancel hotkey pressed");
+                            app.state::<AppState>().jobs.cancel_kind(JobKind::Enhancement);
+                        },
+                        Some(HotkeyAction::Undo) => {
+                            info!("Undo hotkey pressed");
+                            undo_with_notification(app);
+                        },
+                        Some(HotkeyAction::Enhance(preset)) => {
+                            info!("Global hotkey {} pressed ({})", shortcut, preset.as_ref().map_or("default prompt", |p| p.name.as_str()));
+                            enhance_clipboard_in_background(app, preset);
+                        },
+                        None => {},
                     }
                 })
                 .build()
         )
-        .manage(AppState {
-            ollama_url: "http://localhost:11434".to_string(),
-            model_name: Mutex::new("".to_string()), // Will be set dynamically
-            system_prompt: Mutex::new("".to_string()), // Will be set dynamically
-        })
-        .invoke_handler(tauri::generate_handler![enhance_prompt, test_ollama_connection, get_available_models, enable_autostart, disable_autostart, is_autostart_enabled, get_platform, update_model, set_initial_model, update_system_prompt, get_system_prompt, reset_system_prompt])
+        .manage(AppState::load())
+        .invoke_handler(tauri::generate_handler![enhance_prompt, test_ollama_connection, get_available_models, enable_autostart, disable_autostart, is_autostart_enabled, get_platform, update_model, set_initial_model, update_system_prompt, get_system_prompt, reset_system_prompt, get_backend_config, update_backend_config, enhance_prompt_stream, cancel_enhancement, get_running_enhancements, set_cancel_hotkey, get_prompt_mode, update_prompt_mode, get_few_shot_examples, update_few_shot_examples, get_generation_options, update_generation_options, get_settings, import_legacy_settings, get_ollama_url, get_presets, save_preset, delete_preset, set_enhance_hotkey, validate_hotkey, get_hotkey_status, register_hotkeys, set_undo_hotkey, undo_last_enhancement, can_undo, get_history, search_history, delete_history_entry, clear_history, set_history_pinned, copy_history_entry, get_history_settings, update_history_settings, set_active_preset, get_http_api_status, update_http_api_settings, regenerate_http_api_token, get_clipboard_settings, update_clipboard_settings, get_auto_paste_settings, update_auto_paste_settings, get_review_settings, update_review_settings, get_review, accept_review, reject_review, regenerate_review, enhance_prompt_candidates, compare_models, format_comparison_report, save_comparison_report, pull_model, delete_model, get_model_info, get_model_details, get_model_choice, update_fallback_models, get_model_loading_settings, update_model_loading_settings, unload_models, get_fallback_targets, update_fallback_targets, get_target_status])
         .setup(|app| {
             println!("🚀 Setting up Clip Prompt...");
             info!("Clip Prompt started successfully");
@@ -681,19 +1634,26 @@ pub fn run() {
             // Create system tray menu
             let quit_i = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?;
             let show_i = MenuItem::with_id(app, "show", "Show Window", true, None::<&str>)?;
-            let menu = Menu::with_items(app, &[&show_i, &quit_i])?;
+            let cancel_i = MenuItem::with_id(app, "cancel", "Cancel Enhancement", true, None::<&str>)?;
+            let undo_i = MenuItem::with_id(app, "undo", "Undo Last Enhancement", true, None::<&str>)?;
+            let unload_i = MenuItem::with_id(app, "unload", "Unload Model", true, None::<&str>)?;
+            let menu = Menu::with_items(app, &[&show_i, &cancel_i, &undo_i, &unload_i, &quit_i])?;
 
             // Create system tray
             let _ = TrayIconBuilder::with_id("main")
                 .menu(&menu)
                 .icon(app.default_window_icon().unwrap().clone())
-                .tooltip("Clip Prompt - AI Text Enhancer")
+                .tooltip(TRAY_TOOLTIP)
                 .on_tray_icon_event(|_tray, _event| {
                     // Left-click on tray icon does nothing - only show window via menu
                 })
                 .on_menu_event(|app, event| {
                     match event.id.as_ref() {
                         "quit" => {
+                            // Downloads and comparisons go too, so none is left
+                            // reporting progress while the app shuts down
+                            let cancelled = app.state::<AppState>().jobs.cancel_all();
+                            info!("Cancelled {} running job(s) before quitting", cancelled);
                             app.exit(0);
                         }
                         "show" => {
@@ -702,6 +1662,16 @@ pub fn run() {
                                 let _ = window.set_focus();
                             }
                         }
+                        "cancel" => {
+                            let cancelled = app.state::<AppState>().jobs.cancel_kind(JobKind::Enhancement);
+                            info!("Cancelled {} enhancement(s) from tray", cancelled);
+                        }
+                        "undo" => {
+                            undo_with_notification(app);
+                        }
+                        "unload" => {
+                            unload_with_notification(app);
+                        }
                         _ => {}
                     }
                 })
@@ -709,8 +1679,25 @@ pub fn run() {
 
             println!("✅ System tray created successfully");
             println!("📋 Clipboard integration enabled");
-            println!("🎯 Ready! Press Cmd+Shift+E (or Ctrl+Shift+E) anywhere to enhance text");
-            info!("Global hotkey CommandOrControl+Shift+E registered successfully");
+
+            // Register the saved hotkeys; the UI reports any that failed
+            let settings = app.state::<AppState>().settings();
+            hotkeys::register_all(app.handle(), &settings);
+            match &settings.enhance_hotkey {
+                Some(hotkey) => println!("🎯 Ready! Press {} anywhere to enhance text", hotkey),
+                None => println!("🎯 Ready! (enhance hotkey disabled)"),
+            }
+
+            // Let editor plugins and scripts talk to this instance
+            tauri::async_runtime::spawn(ipc::serve(app.handle().clone()));
+            #[cfg(target_os = "linux")]
+            tauri::async_runtime::spawn(dbus::serve(app.handle().clone()));
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                if let Err(e) = restart_http_api(&app_handle).await {
+                    error!("Failed to start HTTP API: {}", e);
+                }
+            });
 
             // Test Ollama connection on startup
             let app_handle = app.handle().clone();
@@ -723,115 +1710,383 @@ pub fn run() {
                 } else {
                     println!("✅ Successfully connected to Ollama");
                     info!("Successfully connected to Ollama on startup");
+                    
+                    // The hotkey works without the window ever opening, so
+                    // pick a model here if none has been saved yet
+                    let state = app_handle.state::<AppState>();
+                    if state.settings().model.is_empty() {
+                        if let Err(e) = set_initial_model(state).await {
+                            error!("Failed to set initial model: {}", e);
+                        }
+                    }
+                    preload_model(&app_handle);
                 }
             });
 
             Ok(())
         })
         .on_window_event(|window, event| {
-            match event {
-                WindowEvent::CloseRequested { api, .. } => {
-                    // Hide window instead of closing
-                    window.hide().unwrap();
-                    api.prevent_close();
-                }
-                _ => {}
+            if let WindowEvent::CloseRequested { api, .. } = event {
+                // Hide window instead of closing
+                window.hide().unwrap();
+                api.prevent_close();
             }
         })
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
 
-async fn handle_global_hotkey(app_handle: tauri::AppHandle) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    println!("📋 Reading clipboard...");
-    info!("Processing global hotkey - reading clipboard...");
+#[derive(Serialize)]
+struct PresetSummary {
+    id: String,
+    name: String,
+}
+
+/// Answer to the IPC `status` command.
+#[derive(Serialize)]
+struct IpcStatus {
+    version: &'static str,
+    model: String,
+    /// The active preset, if any
+    preset: Option<PresetSummary>,
+    presets: Vec<PresetSummary>,
+    backend: &'static str,
+    /// Ids of the enhancements currently running
+    running: Vec<u64>,
+}
+
+/// Carry out a request from another program (see `ipc.rs`).
+async fn handle_ipc_command(app_handle: &tauri::AppHandle, command: IpcCommand) -> Result<serde_json::Value, String> {
+    let state = app_handle.state::<AppState>();
+    let summary = |p: &Preset| PresetSummary { id: p.id.clone(), name: p.name.clone() };
+
+    match command {
+        IpcCommand::Enhance { text, preset, model } => {
+            if text.trim().is_empty() {
+                return Err("Nothing to enhance, the text is empty".to_string());
+            }
+            let preset = requested_preset(&state, preset)?;
+            let job = state.jobs.start(JobKind::Enhancement);
+            let text = stream_enhancement(app_handle, &job, "ipc", &text, preset.as_ref(), model, |_, _| {}).await?;
+            Ok(serde_json::json!({ "text": text }))
+        },
+        IpcCommand::SetModel { model } => {
+            let model = model.trim().to_string();
+            if model.is_empty() {
+                return Err("Model name must not be empty".to_string());
+            }
+            info!("Model set to {} over IPC", model);
+            state.update_settings(|settings| settings.model = model.clone())?;
+            preload_model(app_handle);
+            let _ = app_handle.emit(SETTINGS_CHANGED_EVENT, ());
+            Ok(serde_json::json!({ "model": model }))
+        },
+        IpcCommand::SetPreset { preset } => {
+            let preset = preset.map(|p| state.enhancer().find_preset(&p)).transpose()?;
+            info!("Active preset set to {:?} over IPC", preset.as_ref().map(|p| &p.name));
+            state.update_settings(|settings| settings.active_preset = preset.as_ref().map(|p| p.id.clone()))?;
+            preload_model(app_handle);
+            let _ = app_handle.emit(SETTINGS_CHANGED_EVENT, ());
+            Ok(serde_json::json!({ "preset": preset.as_ref().map(summary) }))
+        },
+        IpcCommand::Status => {
+            let enhancer = state.enhancer();
+            let status = IpcStatus {
+                version: env!("CARGO_PKG_VERSION"),
+                model: enhancer.settings().model.clone(),
+                preset: enhancer.active_preset().as_ref().map(summary),
+                presets: enhancer.settings().presets.iter().map(summary).collect(),
+                backend: enhancer.backend().name(),
+                running: state.jobs.running_ids(),
+            };
+            serde_json::to_value(status).map_err(|e| format!("Failed to serialize status: {}", e))
+        },
+    }
+}
+
+/// Enhance the clipboard the way the enhance hotkey does, with a
+/// notification when it starts and another if it fails. Returns immediately.
+fn enhance_clipboard_in_background(app: &tauri::AppHandle, preset: Option<Preset>) {
+    // Show "processing" notification
+    let body = match &preset {
+        Some(preset) => format!("🤖 {}...", preset.name),
+        None => "🤖 Enhancing your text...".to_string(),
+    };
+    let _ = app.notification()
+        .builder()
+        .title("Clip Prompt")
+        .body(body)
+        .show();
+
+    // Handle the hotkey press asynchronously
+    let app_handle = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let app_handle_clone = app_handle.clone();
+        if let Err(e) = handle_global_hotkey(app_handle, preset).await {
+            error!("Failed to handle global hotkey: {}", e);
+
+            // Show error notification
+            let _ = app_handle_clone.notification()
+                .builder()
+                .title("Clip Prompt")
+                .body(format!("❌ Enhancement failed: {}", e))
+                .show();
+        }
+    });
+}
+
+/// Auto-paste mode: press the copy shortcut in the focused app and return
+/// what it copied. The clipboard is cleared first so an empty result means
+/// nothing was selected, rather than the previous clipboard.
+async fn copy_selection(app_handle: &tauri::AppHandle, clipboard_settings: &ClipboardSettings, auto_paste: &AutoPasteSettings) -> Result<String, String> {
+    // Give the user time to release the hotkey, or its modifiers mix in
+    tokio::time::sleep(auto_paste.delay()).await;
+    clipboard::write_clipboard(app_handle, clipboard_settings, "").await?;
+    input::send_copy(auto_paste).await?;
+    tokio::time::sleep(auto_paste.delay()).await;
+    clipboard::read_clipboard(app_handle, clipboard_settings).await
+}
+
+/// Where a hotkey enhancement hands its result back to, and what to put
+/// back on the clipboard afterwards.
+#[derive(Clone)]
+struct ClipboardTarget {
+    clipboard: ClipboardSettings,
+    auto_paste: AutoPasteSettings,
+    /// The clipboard from before auto-paste borrowed it; `None` when it held
+    /// no text (e.g. an image or files), which writing back would destroy
+    saved_clipboard: Option<String>,
+    /// The clipboard text to put back on undo
+    previous_clipboard: String,
+}
+
+impl ClipboardTarget {
+    /// Give back the clipboard auto-paste borrowed, if the settings say so.
+    async fn restore(&self, app_handle: &tauri::AppHandle) {
+        if !self.auto_paste.enabled || !self.auto_paste.restore_clipboard {
+            return;
+        }
+        let Some(saved_clipboard) = &self.saved_clipboard else {
+            debug!("The clipboard held no text before auto-paste, leaving it as is");
+            return;
+        };
+        if let Err(e) = clipboard::write_clipboard(app_handle, &self.clipboard, saved_clipboard).await {
+            error!("Failed to restore the clipboard: {}", e);
+        }
+    }
+}
+
+/// Put the enhanced text on the clipboard (pasting it over the selection in
+/// auto-paste mode), keep what's needed for undo and tell the user, saying
+/// which target served the request if `served_by` is given.
+async fn deliver_enhancement(app_handle: &tauri::AppHandle, target: ClipboardTarget, enhanced_text: String, served_by: Option<String>) -> Result<(), String> {
+    info!("Text enhanced successfully, writing {} chars back to the clipboard...", enhanced_text.chars().count());
+    
+    // Write enhanced text back to clipboard
+    if let Err(e) = clipboard::write_clipboard(app_handle, &target.clipboard, &enhanced_text).await {
+        error!("Failed to write enhanced text to clipboard: {}", e);
+        target.restore(app_handle).await;
+        return Err(e);
+    }
+    
+    // Replace the selection, then hand the clipboard back once the
+    // focused app has had time to paste from it
+    let auto_paste = &target.auto_paste;
+    let pasted = if auto_paste.enabled {
+        match input::send_paste(auto_paste).await {
+            Ok(()) => {
+                tokio::time::sleep(auto_paste.delay()).await;
+                target.restore(app_handle).await;
+                true
+            },
+            Err(e) => {
+                error!("Failed to paste the enhanced text: {}", e);
+                false
+            }
+        }
+    } else {
+        false
+    };
+    
+    // Keep the original so the enhancement can be undone, unless the
+    // clipboard no longer holds the enhanced text
+    let state = app_handle.state::<AppState>();
+    let restored = pasted && auto_paste.restore_clipboard;
+    if !restored {
+        *state.last_enhancement.lock().unwrap() = Some(ClipboardUndo {
+            original: target.previous_clipboard,
+            enhanced: enhanced_text,
+        });
+    }
+    
+    let body = if pasted {
+        info!("Enhanced text pasted over the selection");
+        format!("✅ Text enhanced and pasted! Press {} in the app to undo", shortcut("Z"))
+    } else {
+        info!("Enhanced text written to clipboard successfully");
+        
+        // Desktop notifications can't carry action buttons, so point
+        // at the undo hotkey or the tray instead
+        let undo_hint = match state.settings().undo_hotkey {
+            Some(hotkey) => format!("{} to undo", hotkey),
+            None => "undo from the tray menu".to_string(),
+        };
+        if auto_paste.enabled {
+            format!("⚠️ Couldn't paste automatically. Press {} to paste, or {}", shortcut("V"), undo_hint)
+        } else {
+            format!("✅ Text enhanced! Press {} to paste, or {}", shortcut("V"), undo_hint)
+        }
+    };
+    let body = match served_by {
+        Some(served_by) => format!("{}\n{}", body, served_by),
+        None => body,
+    };
+    
+    // Show "success" notification
+    let _ = app_handle.notification()
+        .builder()
+        .title("Clip Prompt")
+        .body(body)
+        .show();
+    
+    Ok(())
+}
+
+/// The copy, paste or undo shortcut for `key` the way this platform writes
+/// it: Cmd on macOS, Ctrl elsewhere.
+fn shortcut(key: &str) -> String {
+    let modifier = if cfg!(target_os = "macos") { "Cmd" } else { "Ctrl" };
+    format!("{}+{}", modifier, key)
+}
+
+async fn handle_global_hotkey(app_handle: tauri::AppHandle, preset: Option<Preset>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let settings = app_handle.state::<AppState>().settings();
+    let clipboard_settings = settings.clipboard;
+    let auto_paste = settings.auto_paste;
+    let copy_hint = match clipboard_settings.source {
+        _ if auto_paste.enabled => "📋 Please select some text first, then try again".to_string(),
+        ClipboardSource::Clipboard => format!("📋 Please copy some text first ({}), then try again", shortcut("C")),
+        ClipboardSource::Primary => "📋 Please select some text first, then try again".to_string(),
+    };
+    
+    // In auto-paste mode the clipboard is borrowed for the copy and the
+    // paste, so remember what to put back afterwards
+    let mut target = ClipboardTarget {
+        saved_clipboard: if auto_paste.enabled {
+            clipboard::read_clipboard(&app_handle, &clipboard_settings).await.ok()
+                .filter(|text| !text.is_empty())
+        } else {
+            None
+        },
+        previous_clipboard: String::new(),
+        clipboard: clipboard_settings.clone(),
+        auto_paste: auto_paste.clone(),
+    };
     
-    // Read current clipboard content
-    let clipboard_text = match app_handle.clipboard().read_text() {
+    // Read current clipboard content (or the selection)
+    let read_result = if auto_paste.enabled {
+        info!("Processing global hotkey - copying the selection...");
+        copy_selection(&app_handle, &clipboard_settings, &auto_paste).await
+    } else {
+        info!("Processing global hotkey - reading clipboard...");
+        clipboard::read_source(&app_handle, &clipboard_settings).await
+    };
+    let clipboard_text = match read_result {
         Ok(text) => {
-            println!("📄 Found {} characters: '{}'", text.len(), text.chars().take(50).collect::<String>());
-            info!("Clipboard content read: {} characters", text.len());
+            info!("Clipboard content read: {} characters", text.chars().count());
+            debug!("Clipboard starts with '{}'", text.chars().take(50).collect::<String>());
             text
         },
         Err(e) => {
-            println!("❌ Failed to read clipboard: {}", e);
             error!("Failed to read clipboard: {}", e);
+            target.restore(&app_handle).await;
             
             // Show helpful notification about what to do
             let _ = app_handle.notification()
                 .builder()
                 .title("Clip Prompt")
-                .body("📋 Please copy some text first (Cmd+C), then try again")
+                .body(match clipboard_settings.source {
+                    ClipboardSource::Clipboard if !auto_paste.enabled => copy_hint,
+                    _ => format!("📋 {}", e),
+                })
                 .show();
             
-            return Err(format!("Failed to read clipboard: {}", e).into());
+            return Err(e.into());
         }
     };
 
     // Skip if clipboard is empty or too short
     if clipboard_text.trim().is_empty() {
-        println!("⚠️  Clipboard is empty - please copy some text first");
         info!("Clipboard content is empty or whitespace only");
+        target.restore(&app_handle).await;
         
         // Show "empty clipboard" notification with helpful instructions
         let _ = app_handle.notification()
             .builder()
             .title("Clip Prompt")
-            .body("📋 Please copy some text first (Cmd+C), then try again")
+            .body(copy_hint)
             .show();
         
         return Ok(());
     }
+    
+    // The clipboard to put back on undo; with the selection as the source
+    // that's whatever was copied before, not the selected text
+    target.previous_clipboard = match clipboard_settings.source {
+        _ if auto_paste.enabled => target.saved_clipboard.clone().unwrap_or_default(),
+        ClipboardSource::Clipboard => clipboard_text.clone(),
+        ClipboardSource::Primary => clipboard::read_clipboard(&app_handle, &clipboard_settings).await.unwrap_or_default(),
+    };
 
-    println!("🤖 Enhancing clipboard text...");
     info!("Enhancing clipboard text...");
     
     // Get app state
     let state = app_handle.state::<AppState>();
     
-    // Check if we have a model set
-    let current_model = state.model_name.lock().unwrap().clone();
-    if current_model.is_empty() {
-        println!("❌ No model available for enhancement");
-        info!("No model available for enhancement");
-        
-        // Show error notification
-        let _ = app_handle.notification()
-            .builder()
-            .title("Clip Prompt")
-            .body("❌ No AI model available. Please check your Ollama installation.")
-            .show();
-        
+    // With review on, the review window takes it from here and only
+    // touches the clipboard once the user accepts
+    if settings.review.enabled {
+        review::start(&app_handle, clipboard_text, preset, target)?;
         return Ok(());
     }
     
-    // Enhance the prompt (use current model for global hotkey)
-    match enhance_prompt(clipboard_text, Some(current_model), state).await {
-        Ok(enhanced_text) => {
-            println!("✨ Enhanced! Writing {} chars to clipboard...", enhanced_text.len());
-            info!("Text enhanced successfully, writing back to clipboard...");
-            
-            // Write enhanced text back to clipboard
-            if let Err(e) = app_handle.clipboard().write_text(enhanced_text) {
-                println!("❌ Failed to write to clipboard: {}", e);
-                error!("Failed to write enhanced text to clipboard: {}", e);
-                return Err(format!("Failed to write to clipboard: {}", e).into());
-            }
-            
-            println!("🎉 Done! Press Cmd+V to paste your enhanced text");
-            info!("Enhanced text written to clipboard successfully");
+    // Enhance the prompt, showing progress in the tray tooltip while the
+    // text streams in. The model and target are left to the enhancer so it
+    // can fall back to another target, and the done event reports the rule
+    // that chose the model.
+    let job = state.jobs.start(JobKind::Enhancement);
+    let tray = app_handle.tray_by_id("main");
+    let result = stream_variation(&app_handle, &job, "hotkey", &clipboard_text, preset.as_ref(), &Variation::default(), |_, chars| {
+        if let Some(tray) = &tray {
+            let _ = tray.set_tooltip(Some(format!("Clip Prompt - Enhancing... ({} chars)", chars)));
+        }
+    }).await;
+    if let Some(tray) = &tray {
+        let _ = tray.set_tooltip(Some(TRAY_TOOLTIP));
+    }
+
+    // A cancelled job never reaches the clipboard: stream_enhancement has
+    // already finished the job, so an Ok result can't be cancelled any more
+    match result {
+        Ok(enhancement) => {
+            let served_by = enhancement.served_by();
+            info!("{}", served_by);
+            deliver_enhancement(&app_handle, target, enhancement.generation.text, Some(served_by)).await?
+        },
+        Err(e) if e == CANCELLED_ERROR => {
+            info!("Enhancement cancelled, clipboard left untouched");
+            target.restore(&app_handle).await;
             
-            // Show "success" notification
             let _ = app_handle.notification()
                 .builder()
                 .title("Clip Prompt")
-                .body("✅ Text enhanced! Press Cmd+V to paste")
+                .body("⏹️ Enhancement cancelled")
                 .show();
         },
         Err(e) => {
-            println!("❌ Enhancement failed: {}", e);
             error!("Failed to enhance text: {}", e);
-            return Err(format!("Failed to enhance text: {}", e).into());
+            target.restore(&app_handle).await;
+            return Err(e.into());
         }
     }
     
@@ -2,5 +2,9 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if clip_prompt_lib::is_cli_command(&args) {
+        std::process::exit(clip_prompt_lib::run_cli(args));
+    }
     clip_prompt_lib::run()
 }
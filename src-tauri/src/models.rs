@@ -0,0 +1,315 @@
+use log::debug;
+use serde::Serialize;
+use std::fmt;
+use std::sync::OnceLock;
+use tokio::process::Command;
+
+use crate::backend::ModelInfo;
+use crate::clipboard::hide_console;
+
+/// Which rule of the model resolution policy picked the model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelRule {
+    /// The request named the model
+    Requested,
+    /// The preset names its own model
+    Preset,
+    /// The model chosen in the settings
+    Preferred,
+    /// The first installed model of the fallback list
+    Fallback,
+    /// The best installed model by [`best_installed`]
+    Ranked,
+    /// The model a fallback target names
+    Target,
+}
+
+impl fmt::Display for ModelRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Requested => "requested",
+            Self::Preset => "preset's model",
+            Self::Preferred => "preferred model",
+            Self::Fallback => "fallback list",
+            Self::Ranked => "best installed model",
+            Self::Target => "fallback target's model",
+        })
+    }
+}
+
+/// A model and the rule that chose it.
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelChoice {
+    pub model: String,
+    pub rule: ModelRule,
+}
+
+impl ModelChoice {
+    fn new(model: impl Into<String>, rule: ModelRule) -> Self {
+        Self { model: model.into(), rule }
+    }
+}
+
+/// Why no model could be chosen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModelError {
+    /// The server has no model that can enhance text
+    NoModels,
+    /// The server couldn't be asked which models it has, and no model is
+    /// configured to try anyway
+    Unavailable(String),
+}
+
+impl fmt::Display for ModelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoModels => f.write_str("No AI model is installed. Download one in the Clip Prompt window or with `ollama pull`"),
+            Self::Unavailable(e) => write!(f, "Couldn't find out which models are installed: {}", e),
+        }
+    }
+}
+
+impl From<ModelError> for String {
+    fn from(error: ModelError) -> Self {
+        error.to_string()
+    }
+}
+
+/// Whether `configured` names the installed model `installed`. Ollama adds
+/// `:latest` to names given without a tag.
+fn same_model(configured: &str, installed: &str) -> bool {
+    let configured = configured.trim();
+    configured == installed || (!configured.contains(':') && installed.strip_suffix(":latest") == Some(configured))
+}
+
+/// Pick a model from the installed ones: the preferred model if it is
+/// installed, else the first installed model of `fallbacks`, else the best
+/// installed model for a system with `memory` bytes.
+pub fn choose(preferred: &str, fallbacks: &[String], installed: &[ModelInfo], memory: Option<u64>) -> Result<ModelChoice, ModelError> {
+    let find = |name: &str| installed.iter().find(|info| same_model(name, &info.name));
+
+    if !preferred.trim().is_empty() {
+        if let Some(info) = find(preferred) {
+            return Ok(ModelChoice::new(&info.name, ModelRule::Preferred));
+        }
+        debug!("Preferred model {} isn't installed", preferred);
+    }
+    if let Some(info) = fallbacks.iter().find_map(|name| find(name)) {
+        return Ok(ModelChoice::new(&info.name, ModelRule::Fallback));
+    }
+    best_installed(installed, memory)
+        .map(|info| ModelChoice::new(&info.name, ModelRule::Ranked))
+        .ok_or(ModelError::NoModels)
+}
+
+/// The installed model best suited to enhancing text: one that can generate
+/// text at all, then one that fits in memory, then an instruction-tuned one,
+/// then the biggest.
+pub fn best_installed(installed: &[ModelInfo], memory: Option<u64>) -> Option<&ModelInfo> {
+    installed.iter()
+        .filter_map(|info| rank(info, memory).map(|rank| (rank, info)))
+        // Ties go to the first in alphabetical order
+        .max_by(|(a, a_info), (b, b_info)| a.cmp(b).then_with(|| b_info.name.cmp(&a_info.name)))
+        .map(|(_, info)| info)
+}
+
+/// How good a default `info` is, compared field by field; `None` for models
+/// that can't generate text. Parameter counts and sizes on disk aren't
+/// comparable, so models that report a parameter count beat those that
+/// don't, and the size on disk only breaks ties.
+fn rank(info: &ModelInfo, memory: Option<u64>) -> Option<(bool, u8, bool, u64, u64)> {
+    let name = info.name.to_lowercase();
+    let family = info.family.as_deref().unwrap_or_default().to_lowercase();
+    if name.contains("embed") || name.starts_with("all-minilm") || family.contains("bert") {
+        return None;
+    }
+
+    // Leave room for the system and the context window; unknown sizes get
+    // the benefit of the doubt
+    let fits = match (info.size, memory) {
+        (Some(size), Some(memory)) => size <= memory / 3 * 2,
+        _ => true,
+    };
+
+    // Ollama's default tags are instruction-tuned; base models say so in the tag
+    let tag = name.split_once(':').map_or("", |(_, tag)| tag);
+    let instruct = if ["text", "base"].iter().any(|marker| tag.contains(marker) || name.ends_with(&format!("-{}", marker))) {
+        0
+    } else if ["instruct", "chat", "-it"].iter().any(|marker| name.contains(marker)) {
+        2
+    } else {
+        1
+    };
+
+    let parameters = info.parameter_size.as_deref().and_then(parse_parameters);
+    Some((fits, instruct, parameters.is_some(), parameters.unwrap_or(0), info.size.unwrap_or(0)))
+}
+
+/// Parse a parameter count like `7.2B` or `500M`.
+fn parse_parameters(size: &str) -> Option<u64> {
+    let size = size.trim().to_uppercase();
+    let (number, scale) = match size.chars().last()? {
+        'B' => (&size[..size.len() - 1], 1e9),
+        'M' => (&size[..size.len() - 1], 1e6),
+        'K' => (&size[..size.len() - 1], 1e3),
+        _ => (size.as_str(), 1.0),
+    };
+    number.parse::<f64>().ok().map(|n| (n * scale) as u64)
+}
+
+/// Total physical memory in bytes, if the system says. Asked once, as it
+/// can take a helper process.
+pub async fn total_memory() -> Option<u64> {
+    static TOTAL_MEMORY: OnceLock<Option<u64>> = OnceLock::new();
+
+    if let Some(memory) = TOTAL_MEMORY.get() {
+        return *memory;
+    }
+    let memory = read_total_memory().await;
+    debug!("Total memory: {:?} bytes", memory);
+    *TOTAL_MEMORY.get_or_init(|| memory)
+}
+
+async fn read_total_memory() -> Option<u64> {
+    if cfg!(target_os = "linux") {
+        let meminfo = tokio::fs::read_to_string("/proc/meminfo").await.ok()?;
+        let line = meminfo.lines().find(|line| line.starts_with("MemTotal:"))?;
+        let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+        Some(kib * 1024)
+    } else if cfg!(target_os = "macos") {
+        command_output("sysctl", &["-n", "hw.memsize"]).await?.parse().ok()
+    } else if cfg!(target_os = "windows") {
+        command_output("powershell", &["-NoProfile", "-Command", "(Get-CimInstance Win32_ComputerSystem).TotalPhysicalMemory"]).await?.parse().ok()
+    } else {
+        None
+    }
+}
+
+async fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let mut command = Command::new(program);
+    command.args(args);
+    let output = hide_console(&mut command).output().await.ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GB: u64 = 1_000_000_000;
+
+    fn model(name: &str, parameter_size: Option<&str>, size: Option<u64>) -> ModelInfo {
+        ModelInfo {
+            name: name.to_string(),
+            parameter_size: parameter_size.map(str::to_string),
+            size,
+            ..ModelInfo::default()
+        }
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn same_model_adds_latest_to_untagged_names() {
+        assert!(same_model("llama3.2", "llama3.2:latest"));
+        assert!(same_model(" llama3.2:3b ", "llama3.2:3b"));
+        assert!(!same_model("llama3.2:3b", "llama3.2:latest"));
+        assert!(!same_model("llama3.2", "llama3.2:3b"));
+        assert!(!same_model("llama3", "llama3.2:latest"));
+    }
+
+    #[test]
+    fn parse_parameters_reads_suffixes() {
+        assert_eq!(parse_parameters("7.2B"), Some(7_200_000_000));
+        assert_eq!(parse_parameters("500M"), Some(500_000_000));
+        assert_eq!(parse_parameters(" 33k "), Some(33_000));
+        assert_eq!(parse_parameters("1234"), Some(1234));
+        assert_eq!(parse_parameters("large"), None);
+        assert_eq!(parse_parameters(""), None);
+    }
+
+    #[test]
+    fn choose_prefers_the_preferred_model() {
+        let installed = [model("mistral:latest", None, None), model("llama3.2:latest", None, None)];
+
+        let choice = choose("llama3.2", &names(&["mistral"]), &installed, None).unwrap();
+
+        assert_eq!(choice.model, "llama3.2:latest");
+        assert_eq!(choice.rule, ModelRule::Preferred);
+    }
+
+    #[test]
+    fn choose_falls_back_in_order() {
+        let installed = [
+            model("qwen2.5:7b", Some("7.6B"), None),
+            model("mistral:latest", Some("7.2B"), None),
+            model("gemma2:2b", Some("2.6B"), None),
+        ];
+
+        let choice = choose("llama3.2", &names(&["phi3", "gemma2:2b", "mistral"]), &installed, None).unwrap();
+        assert_eq!(choice.model, "gemma2:2b");
+        assert_eq!(choice.rule, ModelRule::Fallback);
+
+        let choice = choose("llama3.2", &names(&["phi3"]), &installed, None).unwrap();
+        assert_eq!(choice.model, "qwen2.5:7b");
+        assert_eq!(choice.rule, ModelRule::Ranked);
+    }
+
+    #[test]
+    fn choose_fails_without_text_models() {
+        let installed = [model("nomic-embed-text:latest", None, None), model("all-minilm:latest", None, None)];
+
+        assert_eq!(choose("", &[], &installed, None).unwrap_err(), ModelError::NoModels);
+        assert_eq!(choose("", &[], &[], None).unwrap_err(), ModelError::NoModels);
+    }
+
+    #[test]
+    fn best_installed_skips_embedding_models() {
+        let mut bert = model("granite:latest", Some("0.3B"), None);
+        bert.family = Some("nomic-bert".to_string());
+        let installed = [
+            model("mxbai-embed-large:latest", Some("334M"), None),
+            bert,
+            model("llama3.2:1b", Some("1.2B"), None),
+        ];
+
+        assert_eq!(best_installed(&installed, None).unwrap().name, "llama3.2:1b");
+    }
+
+    #[test]
+    fn best_installed_prefers_instruction_tuned_models() {
+        let installed = [
+            model("llama3.1:70b-text-q4_0", Some("70.6B"), None),
+            model("llama3.2:3b", Some("3.2B"), None),
+            model("phi3:3.8b-mini-instruct", Some("3.8B"), None),
+        ];
+
+        assert_eq!(best_installed(&installed, None).unwrap().name, "phi3:3.8b-mini-instruct");
+        assert_eq!(best_installed(&installed[..2], None).unwrap().name, "llama3.2:3b");
+    }
+
+    #[test]
+    fn best_installed_prefers_models_that_fit_in_memory() {
+        let installed = [
+            model("llama3.1:70b", Some("70.6B"), Some(40 * GB)),
+            model("llama3.1:8b", Some("8.0B"), Some(5 * GB)),
+        ];
+
+        assert_eq!(best_installed(&installed, Some(16 * GB)).unwrap().name, "llama3.1:8b");
+        assert_eq!(best_installed(&installed, Some(64 * GB)).unwrap().name, "llama3.1:70b");
+        assert_eq!(best_installed(&installed, None).unwrap().name, "llama3.1:70b");
+    }
+
+    #[test]
+    fn best_installed_ranks_parameter_counts_above_sizes() {
+        let installed = [
+            model("custom:latest", None, Some(20 * GB)),
+            model("llama3.2:3b", Some("3.2B"), Some(2 * GB)),
+        ];
+
+        assert_eq!(best_installed(&installed, None).unwrap().name, "llama3.2:3b");
+    }
+}
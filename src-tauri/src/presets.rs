@@ -0,0 +1,99 @@
+use serde::{Deserialize, Serialize};
+
+use crate::backend::GenerationOptions;
+
+/// A named way of rewriting text ("Fix grammar", "Commit message", ...) that
+/// can be run from its own global shortcut.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preset {
+    /// Assigned when the preset is first saved
+    pub id: String,
+    pub name: String,
+    pub system_prompt: String,
+    /// Model to use; empty means the currently selected model
+    pub model: String,
+    /// Used instead of the model's own generation options when not empty
+    pub options: GenerationOptions,
+    /// Global shortcut that runs this preset on the clipboard
+    pub hotkey: Option<String>,
+}
+
+impl Preset {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("Preset name must not be empty".to_string());
+        }
+        if self.system_prompt.trim().is_empty() {
+            return Err(format!("Preset '{}' needs a system prompt", self.name));
+        }
+        self.options.validate()
+    }
+}
+
+pub fn find<'a>(presets: &'a [Preset], id: &str) -> Option<&'a Preset> {
+    presets.iter().find(|p| p.id == id)
+}
+
+/// Add `preset`, or replace the saved preset with the same id. A preset
+/// without an id is new and gets one; an id that isn't saved is an error.
+/// Returns the saved preset.
+pub fn upsert(presets: &mut Vec<Preset>, mut preset: Preset) -> Result<Preset, String> {
+    if preset.id.is_empty() {
+        let next = presets.iter()
+            .filter_map(|p| p.id.parse::<u64>().ok())
+            .max()
+            .unwrap_or(0) + 1;
+        preset.id = next.to_string();
+        presets.push(preset.clone());
+        return Ok(preset);
+    }
+
+    let existing = presets.iter_mut().find(|p| p.id == preset.id)
+        .ok_or_else(|| format!("Unknown preset: {}", preset.id))?;
+    *existing = preset.clone();
+    Ok(preset)
+}
+
+/// Remove a preset, returning it if it existed.
+pub fn remove(presets: &mut Vec<Preset>, id: &str) -> Option<Preset> {
+    let index = presets.iter().position(|p| p.id == id)?;
+    Some(presets.remove(index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preset(id: &str, name: &str) -> Preset {
+        Preset { id: id.to_string(), name: name.to_string(), system_prompt: "Fix the grammar.".to_string(), ..Preset::default() }
+    }
+
+    #[test]
+    fn upsert_numbers_new_presets() {
+        let mut presets = vec![preset("3", "Grammar"), preset("custom", "Custom")];
+
+        let saved = upsert(&mut presets, preset("", "Commit message")).unwrap();
+
+        assert_eq!(saved.id, "4");
+        assert_eq!(find(&presets, "4").map(|p| p.name.as_str()), Some("Commit message"));
+    }
+
+    #[test]
+    fn upsert_replaces_a_saved_preset() {
+        let mut presets = vec![preset("1", "Grammar")];
+
+        upsert(&mut presets, preset("1", "Spelling")).unwrap();
+
+        assert_eq!(presets.len(), 1);
+        assert_eq!(presets[0].name, "Spelling");
+    }
+
+    #[test]
+    fn upsert_rejects_unknown_ids() {
+        let mut presets = vec![preset("1", "Grammar")];
+
+        assert_eq!(upsert(&mut presets, preset("7", "Spelling")).unwrap_err(), "Unknown preset: 7");
+        assert_eq!(presets.len(), 1);
+    }
+}
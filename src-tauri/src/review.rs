@@ -0,0 +1,318 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use tauri::{Emitter, Manager};
+
+use crate::enhancer::{Variation, MAX_CANDIDATES};
+use crate::jobs::{JobKind, CANCELLED_ERROR};
+use crate::presets::Preset;
+use crate::{AppState, ClipboardTarget};
+
+pub const REVIEW_WINDOW: &str = "review";
+/// Sent to the review window with the whole [`ReviewState`] whenever it
+/// changes, so a window that opens late still shows everything.
+pub const REVIEW_UPDATED_EVENT: &str = "review-updated";
+
+/// Numbers each set of candidates, so a generation spawned for one set
+/// can't attach itself to the set that replaced it.
+static NEXT_SERIAL: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReviewSettings {
+    /// Show hotkey results in the review window before they reach the
+    /// clipboard
+    pub enabled: bool,
+    /// How many alternatives to generate at once, with different seeds
+    pub candidates: usize,
+    /// Generate one alternative per model instead; overrides `candidates`
+    pub models: Vec<String>,
+}
+
+impl Default for ReviewSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            candidates: 1,
+            models: Vec::new(),
+        }
+    }
+}
+
+impl ReviewSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        self.variations().map(|_| ())
+    }
+
+    fn variations(&self) -> Result<Vec<Variation>, String> {
+        if self.candidates > MAX_CANDIDATES {
+            return Err(format!("Ask for between 1 and {} candidates", MAX_CANDIDATES));
+        }
+        Variation::spread(self.candidates.max(1), &self.models)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReviewStatus {
+    Generating,
+    Ready,
+    Failed,
+    Cancelled,
+}
+
+/// One alternative in the review window.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReviewCandidate {
+    /// The text so far while generating
+    pub enhanced: String,
+    pub status: ReviewStatus,
+    pub error: Option<String>,
+    /// The model that wrote the text; empty until the enhancer has picked one
+    /// for a candidate that doesn't name its own
+    pub model: String,
+    pub seed: Option<i64>,
+    #[serde(skip)]
+    variation: Variation,
+    /// Which model and target wrote the text, once it is ready
+    #[serde(skip)]
+    served_by: Option<String>,
+    /// The running generation, if any
+    #[serde(skip)]
+    job_id: Option<u64>,
+}
+
+/// What the review window shows.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReviewState {
+    pub original: String,
+    /// Name of the preset used, if any
+    pub preset: Option<String>,
+    pub candidates: Vec<ReviewCandidate>,
+}
+
+/// A hotkey enhancement waiting for the user to accept or reject it.
+pub struct PendingReview {
+    state: ReviewState,
+    preset: Option<Preset>,
+    target: ClipboardTarget,
+    /// Which set of candidates is current; changes on every [`Self::reset`]
+    serial: u64,
+}
+
+impl PendingReview {
+    fn cancel_jobs(&self, app_handle: &tauri::AppHandle) {
+        let state = app_handle.state::<AppState>();
+        for job_id in self.state.candidates.iter().filter_map(|c| c.job_id) {
+            state.jobs.cancel(job_id);
+        }
+    }
+
+    /// Replace the candidates with fresh ones for `variations`.
+    fn reset(&mut self, variations: Vec<Variation>) {
+        self.serial = NEXT_SERIAL.fetch_add(1, Ordering::Relaxed);
+        self.state.candidates = variations.into_iter().map(|variation| ReviewCandidate {
+            enhanced: String::new(),
+            status: ReviewStatus::Generating,
+            error: None,
+            model: variation.model.clone().unwrap_or_default(),
+            seed: variation.seed,
+            variation,
+            served_by: None,
+            job_id: None,
+        }).collect();
+    }
+}
+
+/// Open the review window for `original` and start generating.
+pub fn start(app_handle: &tauri::AppHandle, original: String, preset: Option<Preset>, target: ClipboardTarget) -> Result<(), String> {
+    let state = app_handle.state::<AppState>();
+    let variations = state.settings().review.variations()?;
+    let mut review = PendingReview {
+        state: ReviewState {
+            original,
+            preset: preset.as_ref().map(|p| p.name.clone()),
+            candidates: Vec::new(),
+        },
+        preset,
+        target,
+        serial: 0,
+    };
+    review.reset(variations);
+    let count = review.state.candidates.len();
+    let serial = review.serial;
+
+    // A new hotkey press replaces a review nobody answered
+    if let Some(replaced) = state.review.lock().unwrap().replace(review) {
+        debug!("Replacing an unanswered review");
+        replaced.cancel_jobs(app_handle);
+    }
+
+    open_window(app_handle)?;
+    emit(app_handle);
+    for index in 0..count {
+        tauri::async_runtime::spawn(generate(app_handle.clone(), serial, index));
+    }
+    Ok(())
+}
+
+fn open_window(app_handle: &tauri::AppHandle) -> Result<(), String> {
+    if let Some(window) = app_handle.get_webview_window(REVIEW_WINDOW) {
+        let _ = window.show();
+        let _ = window.set_focus();
+        return Ok(());
+    }
+
+    let window = tauri::WebviewWindowBuilder::new(app_handle, REVIEW_WINDOW, tauri::WebviewUrl::App("review.html".into()))
+        .title("Clip Prompt - Review")
+        .inner_size(760.0, 440.0)
+        .min_inner_size(480.0, 320.0)
+        .always_on_top(true)
+        .skip_taskbar(true)
+        .center()
+        .build()
+        .map_err(|e| format!("Failed to open the review window: {}", e))?;
+
+    // Closing the window is the same as rejecting
+    let app = app_handle.clone();
+    window.on_window_event(move |event| {
+        if let tauri::WindowEvent::Destroyed = event {
+            reject(&app);
+        }
+    });
+    Ok(())
+}
+
+fn close_window(app_handle: &tauri::AppHandle) {
+    if let Some(window) = app_handle.get_webview_window(REVIEW_WINDOW) {
+        let _ = window.close();
+    }
+}
+
+/// The review window's current contents, if a review is open.
+pub fn current(app_handle: &tauri::AppHandle) -> Option<ReviewState> {
+    let state = app_handle.state::<AppState>();
+    let review = state.review.lock().unwrap();
+    review.as_ref().map(|review| review.state.clone())
+}
+
+/// Send the whole review to the window.
+fn emit(app_handle: &tauri::AppHandle) {
+    if let Some(review) = current(app_handle) {
+        let _ = app_handle.emit_to(REVIEW_WINDOW, REVIEW_UPDATED_EVENT, review);
+    }
+}
+
+/// Apply `update` to candidate `index` of the open review, unless it has
+/// moved on from `job_id`, and send the result to the window.
+fn update(app_handle: &tauri::AppHandle, index: usize, job_id: u64, update: impl FnOnce(&mut ReviewCandidate)) {
+    let state = app_handle.state::<AppState>();
+    let mut review = state.review.lock().unwrap();
+    let Some(review) = review.as_mut() else {
+        return;
+    };
+    let Some(candidate) = review.state.candidates.get_mut(index).filter(|c| c.job_id == Some(job_id)) else {
+        return;
+    };
+    update(candidate);
+    let _ = app_handle.emit_to(REVIEW_WINDOW, REVIEW_UPDATED_EVENT, review.state.clone());
+}
+
+/// Generate candidate `index` of the open review, streaming the text into
+/// the window, unless the review has moved on from the candidates numbered
+/// `serial`. Candidates that don't name a model go through the fallback
+/// targets like a hotkey enhancement without review.
+async fn generate(app_handle: tauri::AppHandle, serial: u64, index: usize) {
+    let state = app_handle.state::<AppState>();
+    let job = state.jobs.start(JobKind::Enhancement);
+    let job_id = job.id;
+    let request = {
+        let mut review = state.review.lock().unwrap();
+        review.as_mut().filter(|review| review.serial == serial).and_then(|review| {
+            let candidate = review.state.candidates.get_mut(index)?;
+            candidate.job_id = Some(job_id);
+            Some((review.state.original.clone(), review.preset.clone(), candidate.variation.clone()))
+        })
+    };
+    let Some((original, preset, variation)) = request else {
+        state.jobs.finish(job_id);
+        return;
+    };
+
+    let result = crate::stream_variation(&app_handle, &job, "hotkey", &original, preset.as_ref(), &variation, |delta, _| {
+        update(&app_handle, index, job_id, |candidate| candidate.enhanced.push_str(delta));
+    }).await;
+
+    update(&app_handle, index, job_id, |candidate| match result {
+        Ok(enhancement) => {
+            candidate.served_by = Some(enhancement.served_by());
+            candidate.enhanced = enhancement.generation.text;
+            candidate.model = enhancement.model;
+            candidate.status = ReviewStatus::Ready;
+        },
+        Err(e) if e == CANCELLED_ERROR => candidate.status = ReviewStatus::Cancelled,
+        Err(e) => {
+            candidate.status = ReviewStatus::Failed;
+            candidate.error = Some(e);
+        },
+    });
+}
+
+/// Throw away the current candidates and generate new ones.
+pub fn regenerate(app_handle: &tauri::AppHandle) -> Result<(), String> {
+    let state = app_handle.state::<AppState>();
+    let variations = state.settings().review.variations()?;
+    let (serial, count) = {
+        let mut review = state.review.lock().unwrap();
+        let review = review.as_mut().ok_or_else(|| "Nothing to review".to_string())?;
+        review.cancel_jobs(app_handle);
+        review.reset(variations);
+        (review.serial, review.state.candidates.len())
+    };
+
+    emit(app_handle);
+    for index in 0..count {
+        tauri::async_runtime::spawn(generate(app_handle.clone(), serial, index));
+    }
+    Ok(())
+}
+
+/// Take `text` (candidate `index`, possibly edited) and deliver it like a
+/// hotkey enhancement without review would.
+pub async fn accept(app_handle: &tauri::AppHandle, index: usize, text: String) -> Result<(), String> {
+    if text.trim().is_empty() {
+        return Err("Nothing to accept, the text is empty".to_string());
+    }
+    let review = take(app_handle).ok_or_else(|| "Nothing to review".to_string())?;
+    close_window(app_handle);
+    let served_by = review.state.candidates.get(index).and_then(|c| c.served_by.clone());
+    info!("Review accepted{}", served_by.as_ref().map_or(String::new(), |s| format!(": {}", s)));
+
+    // Let focus go back to the app the selection came from before pasting
+    if review.target.auto_paste.enabled {
+        tokio::time::sleep(review.target.auto_paste.delay()).await;
+    }
+    crate::deliver_enhancement(app_handle, review.target, text, served_by).await
+}
+
+/// Close the review and leave the clipboard as it was.
+pub fn reject(app_handle: &tauri::AppHandle) {
+    let Some(review) = take(app_handle) else {
+        return;
+    };
+    close_window(app_handle);
+    info!("Review rejected, clipboard left untouched");
+    let app_handle = app_handle.clone();
+    tauri::async_runtime::spawn(async move {
+        review.target.restore(&app_handle).await;
+    });
+}
+
+/// End the open review, cancelling generations that are still running.
+fn take(app_handle: &tauri::AppHandle) -> Option<PendingReview> {
+    let state = app_handle.state::<AppState>();
+    let review = state.review.lock().unwrap().take()?;
+    review.cancel_jobs(app_handle);
+    Some(review)
+}
@@ -0,0 +1,330 @@
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::backend::{BackendConfig, FewShotExample, GenerationOptions, ModelLoadingSettings, PromptMode};
+use crate::clipboard::ClipboardSettings;
+use crate::history::HistorySettings;
+use crate::hotkeys::DEFAULT_ENHANCE_HOTKEY;
+use crate::http_api::HttpApiSettings;
+use crate::input::AutoPasteSettings;
+use crate::presets::Preset;
+use crate::review::ReviewSettings;
+use crate::targets::FallbackTarget;
+
+/// Must match `identifier` in tauri.conf.json so the settings live in the
+/// same directory Tauri uses for the app's config.
+pub const APP_IDENTIFIER: &str = "com.clip-prompt";
+const SETTINGS_FILE: &str = "settings.json";
+
+/// Current version of the settings file format. Bump it and append a step to
+/// [`MIGRATIONS`] whenever a change needs existing files rewritten.
+pub const SETTINGS_VERSION: u32 = 1;
+
+/// `MIGRATIONS[n]` upgrades a version `n` document to version `n + 1`.
+const MIGRATIONS: [fn(&mut Map<String, Value>); SETTINGS_VERSION as usize] = [
+    migrate_v0_to_v1,
+];
+
+/// Everything Clip Prompt remembers between runs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub version: u32,
+    /// Selected model; empty until one has been chosen
+    pub model: String,
+    /// Models to use, in order, when the selected one isn't installed
+    pub fallback_models: Vec<String>,
+    /// Custom system prompt; empty means use the default
+    pub system_prompt: String,
+    pub prompt_mode: PromptMode,
+    pub few_shot_examples: Vec<FewShotExample>,
+    /// Generation options keyed by model name
+    pub generation_options: BTreeMap<String, GenerationOptions>,
+    pub backend: BackendConfig,
+    /// Servers and models to try, in order, when the selected backend fails
+    pub fallback_targets: Vec<FallbackTarget>,
+    /// Preloading the selected model and how long it stays loaded
+    pub model_loading: ModelLoadingSettings,
+    /// Shortcut that enhances the clipboard; `None` disables it
+    pub enhance_hotkey: Option<String>,
+    pub cancel_hotkey: Option<String>,
+    /// Shortcut that restores the clipboard from before the last enhancement
+    pub undo_hotkey: Option<String>,
+    pub presets: Vec<Preset>,
+    /// Id of the preset the enhance hotkey and IPC requests use when they
+    /// don't name one; `None` means the system prompt
+    pub active_preset: Option<String>,
+    pub history: HistorySettings,
+    pub http_api: HttpApiSettings,
+    /// Where the clipboard enhancement reads from, and how
+    pub clipboard: ClipboardSettings,
+    /// Copy the selection and paste the result back by synthesizing keystrokes
+    pub auto_paste: AutoPasteSettings,
+    /// Review hotkey results before they reach the clipboard
+    pub review: ReviewSettings,
+    /// Keys this build doesn't know (e.g. from a newer version), kept so
+    /// saving doesn't drop them
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            model: "".to_string(),
+            fallback_models: Vec::new(),
+            system_prompt: "".to_string(),
+            prompt_mode: PromptMode::default(),
+            few_shot_examples: Vec::new(),
+            generation_options: BTreeMap::new(),
+            backend: BackendConfig::default(),
+            fallback_targets: Vec::new(),
+            model_loading: ModelLoadingSettings::default(),
+            enhance_hotkey: Some(DEFAULT_ENHANCE_HOTKEY.to_string()),
+            cancel_hotkey: None,
+            undo_hotkey: None,
+            presets: Vec::new(),
+            active_preset: None,
+            history: HistorySettings::default(),
+            http_api: HttpApiSettings::default(),
+            clipboard: ClipboardSettings::default(),
+            auto_paste: AutoPasteSettings::default(),
+            review: ReviewSettings::default(),
+            extra: Map::new(),
+        }
+    }
+}
+
+/// Default location of the settings file, shared by every entry point.
+pub fn settings_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join(APP_IDENTIFIER).join(SETTINGS_FILE))
+}
+
+impl Settings {
+    /// Load settings from `path`, migrating older files. A missing file gives
+    /// the defaults; an unreadable one is moved aside so it isn't overwritten.
+    pub fn load(path: &Path) -> Self {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                info!("No settings file at {}, using defaults", path.display());
+                return Self::default();
+            },
+            Err(e) => {
+                error!("Failed to read settings file {}: {}", path.display(), e);
+                return Self::default();
+            },
+        };
+
+        match Self::from_json(&contents) {
+            Ok(settings) => {
+                debug!("Loaded settings from {}", path.display());
+                settings
+            },
+            Err(e) => {
+                let backup = path.with_extension("json.corrupt");
+                error!("Failed to parse settings file {}: {} (moving it to {})", path.display(), e, backup.display());
+                if let Err(e) = fs::rename(path, &backup) {
+                    error!("Failed to move corrupt settings file aside: {}", e);
+                }
+                Self::default()
+            },
+        }
+    }
+
+    /// Load settings from `path` without touching the file, for readers
+    /// such as the command line that must not move the tray app's settings
+    /// aside. A missing file gives the defaults; anything else that goes
+    /// wrong is an error.
+    pub fn read(path: &Path) -> Result<Self, String> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Self::from_json(&contents)
+                .map_err(|e| format!("Failed to parse settings file {}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(format!("Failed to read settings file {}: {}", path.display(), e)),
+        }
+    }
+
+    /// Parse a settings document of any known version.
+    pub fn from_json(contents: &str) -> Result<Self, String> {
+        let value: Value = serde_json::from_str(contents)
+            .map_err(|e| format!("Invalid settings JSON: {}", e))?;
+        let Value::Object(mut document) = value else {
+            return Err("Settings file must contain a JSON object".to_string());
+        };
+
+        migrate(&mut document);
+
+        serde_json::from_value(Value::Object(document))
+            .map_err(|e| format!("Invalid settings: {}", e))
+    }
+
+    /// Write the settings to `path`, replacing the old file atomically.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create settings directory: {}", e))?;
+        }
+
+        let contents = serde_json::to_string_pretty(self)
+            .map_err(|e| format!("Failed to serialize settings: {}", e))?;
+
+        let temp_path = path.with_extension("json.tmp");
+        fs::write(&temp_path, contents)
+            .map_err(|e| format!("Failed to write settings file: {}", e))?;
+        fs::rename(&temp_path, path)
+            .map_err(|e| format!("Failed to replace settings file: {}", e))?;
+
+        debug!("Saved settings to {}", path.display());
+        Ok(())
+    }
+
+    /// Overlay a settings document of any version (for example the values the
+    /// webview used to keep in localStorage) on top of these settings. Keys
+    /// the document doesn't mention keep their current value.
+    pub fn merge_document(&self, mut document: Map<String, Value>) -> Result<Self, String> {
+        migrate(&mut document);
+
+        let Value::Object(mut merged) = serde_json::to_value(self)
+            .map_err(|e| format!("Failed to serialize settings: {}", e))? else {
+            return Err("Failed to serialize settings".to_string());
+        };
+        merged.extend(document);
+
+        serde_json::from_value(Value::Object(merged))
+            .map_err(|e| format!("Invalid settings: {}", e))
+    }
+}
+
+/// Bring a settings document up to [`SETTINGS_VERSION`]. Documents without a
+/// `version` key are treated as version 0.
+fn migrate(document: &mut Map<String, Value>) {
+    let mut version = document.get("version").and_then(Value::as_u64).unwrap_or(0) as u32;
+
+    if version > SETTINGS_VERSION {
+        warn!("Settings file is version {}, newer than this build understands ({}); unknown keys will be kept as they are", version, SETTINGS_VERSION);
+        return;
+    }
+
+    while version < SETTINGS_VERSION {
+        debug!("Migrating settings from version {} to {}", version, version + 1);
+        MIGRATIONS[version as usize](document);
+        version += 1;
+        document.insert("version".to_string(), Value::from(version));
+    }
+}
+
+/// Version 0 is the layout the webview used in localStorage: the selected
+/// model and the system prompt under camelCase keys.
+fn migrate_v0_to_v1(document: &mut Map<String, Value>) {
+    const RENAMES: [(&str, &str); 2] = [
+        ("selectedModel", "model"),
+        ("systemPrompt", "system_prompt"),
+    ];
+
+    for (old, new) in RENAMES {
+        if let Some(value) = document.remove(old) {
+            document.insert(new.to_string(), value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn document(value: Value) -> Map<String, Value> {
+        let Value::Object(document) = value else {
+            panic!("not an object: {}", value);
+        };
+        document
+    }
+
+    #[test]
+    fn v0_document_becomes_settings() {
+        let legacy = json!({
+            "selectedModel": "llama3.2:3b",
+            "systemPrompt": "Fix the grammar.",
+        });
+
+        let settings = Settings::from_json(&legacy.to_string()).unwrap();
+
+        assert_eq!(settings.version, SETTINGS_VERSION);
+        assert_eq!(settings.model, "llama3.2:3b");
+        assert_eq!(settings.system_prompt, "Fix the grammar.");
+
+        let saved = serde_json::to_string(&settings).unwrap();
+        let reloaded = Settings::from_json(&saved).unwrap();
+        assert_eq!(reloaded.model, settings.model);
+        assert_eq!(reloaded.system_prompt, settings.system_prompt);
+    }
+
+    #[test]
+    fn v0_document_keeps_unknown_keys() {
+        let settings = Settings::from_json(r#"{"selectedModel": "llama3.2:3b", "theme": "dark"}"#).unwrap();
+        assert_eq!(settings.model, "llama3.2:3b");
+        assert_eq!(settings.version, SETTINGS_VERSION);
+        assert_eq!(settings.extra.get("theme"), Some(&json!("dark")));
+        assert!(!settings.extra.contains_key("selectedModel"));
+    }
+
+    #[test]
+    fn newer_version_is_left_alone() {
+        let original = document(json!({
+            "version": SETTINGS_VERSION + 1,
+            "selectedModel": "llama3.2:3b",
+            "model": "qwen2.5:7b",
+        }));
+        let mut migrated = original.clone();
+
+        migrate(&mut migrated);
+
+        assert_eq!(migrated, original);
+    }
+
+    #[test]
+    fn newer_version_keeps_unknown_keys_when_saved() {
+        let dir = std::env::temp_dir().join(format!("clip-prompt-settings-{}", std::process::id()));
+        let path = dir.join(SETTINGS_FILE);
+        let newer = json!({
+            "version": SETTINGS_VERSION + 1,
+            "model": "qwen2.5:7b",
+            "theme": {"accent": "teal"},
+        });
+
+        let mut settings = Settings::from_json(&newer.to_string()).unwrap();
+        settings.model = "llama3.2:3b".to_string();
+        settings.save(&path).unwrap();
+        let saved: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(saved["version"], json!(SETTINGS_VERSION + 1));
+        assert_eq!(saved["model"], json!("llama3.2:3b"));
+        assert_eq!(saved["theme"], json!({"accent": "teal"}));
+    }
+
+    #[test]
+    fn merge_keeps_unmentioned_keys() {
+        let current = Settings {
+            model: "qwen2.5:7b".to_string(),
+            system_prompt: "Be brief.".to_string(),
+            prompt_mode: PromptMode::Generate,
+            cancel_hotkey: Some("Escape".to_string()),
+            ..Settings::default()
+        };
+
+        let merged = current.merge_document(document(json!({"selectedModel": "llama3.2:3b"}))).unwrap();
+
+        assert_eq!(merged.model, "llama3.2:3b");
+        assert_eq!(merged.system_prompt, "Be brief.");
+        assert_eq!(merged.prompt_mode, PromptMode::Generate);
+        assert_eq!(merged.cancel_hotkey.as_deref(), Some("Escape"));
+        assert_eq!(merged.version, SETTINGS_VERSION);
+    }
+}
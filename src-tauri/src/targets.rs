@@ -0,0 +1,261 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::backend::{create_backend, normalize_ollama_url, BackendConfig, BackendKind, LlmBackend};
+
+/// Most fallback targets, so a long list can't make a failing enhancement
+/// take minutes.
+pub const MAX_TARGETS: usize = 8;
+/// How long a target that failed is tried only after the others. Doubles
+/// with each failure in a row, up to [`MAX_COOLDOWN`].
+const BASE_COOLDOWN: Duration = Duration::from_secs(30);
+const MAX_COOLDOWN: Duration = Duration::from_secs(5 * 60);
+
+/// A server and model to fall back to when the ones before it fail.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FallbackTarget {
+    /// Shown in notifications; empty means the server's address
+    pub name: String,
+    pub backend: BackendConfig,
+    /// Model to use on this server; empty applies the usual model policy
+    /// there
+    pub model: String,
+}
+
+impl FallbackTarget {
+    /// Check the target, returning it trimmed and with the Ollama URL
+    /// normalized.
+    pub fn normalize(mut self) -> Result<Self, String> {
+        self.name = self.name.trim().to_string();
+        self.model = self.model.trim().to_string();
+        match self.backend.kind {
+            BackendKind::Ollama => {
+                self.backend.ollama_url = match self.backend.ollama_url.trim() {
+                    "" => "".to_string(),
+                    url => normalize_ollama_url(url)?,
+                };
+            },
+            BackendKind::OpenAi => {
+                self.backend.openai_url = self.backend.openai_url.trim().to_string();
+                if self.backend.openai_url.is_empty() {
+                    return Err("Fallback targets on an OpenAI-compatible server need its URL".to_string());
+                }
+                if self.model.is_empty() {
+                    return Err(format!("Name the model to use on {}", self.backend.openai_url));
+                }
+            },
+        }
+        Ok(self)
+    }
+}
+
+/// Somewhere an enhancement can be sent: the primary server with the usual
+/// model, or a fallback target.
+#[derive(Debug, Clone)]
+pub struct Target {
+    /// Tells targets apart in [`TargetHealth`]
+    pub key: String,
+    /// The target's name, or which server it is
+    pub label: String,
+    pub config: BackendConfig,
+    /// The Ollama URL to use, with the settings' empty URL resolved
+    pub ollama_url: String,
+    /// The model the target names, if any
+    pub model: Option<String>,
+    /// Whether this is a fallback target rather than the primary server
+    pub fallback: bool,
+}
+
+impl Target {
+    pub fn new(name: &str, config: BackendConfig, ollama_url: String, model: Option<String>, fallback: bool) -> Self {
+        let address = match config.kind {
+            BackendKind::Ollama => ollama_url.clone(),
+            BackendKind::OpenAi => config.openai_url.clone(),
+        };
+        let backend = create_backend(&config, &ollama_url).name();
+        Self {
+            key: format!("{} {} {}", backend, address, model.as_deref().unwrap_or_default()),
+            label: if name.is_empty() { format!("{} at {}", backend, address) } else { name.to_string() },
+            config,
+            ollama_url,
+            model,
+            fallback,
+        }
+    }
+
+    pub fn backend(&self) -> Box<dyn LlmBackend> {
+        create_backend(&self.config, &self.ollama_url)
+    }
+}
+
+/// Why a target couldn't enhance a text.
+#[derive(Debug, Clone)]
+pub enum TargetError {
+    /// The server or its model failed, so the target is tried last for a
+    /// while
+    Down(String),
+    /// The target can't take this enhancement (the text is too long for the
+    /// model, the server has no model to run it or refused the request), but
+    /// the server is fine
+    Unsuitable(String),
+}
+
+impl fmt::Display for TargetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Down(e) | Self::Unsuitable(e) => f.write_str(e),
+        }
+    }
+}
+
+/// How a target has been doing, for the settings window.
+#[derive(Debug, Clone, Serialize)]
+pub struct TargetStatus {
+    pub label: String,
+    pub model: Option<String>,
+    pub fallback: bool,
+    /// Failures since the target last succeeded
+    pub failures: u32,
+    pub last_error: Option<String>,
+    /// Seconds until the target is tried in its usual place again; 0 if it is
+    /// healthy
+    pub retry_in_secs: u64,
+}
+
+#[derive(Debug, Clone)]
+struct Health {
+    failures: u32,
+    last_error: String,
+    retry_at: Instant,
+}
+
+/// Recent failures of each target, shared by every enhancement so one that
+/// finds a server down spares the next ones the wait.
+#[derive(Debug, Default)]
+pub struct TargetHealth {
+    targets: Mutex<HashMap<String, Health>>,
+}
+
+impl TargetHealth {
+    /// `targets` in the order to try them: those that haven't failed
+    /// recently as listed, then the others, soonest to recover first.
+    /// Failing targets are still tried, in case they have recovered.
+    pub fn order(&self, targets: Vec<Target>) -> Vec<Target> {
+        let now = Instant::now();
+        let health = self.targets.lock().unwrap();
+        let (mut ready, mut cooling): (Vec<_>, Vec<_>) = targets.into_iter()
+            .map(|target| (health.get(&target.key).map(|h| h.retry_at).filter(|at| *at > now), target))
+            .partition(|(retry_at, _)| retry_at.is_none());
+        cooling.sort_by_key(|(retry_at, _)| *retry_at);
+        ready.extend(cooling);
+        ready.into_iter().map(|(_, target)| target).collect()
+    }
+
+    pub fn succeeded(&self, target: &Target) {
+        if self.targets.lock().unwrap().remove(&target.key).is_some() {
+            info!("{} is working again", target.label);
+        }
+    }
+
+    pub fn failed(&self, target: &Target, error: &str) {
+        let mut targets = self.targets.lock().unwrap();
+        let failures = targets.get(&target.key).map_or(0, |h| h.failures) + 1;
+        let cooldown = BASE_COOLDOWN.saturating_mul(1 << (failures - 1).min(8)).min(MAX_COOLDOWN);
+        info!("{} failed ({} in a row), trying it last for {} s", target.label, failures, cooldown.as_secs());
+        targets.insert(target.key.clone(), Health {
+            failures,
+            last_error: error.to_string(),
+            retry_at: Instant::now() + cooldown,
+        });
+    }
+
+    pub fn status(&self, target: &Target) -> TargetStatus {
+        let health = self.targets.lock().unwrap().get(&target.key).cloned();
+        TargetStatus {
+            label: target.label.clone(),
+            model: target.model.clone(),
+            fallback: target.fallback,
+            failures: health.as_ref().map_or(0, |h| h.failures),
+            retry_in_secs: health.as_ref()
+                .map_or(0, |h| h.retry_at.saturating_duration_since(Instant::now()).as_millis().div_ceil(1000) as u64),
+            last_error: health.map(|h| h.last_error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(host: &str) -> Target {
+        Target::new(host, BackendConfig::default(), format!("http://{}:11434", host), None, true)
+    }
+
+    fn labels(targets: Vec<Target>) -> Vec<String> {
+        targets.into_iter().map(|target| target.label).collect()
+    }
+
+    #[test]
+    fn healthy_targets_keep_their_order() {
+        let health = TargetHealth::default();
+        let targets = vec![target("a"), target("b"), target("c")];
+        assert_eq!(labels(health.order(targets)), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn failed_targets_go_last_soonest_to_recover_first() {
+        let health = TargetHealth::default();
+        let (a, b, c) = (target("a"), target("b"), target("c"));
+        health.failed(&a, "connection refused");
+        health.failed(&a, "connection refused");
+        health.failed(&b, "timed out");
+
+        assert_eq!(labels(health.order(vec![a.clone(), b.clone(), c.clone()])), ["c", "b", "a"]);
+
+        health.succeeded(&a);
+        assert_eq!(labels(health.order(vec![a, b, c])), ["a", "c", "b"]);
+    }
+
+    #[test]
+    fn cooldown_doubles_up_to_the_limit() {
+        let health = TargetHealth::default();
+        let a = target("a");
+
+        health.failed(&a, "connection refused");
+        let status = health.status(&a);
+        assert_eq!(status.failures, 1);
+        assert_eq!(status.last_error.as_deref(), Some("connection refused"));
+        assert!(status.retry_in_secs > 0 && status.retry_in_secs <= BASE_COOLDOWN.as_secs());
+
+        health.failed(&a, "timed out");
+        let status = health.status(&a);
+        assert_eq!(status.failures, 2);
+        assert_eq!(status.last_error.as_deref(), Some("timed out"));
+        assert!(status.retry_in_secs > BASE_COOLDOWN.as_secs() && status.retry_in_secs <= 2 * BASE_COOLDOWN.as_secs());
+
+        for _ in 0..20 {
+            health.failed(&a, "timed out");
+        }
+        let status = health.status(&a);
+        assert_eq!(status.failures, 22);
+        assert!(status.retry_in_secs > MAX_COOLDOWN.as_secs() - 5 && status.retry_in_secs <= MAX_COOLDOWN.as_secs());
+    }
+
+    #[test]
+    fn success_clears_the_failures() {
+        let health = TargetHealth::default();
+        let a = target("a");
+        health.failed(&a, "connection refused");
+        health.succeeded(&a);
+
+        let status = health.status(&a);
+        assert_eq!(status.failures, 0);
+        assert_eq!(status.retry_in_secs, 0);
+        assert!(status.last_error.is_none());
+    }
+}
@@ -1,8 +1,28 @@
 // Tauri 2 global API
 const { invoke } = window.__TAURI__.core;
+const { listen } = window.__TAURI__.event;
 
 // Global variables
 let isEnhancing = false;
+let streamJobId = null;
+let presets = [];
+let activePresetId = null;
+let isComparing = false;
+// The last finished comparison, for the report buttons
+let comparison = null;
+// How the backend explains which rule chose a model
+const MODEL_RULES = {
+    requested: 'requested',
+    preset: "preset's model",
+    preferred: 'preferred model',
+    fallback: 'from fallback list',
+    ranked: 'best installed model',
+    target: "fallback target's model"
+};
+// Job id of the running model download
+let pullJobId = null;
+// Resets the delete model button if the deletion isn't confirmed
+let deleteModelTimer = null;
 
 /**
  * Initialize the application
@@ -12,6 +32,7 @@ async function init() {
     
     // Set up event listeners
     setupEventListeners();
+    await setupStreamListeners();
     
     // Load saved settings
     await loadSettings();
@@ -42,9 +63,64 @@ function setupEventListeners() {
     document.getElementById('enhanceBtn').addEventListener('click', handleEnhance);
     document.getElementById('clearBtn').addEventListener('click', handleClear);
     document.getElementById('copyBtn').addEventListener('click', handleCopy);
+    document.getElementById('cancelBtn').addEventListener('click', handleCancel);
+    document.getElementById('compareBtn').addEventListener('click', handleCompare);
+    document.getElementById('copyReportBtn').addEventListener('click', handleCopyReport);
+    document.getElementById('saveReportBtn').addEventListener('click', handleSaveReport);
+    document.getElementById('enhanceHotkey').addEventListener('change', handleEnhanceHotkeyChange);
+    document.getElementById('cancelHotkey').addEventListener('change', handleCancelHotkeyChange);
+    document.getElementById('undoHotkey').addEventListener('change', handleUndoHotkeyChange);
+    document.getElementById('retryHotkeys').addEventListener('click', handleRetryHotkeys);
+    document.getElementById('historySearch').addEventListener('input', loadHistory);
+    document.getElementById('historyEnabled').addEventListener('change', handleHistorySettingsChange);
+    document.querySelectorAll('.review-setting').forEach(field => {
+        field.addEventListener('change', handleReviewSettingsChange);
+    });
+    document.querySelectorAll('.history-setting').forEach(field => {
+        field.addEventListener('change', handleHistorySettingsChange);
+    });
+    document.getElementById('clearHistory').addEventListener('click', handleClearHistory);
+    document.querySelectorAll('.clipboard-setting').forEach(field => {
+        field.addEventListener('change', handleClipboardSettingsChange);
+    });
+    document.querySelectorAll('.auto-paste-setting').forEach(field => {
+        field.addEventListener('change', handleAutoPasteSettingsChange);
+    });
+    document.getElementById('httpApiEnabled').addEventListener('change', handleHttpApiSettingsChange);
+    document.getElementById('httpApiPort').addEventListener('change', handleHttpApiSettingsChange);
+    document.getElementById('copyHttpApiToken').addEventListener('click', handleCopyHttpApiToken);
+    document.getElementById('regenerateHttpApiToken').addEventListener('click', handleRegenerateHttpApiToken);
+    document.getElementById('promptMode').addEventListener('change', handlePromptModeChange);
+    document.querySelectorAll('.generation-option').forEach(field => {
+        field.addEventListener('change', handleGenerationOptionsChange);
+    });
+    document.getElementById('addFewShotExample').addEventListener('click', () => {
+        addFewShotExampleRow({ input: '', output: '' });
+    });
+    document.getElementById('activePreset').addEventListener('change', handleActivePresetChange);
+    document.getElementById('addPreset').addEventListener('click', () => {
+        const preset = { id: '', name: '', system_prompt: '', model: '', options: {}, hotkey: null };
+        presets.push(preset);
+        addPresetRow(preset);
+    });
     
     // Settings event listeners
     document.getElementById('modelSelect').addEventListener('change', handleModelChange);
+    document.getElementById('fallbackModels').addEventListener('change', handleFallbackModelsChange);
+    document.querySelectorAll('.model-loading-setting').forEach(field => {
+        field.addEventListener('change', handleModelLoadingSettingsChange);
+    });
+    document.getElementById('unloadModelsBtn').addEventListener('click', handleUnloadModels);
+    document.getElementById('pullModelBtn').addEventListener('click', handlePullModel);
+    document.getElementById('cancelPullBtn').addEventListener('click', handleCancelPull);
+    document.getElementById('deleteModelBtn').addEventListener('click', handleDeleteModel);
+    document.getElementById('backendKind').addEventListener('change', handleBackendChange);
+    document.getElementById('ollamaUrl').addEventListener('change', handleBackendChange);
+    document.getElementById('openaiUrl').addEventListener('change', handleBackendChange);
+    document.getElementById('openaiApiKey').addEventListener('change', handleBackendChange);
+    document.getElementById('addFallbackTarget').addEventListener('click', () => {
+        addFallbackTargetRow({ name: '', backend: { kind: 'ollama', ollama_url: '', openai_url: '', openai_api_key: '' }, model: '' });
+    });
     document.getElementById('toggleInstallInstructions').addEventListener('click', toggleInstallInstructions);
     document.getElementById('resetSystemPrompt').addEventListener('click', handleResetSystemPrompt);
     document.getElementById('systemPrompt').addEventListener('input', handleSystemPromptChange);
@@ -75,216 +151,1608 @@ function setupEventListeners() {
     console.log('✅ Event listeners set up');
 }
 
-
+
+/**
+ * Follow streaming enhancements (from this window or the global hotkey)
+ * and render their text as it arrives
+ */
+async function setupStreamListeners() {
+    const outputTextarea = document.getElementById('outputText');
+    
+    await listen('enhance-started', (event) => {
+        streamJobId = event.payload.job_id;
+        if (event.payload.source !== 'ui') {
+            document.getElementById('inputText').value = event.payload.input;
+        }
+        outputTextarea.value = '';
+        updateOllamaStatus('connecting', 'Enhancing text...');
+    });
+    
+    await listen('enhance-delta', (event) => {
+        if (event.payload.job_id !== streamJobId) return;
+        outputTextarea.value += event.payload.delta;
+        outputTextarea.scrollTop = outputTextarea.scrollHeight;
+    });
+    
+    await listen('enhance-done', (event) => {
+        if (event.payload.job_id !== streamJobId) return;
+        outputTextarea.value = event.payload.text;
+        const { model, model_rule: rule, target, fallback } = event.payload;
+        updateOllamaStatus('connected', `Text enhanced with ${model} (${MODEL_RULES[rule]}) on ${target}${fallback ? ' (fallback)' : ''}`);
+    });
+    
+    await listen('enhance-error', (event) => {
+        if (event.payload.job_id !== streamJobId) return;
+        updateOllamaStatus('error', `Enhancement failed: ${event.payload.error}`);
+    });
+    
+    // Any enhancement (including the hotkey's) may change how the targets are doing
+    await listen('enhance-done', loadTargetStatus);
+    await listen('enhance-error', loadTargetStatus);
+    
+    await listen('enhance-cancelled', (event) => {
+        if (event.payload.job_id !== streamJobId) return;
+        updateOllamaStatus('error', 'Enhancement cancelled');
+    });
+    
+    await listen('compare-progress', (event) => {
+        if (!isComparing) return;
+        showComparisonResult(event.payload.index, event.payload.result);
+    });
+    
+    await listen('model-pull-started', (event) => {
+        pullJobId = event.payload.job_id;
+    });
+    
+    await listen('model-pull-progress', (event) => {
+        if (event.payload.job_id !== pullJobId) return;
+        showPullProgress(event.payload);
+    });
+    
+    await listen('history-updated', loadHistory);
+    
+    // The model or active preset was switched over IPC
+    await listen('settings-changed', async () => {
+        const settings = await invoke('get_settings');
+        document.getElementById('modelSelect').value = settings.model;
+        activePresetId = settings.active_preset;
+        updatePresetSelect();
+    });
+    
+    console.log('✅ Stream listeners set up');
+}
+
+/**
+ * Handle model change
+ */
+async function handleModelChange() {
+    const modelSelect = document.getElementById('modelSelect');
+    const selectedModel = modelSelect.value;
+    
+    try {
+        console.log('🔄 Changing model to:', selectedModel);
+        updateModelStatus('connecting', `Switching to ${selectedModel}...`);
+        
+        // Update (and save) the model in the backend
+        await invoke('update_model', { model: selectedModel });
+        
+        // Test the new model with a simple request
+        await invoke('test_ollama_connection');
+        
+        updateModelStatus('connected', selectedModel);
+        await loadGenerationOptions(selectedModel);
+        await loadModelDetails(selectedModel);
+        console.log('✅ Model changed successfully');
+    } catch (error) {
+        console.error('❌ Model change failed:', error);
+        updateModelStatus('error', `Failed to switch to ${selectedModel}`);
+    }
+}
+
+/**
+ * Format a byte count as MB or GB
+ */
+function formatBytes(bytes) {
+    return bytes >= 1e9 ? `${(bytes / 1e9).toFixed(1)} GB` : `${Math.round(bytes / 1e6)} MB`;
+}
+
+/**
+ * Short summary of a model's size and quantization, e.g. "7.2B · Q4_K_M · 4.4 GB"
+ */
+function describeModel(info) {
+    return [
+        info.parameter_size,
+        info.quantization_level,
+        info.size != null ? formatBytes(info.size) : null
+    ].filter(Boolean).join(' · ');
+}
+
+/**
+ * Show the selected model's family, context length and capabilities
+ */
+async function loadModelDetails(model) {
+    const detailsText = document.getElementById('modelDetails');
+    detailsText.classList.add('hidden');
+    if (!model) return;
+    
+    try {
+        const details = await invoke('get_model_details', { model });
+        const summary = [
+            details.family,
+            details.context_length != null ? `${details.context_length.toLocaleString()} token context` : null,
+            details.capabilities.length > 0 ? details.capabilities.join(', ') : null
+        ].filter(Boolean).join(' · ');
+        detailsText.textContent = summary;
+        detailsText.classList.toggle('hidden', !summary);
+    } catch (error) {
+        console.error('❌ Failed to load model details:', error);
+    }
+}
+
+/**
+ * Show a progress report of the running model download
+ */
+function showPullProgress(progress) {
+    const status = document.getElementById('pullStatus');
+    const bar = document.getElementById('pullBar');
+    
+    if (progress.total) {
+        const completed = progress.completed ?? 0;
+        bar.style.width = `${Math.min(100, (completed / progress.total) * 100).toFixed(1)}%`;
+        status.textContent = `Downloading ${progress.model}: ${formatBytes(completed)} of ${formatBytes(progress.total)}`;
+    } else {
+        status.textContent = `${progress.model}: ${progress.status}`;
+    }
+}
+
+/**
+ * Show an error from downloading or deleting a model
+ */
+function showModelManagementError(error) {
+    const errorText = document.getElementById('modelManagementError');
+    errorText.textContent = error ?? '';
+    errorText.classList.toggle('hidden', !error);
+}
+
+/**
+ * Download the named model, then reload the model list
+ */
+async function handlePullModel() {
+    const model = document.getElementById('pullModelName').value.trim();
+    const pullBtn = document.getElementById('pullModelBtn');
+    if (!model || pullBtn.disabled) return;
+    
+    pullBtn.disabled = true;
+    pullJobId = null;
+    showModelManagementError(null);
+    document.getElementById('pullBar').style.width = '0%';
+    document.getElementById('pullStatus').textContent = `Starting download of ${model}...`;
+    document.getElementById('pullProgress').classList.remove('hidden');
+    
+    try {
+        console.log('⬇️ Downloading model:', model);
+        await invoke('pull_model', { model });
+        document.getElementById('pullModelName').value = '';
+        console.log('✅ Model downloaded:', model);
+        
+        // The first model also becomes the selected one
+        if (await testOllamaConnection()) {
+            await loadAvailableModels();
+        }
+    } catch (error) {
+        console.error('❌ Model download failed:', error);
+        showModelManagementError(error === 'Enhancement cancelled' ? 'Download cancelled' : error);
+    } finally {
+        pullJobId = null;
+        pullBtn.disabled = false;
+        document.getElementById('pullProgress').classList.add('hidden');
+    }
+}
+
+/**
+ * Stop the running model download
+ */
+async function handleCancelPull() {
+    if (pullJobId == null) return;
+    
+    try {
+        await invoke('cancel_enhancement', { jobId: pullJobId });
+    } catch (error) {
+        console.error('❌ Failed to cancel download:', error);
+    }
+}
+
+/**
+ * Delete the selected model. The first click asks for confirmation, the
+ * second (within a few seconds) deletes.
+ */
+async function handleDeleteModel() {
+    const model = document.getElementById('modelSelect').value;
+    const deleteBtn = document.getElementById('deleteModelBtn');
+    if (!model) return;
+    
+    if (deleteModelTimer == null) {
+        deleteBtn.textContent = 'Confirm';
+        deleteModelTimer = setTimeout(() => {
+            deleteModelTimer = null;
+            deleteBtn.textContent = 'Delete';
+        }, 4000);
+        return;
+    }
+    
+    clearTimeout(deleteModelTimer);
+    deleteModelTimer = null;
+    deleteBtn.textContent = 'Delete';
+    showModelManagementError(null);
+    
+    try {
+        console.log('🗑️ Deleting model:', model);
+        await invoke('delete_model', { model });
+        console.log('✅ Model deleted:', model);
+        await loadAvailableModels();
+    } catch (error) {
+        console.error('❌ Failed to delete model:', error);
+        showModelManagementError(error);
+    }
+}
+
+/**
+ * Read the backend configuration from the settings form
+ */
+function readBackendConfig() {
+    return {
+        kind: document.getElementById('backendKind').value,
+        ollama_url: document.getElementById('ollamaUrl').value.trim(),
+        openai_url: document.getElementById('openaiUrl').value.trim(),
+        openai_api_key: document.getElementById('openaiApiKey').value.trim()
+    };
+}
+
+/**
+ * Fill the settings form from a backend configuration
+ */
+function applyBackendConfig(config) {
+    document.getElementById('backendKind').value = config.kind;
+    document.getElementById('ollamaUrl').value = config.ollama_url;
+    document.getElementById('openaiUrl').value = config.openai_url;
+    document.getElementById('openaiApiKey').value = config.openai_api_key;
+    document.getElementById('ollamaSettings').classList.toggle('hidden', config.kind !== 'ollama');
+    document.getElementById('openaiSettings').classList.toggle('hidden', config.kind !== 'openai');
+    // Only Ollama can download and delete models
+    document.getElementById('modelManagement').classList.toggle('hidden', config.kind !== 'ollama');
+}
+
+/**
+ * Show the Ollama URL used when the field is left empty
+ */
+async function loadOllamaUrlPlaceholder() {
+    try {
+        const { fallback } = await invoke('get_ollama_url');
+        document.getElementById('ollamaUrl').placeholder = fallback;
+    } catch (error) {
+        console.error('❌ Failed to load Ollama URL:', error);
+    }
+}
+
+/**
+ * Handle model server change
+ */
+async function handleBackendChange() {
+    const config = readBackendConfig();
+    const errorText = document.getElementById('backendConfigError');
+    document.getElementById('ollamaSettings').classList.toggle('hidden', config.kind !== 'ollama');
+    document.getElementById('openaiSettings').classList.toggle('hidden', config.kind !== 'openai');
+    
+    try {
+        console.log('🔄 Switching model server to:', config.kind);
+        applyBackendConfig(await invoke('update_backend_config', { config }));
+        errorText.classList.add('hidden');
+        
+        // Models differ between servers, so reload them
+        if (await testOllamaConnection()) {
+            await loadAvailableModels();
+        }
+    } catch (error) {
+        console.error('❌ Failed to switch model server:', error);
+        errorText.textContent = error;
+        errorText.classList.remove('hidden');
+        updateOllamaStatus('error', `Failed to switch server: ${error}`);
+    }
+}
+
+/**
+ * Add an editable row for a fallback target
+ */
+function addFallbackTargetRow(target) {
+    const container = document.getElementById('fallbackTargets');
+    const row = document.createElement('div');
+    row.className = 'grid grid-cols-[10rem_1fr_1fr_auto] gap-2 items-center';
+    
+    const kind = document.createElement('select');
+    kind.className = 'input text-sm target-kind';
+    kind.innerHTML = '<option value="ollama">Ollama</option><option value="openai">OpenAI-compatible</option>';
+    kind.value = target.backend.kind;
+    
+    const url = document.createElement('input');
+    url.className = 'input text-sm target-url';
+    url.type = 'text';
+    
+    const model = document.createElement('input');
+    model.className = 'input text-sm target-model';
+    model.type = 'text';
+    model.placeholder = 'Model';
+    model.value = target.model;
+    
+    const remove = document.createElement('button');
+    remove.className = 'text-red-400 hover:text-red-300 px-2';
+    remove.textContent = '✕';
+    remove.addEventListener('click', () => {
+        row.remove();
+        handleFallbackTargetsChange();
+    });
+    
+    const name = document.createElement('input');
+    name.className = 'input text-sm target-name col-span-2';
+    name.type = 'text';
+    name.placeholder = 'Name shown in notifications (optional)';
+    name.value = target.name;
+    
+    const apiKey = document.createElement('input');
+    apiKey.className = 'input text-sm target-api-key';
+    apiKey.type = 'password';
+    apiKey.placeholder = 'API key (optional)';
+    apiKey.value = target.backend.openai_api_key;
+    
+    // One URL field, which means the Ollama or the OpenAI-compatible URL
+    row.target = target;
+    const showKind = () => {
+        const openai = kind.value === 'openai';
+        url.value = openai ? row.target.backend.openai_url : row.target.backend.ollama_url;
+        url.placeholder = openai ? 'http://workstation:8080/v1' : 'Ollama URL (empty for the default)';
+        apiKey.classList.toggle('invisible', !openai);
+    };
+    showKind();
+    kind.addEventListener('change', () => {
+        row.target.backend.kind = kind.value;
+        showKind();
+        handleFallbackTargetsChange();
+    });
+    url.addEventListener('change', () => {
+        row.target.backend[kind.value === 'openai' ? 'openai_url' : 'ollama_url'] = url.value;
+        handleFallbackTargetsChange();
+    });
+    [model, name, apiKey].forEach(field => field.addEventListener('change', handleFallbackTargetsChange));
+    row.showKind = showKind;
+    
+    row.append(kind, url, model, remove, name, apiKey);
+    container.appendChild(row);
+}
+
+/**
+ * Save the fallback targets in the order shown
+ */
+async function handleFallbackTargetsChange() {
+    const errorText = document.getElementById('fallbackTargetsError');
+    const rows = Array.from(document.querySelectorAll('#fallbackTargets > div'));
+    const targets = rows.map(row => ({
+        name: row.querySelector('.target-name').value,
+        backend: {
+            ...row.target.backend,
+            kind: row.querySelector('.target-kind').value,
+            openai_api_key: row.querySelector('.target-api-key').value
+        },
+        model: row.querySelector('.target-model').value
+    }));
+    
+    try {
+        // Show the URLs as saved (normalized)
+        const saved = await invoke('update_fallback_targets', { targets });
+        rows.forEach((row, index) => {
+            row.target = saved[index];
+            row.showKind();
+        });
+        errorText.classList.add('hidden');
+        console.log('✅ Fallback targets updated');
+        await loadTargetStatus();
+    } catch (error) {
+        console.error('❌ Failed to update fallback targets:', error);
+        errorText.textContent = error;
+        errorText.classList.remove('hidden');
+    }
+}
+
+/**
+ * Show how the model server and each fallback target have been doing
+ */
+async function loadTargetStatus() {
+    const container = document.getElementById('targetStatus');
+    
+    try {
+        const statuses = await invoke('get_target_status');
+        container.replaceChildren(...statuses.map(status => {
+            const line = document.createElement('p');
+            const name = `${status.fallback ? 'Fallback' : 'Primary'}: ${status.label}${status.model ? ` · ${status.model}` : ''}`;
+            if (status.failures === 0) {
+                line.className = 'text-green-400';
+                line.textContent = `● ${name}`;
+            } else {
+                line.className = 'text-red-400';
+                const retry = status.retry_in_secs > 0 ? `, tried last for ${status.retry_in_secs}s more` : '';
+                line.textContent = `✕ ${name} — failed ${status.failures}× (${status.last_error})${retry}`;
+            }
+            return line;
+        }));
+        // The primary server alone is covered by the connection status
+        container.classList.toggle('hidden', statuses.length < 2);
+    } catch (error) {
+        console.error('❌ Failed to load target status:', error);
+    }
+}
+
+/**
+ * Handle text enhancement
+ */
+async function handleEnhance() {
+    if (isEnhancing) return;
+    
+    const inputText = document.getElementById('inputText').value.trim();
+    const outputTextarea = document.getElementById('outputText');
+    const enhanceBtn = document.getElementById('enhanceBtn');
+    const modelSelect = document.getElementById('modelSelect');
+    
+    if (!inputText) {
+        updateOllamaStatus('error', 'Please enter some text to enhance');
+        return;
+    }
+    
+    // Check if a valid model is selected
+    if (!modelSelect.value || modelSelect.value === 'No models available' || modelSelect.value === 'Failed to load models') {
+        updateOllamaStatus('error', 'No AI model available. Please check your Ollama installation.');
+        return;
+    }
+    
+    isEnhancing = true;
+    enhanceBtn.textContent = 'Enhancing...';
+    enhanceBtn.disabled = true;
+    document.getElementById('cancelBtn').classList.remove('hidden');
+    outputTextarea.value = '';
+    showCandidates([]);
+    
+    updateOllamaStatus('connecting', 'Enhancing text...');
+    
+    try {
+        console.log('🤖 Enhancing text:', inputText);
+        
+        // Get the currently selected model, unless a preset picks its own
+        const preset = document.getElementById('presetSelect').value || null;
+        const selectedModel = preset ? null : modelSelect.value;
+        
+        const count = parseInt(document.getElementById('candidateCount').value, 10) || 1;
+        if (count > 1) {
+            // Alternatives with different seeds, all generated at once
+            const candidates = await invoke('enhance_prompt_candidates', {
+                prompt: inputText,
+                count,
+                preset
+            });
+            showCandidates(candidates);
+            
+            const first = candidates.find(candidate => candidate.text != null);
+            if (!first) {
+                throw candidates[0]?.error ?? 'Every candidate failed';
+            }
+            outputTextarea.value = first.text;
+            updateOllamaStatus('connected', 'Candidates ready, pick one below');
+        } else {
+            // Text streams into the output via the enhance-delta listener
+            const enhanced = await invoke('enhance_prompt_stream', { 
+                prompt: inputText,
+                model: selectedModel,
+                preset
+            });
+            
+            outputTextarea.value = enhanced;
+            updateOllamaStatus('connected', 'Text enhanced successfully');
+        }
+        
+        console.log('✅ Enhancement complete');
+    } catch (error) {
+        console.error('❌ Enhancement failed:', error);
+        if (error === 'Enhancement cancelled') {
+            updateOllamaStatus('error', 'Enhancement cancelled');
+        } else {
+            outputTextarea.value = '';
+            updateOllamaStatus('error', `Enhancement failed: ${error}`);
+        }
+    } finally {
+        isEnhancing = false;
+        enhanceBtn.textContent = 'Enhance';
+        enhanceBtn.disabled = false;
+        document.getElementById('cancelBtn').classList.add('hidden');
+    }
+}
+
+/**
+ * List the candidates from a multi-candidate enhancement, each with a button
+ * that makes it the result and copies it
+ */
+function showCandidates(candidates) {
+    const list = document.getElementById('candidateList');
+    list.replaceChildren();
+    list.classList.toggle('hidden', candidates.length === 0);
+    
+    candidates.forEach((candidate, index) => {
+        const card = document.createElement('div');
+        card.className = 'bg-gray-700/50 border border-gray-600/50 rounded-lg p-4 space-y-2';
+        
+        const header = document.createElement('div');
+        header.className = 'flex justify-between items-center gap-3';
+        const meta = document.createElement('p');
+        meta.className = 'text-xs text-gray-400';
+        meta.textContent = [
+            `#${index + 1}`,
+            candidate.model,
+            candidate.seed != null ? `seed ${candidate.seed}` : null,
+            candidate.temperature != null ? `temperature ${candidate.temperature}` : null,
+            candidate.text != null ? `${(candidate.duration_ms / 1000).toFixed(1)}s` : null
+        ].filter(Boolean).join(' · ');
+        header.appendChild(meta);
+        
+        const body = document.createElement('p');
+        body.className = 'text-sm whitespace-pre-wrap';
+        if (candidate.text != null) {
+            body.classList.add('text-gray-200');
+            body.textContent = candidate.text;
+            
+            const useButton = document.createElement('button');
+            useButton.className = 'text-sm text-blue-400 hover:text-blue-300 font-medium transition-colors';
+            useButton.textContent = 'Use This';
+            useButton.addEventListener('click', async () => {
+                document.getElementById('outputText').value = candidate.text;
+                await handleCopy();
+            });
+            header.appendChild(useButton);
+        } else {
+            body.classList.add('text-red-400');
+            body.textContent = candidate.error;
+        }
+        
+        card.append(header, body);
+        list.appendChild(card);
+    });
+}
+
+/**
+ * One checkbox per available model, keeping the ones already ticked
+ */
+function showCompareModels(models) {
+    const container = document.getElementById('compareModels');
+    const checked = new Set(selectedCompareModels());
+    container.replaceChildren();
+    
+    models.forEach(model => {
+        const label = document.createElement('label');
+        label.className = 'flex items-center gap-2 text-sm text-gray-300';
+        const checkbox = document.createElement('input');
+        checkbox.type = 'checkbox';
+        checkbox.value = model;
+        checkbox.checked = checked.has(model);
+        const name = document.createElement('span');
+        name.className = 'truncate';
+        name.textContent = model;
+        label.append(checkbox, name);
+        container.appendChild(label);
+    });
+}
+
+/**
+ * The models ticked for comparison
+ */
+function selectedCompareModels() {
+    return Array.from(document.querySelectorAll('#compareModels input:checked'), checkbox => checkbox.value);
+}
+
+/**
+ * Format an optional number for the comparison table
+ */
+function formatStat(value, digits = 0) {
+    return value != null ? value.toFixed(digits) : '–';
+}
+
+/**
+ * Fill row `index` of the comparison table, and its output card, with a
+ * model's result
+ */
+function showComparisonResult(index, result) {
+    const row = document.getElementById('compareTable').children[index];
+    const output = document.getElementById('compareOutputs').children[index];
+    if (!row || !output) return;
+    
+    const stats = result.stats;
+    const failed = result.text == null;
+    const cells = [
+        result.model,
+        failed ? '–' : `${formatStat(result.duration_ms / 1000, 1)}s`,
+        stats.load_duration_ms != null ? `${formatStat(stats.load_duration_ms / 1000, 1)}s` : '–',
+        formatStat(stats.prompt_eval_count),
+        formatStat(stats.eval_count),
+        formatStat(result.tokens_per_second, 1),
+        failed ? '✕ Failed' : '✓ Done'
+    ];
+    Array.from(row.children).forEach((cell, i) => {
+        cell.textContent = cells[i];
+    });
+    row.lastChild.className = `py-2 ${failed ? 'text-red-400' : 'text-green-400'}`;
+    
+    const body = output.lastChild;
+    body.className = `text-sm whitespace-pre-wrap ${failed ? 'text-red-400' : 'text-gray-200'}`;
+    body.textContent = failed ? result.error : result.text;
+}
+
+/**
+ * Empty table rows and output cards for the models being compared
+ */
+function resetComparison(models) {
+    const table = document.getElementById('compareTable');
+    const outputs = document.getElementById('compareOutputs');
+    table.replaceChildren();
+    outputs.replaceChildren();
+    
+    models.forEach(model => {
+        const row = document.createElement('tr');
+        const cells = [model, '–', '–', '–', '–', '–', 'Running...'];
+        cells.forEach((text, i) => {
+            const cell = document.createElement('td');
+            cell.className = i === 0 ? 'py-2 pr-3 text-white' : i === cells.length - 1 ? 'py-2 text-gray-400' : 'py-2 pr-3 text-right text-gray-300';
+            cell.textContent = text;
+            row.appendChild(cell);
+        });
+        table.appendChild(row);
+        
+        const card = document.createElement('div');
+        card.className = 'bg-gray-700/50 border border-gray-600/50 rounded-lg p-4 space-y-2';
+        const title = document.createElement('p');
+        title.className = 'text-xs text-gray-400';
+        title.textContent = model;
+        const body = document.createElement('p');
+        body.className = 'text-sm text-gray-400';
+        body.textContent = 'Waiting for output...';
+        card.append(title, body);
+        outputs.appendChild(card);
+    });
+    document.getElementById('compareResults').classList.toggle('hidden', models.length === 0);
+}
+
+/**
+ * Run the test input through the ticked models, or cancel a running comparison
+ */
+async function handleCompare() {
+    if (isComparing) {
+        await handleCancel();
+        return;
+    }
+    
+    const prompt = document.getElementById('inputText').value.trim();
+    const models = selectedCompareModels();
+    const errorText = document.getElementById('compareError');
+    const compareBtn = document.getElementById('compareBtn');
+    
+    if (!prompt) {
+        errorText.textContent = 'Enter some input text in the test above first';
+        errorText.classList.remove('hidden');
+        return;
+    }
+    
+    isComparing = true;
+    comparison = null;
+    compareBtn.textContent = 'Cancel';
+    errorText.classList.add('hidden');
+    document.getElementById('compareReportPath').classList.add('hidden');
+    document.getElementById('copyReportBtn').classList.add('hidden');
+    document.getElementById('saveReportBtn').classList.add('hidden');
+    resetComparison(models);
+    
+    try {
+        console.log('⚖️ Comparing models:', models);
+        comparison = await invoke('compare_models', {
+            prompt,
+            models,
+            preset: document.getElementById('presetSelect').value || null,
+            concurrency: parseInt(document.getElementById('compareConcurrency').value, 10) || 1
+        });
+        comparison.results.forEach((result, index) => showComparisonResult(index, result));
+        document.getElementById('copyReportBtn').classList.remove('hidden');
+        document.getElementById('saveReportBtn').classList.remove('hidden');
+        console.log('✅ Comparison complete');
+    } catch (error) {
+        console.error('❌ Comparison failed:', error);
+        errorText.textContent = error;
+        errorText.classList.remove('hidden');
+        if (error !== 'Enhancement cancelled') {
+            resetComparison([]);
+        }
+    } finally {
+        isComparing = false;
+        compareBtn.textContent = 'Compare';
+    }
+}
+
+/**
+ * Copy the last comparison as a Markdown report
+ */
+async function handleCopyReport() {
+    if (!comparison) return;
+    
+    try {
+        const report = await invoke('format_comparison_report', { comparison });
+        await navigator.clipboard.writeText(report);
+        console.log('📋 Comparison report copied');
+    } catch (error) {
+        console.error('❌ Failed to copy report:', error);
+        const errorText = document.getElementById('compareError');
+        errorText.textContent = error;
+        errorText.classList.remove('hidden');
+    }
+}
+
+/**
+ * Save the last comparison as a Markdown report and show where it went
+ */
+async function handleSaveReport() {
+    if (!comparison) return;
+    const pathText = document.getElementById('compareReportPath');
+    
+    try {
+        const path = await invoke('save_comparison_report', { comparison });
+        pathText.textContent = `Report saved to ${path}`;
+        pathText.classList.remove('hidden');
+        console.log('💾 Comparison report saved:', path);
+    } catch (error) {
+        console.error('❌ Failed to save report:', error);
+        const errorText = document.getElementById('compareError');
+        errorText.textContent = error;
+        errorText.classList.remove('hidden');
+    }
+}
+
+/**
+ * Handle cancel of running enhancements
+ */
+async function handleCancel() {
+    try {
+        const cancelled = await invoke('cancel_enhancement', { jobId: null });
+        console.log(`⏹️ Cancelled ${cancelled} enhancement(s)`);
+    } catch (error) {
+        console.error('❌ Cancel failed:', error);
+    }
+}
+
+/**
+ * Handle cancel hotkey change
+ */
+async function handleCancelHotkeyChange() {
+    const shortcut = document.getElementById('cancelHotkey').value.trim();
+    const errorText = document.getElementById('cancelHotkeyError');
+    
+    try {
+        await invoke('set_cancel_hotkey', { shortcut: shortcut || null });
+        errorText.classList.add('hidden');
+        console.log('✅ Cancel hotkey updated');
+    } catch (error) {
+        console.error('❌ Failed to set cancel hotkey:', error);
+        errorText.textContent = error;
+        errorText.classList.remove('hidden');
+    }
+    await refreshHotkeyStatus();
+}
+
+/**
+ * Handle undo hotkey change
+ */
+async function handleUndoHotkeyChange() {
+    const shortcut = document.getElementById('undoHotkey').value.trim();
+    const errorText = document.getElementById('undoHotkeyError');
+    
+    try {
+        await invoke('set_undo_hotkey', { shortcut: shortcut || null });
+        errorText.classList.add('hidden');
+        console.log('✅ Undo hotkey updated');
+    } catch (error) {
+        console.error('❌ Failed to set undo hotkey:', error);
+        errorText.textContent = error;
+        errorText.classList.remove('hidden');
+    }
+    await refreshHotkeyStatus();
+}
+
+/**
+ * Handle enhance hotkey change
+ */
+async function handleEnhanceHotkeyChange() {
+    const shortcut = document.getElementById('enhanceHotkey').value.trim();
+    const errorText = document.getElementById('enhanceHotkeyError');
+    
+    try {
+        await invoke('set_enhance_hotkey', { shortcut: shortcut || null });
+        errorText.classList.add('hidden');
+        document.getElementById('enhanceHotkeyHint').textContent = shortcut || 'your hotkey';
+        console.log('✅ Enhance hotkey updated');
+    } catch (error) {
+        console.error('❌ Failed to set enhance hotkey:', error);
+        errorText.textContent = error;
+        errorText.classList.remove('hidden');
+    }
+    await refreshHotkeyStatus();
+}
+
+/**
+ * Warn about saved hotkeys that couldn't be registered
+ */
+function showHotkeyStatus(status) {
+    const failed = status.filter(hotkey => !hotkey.registered);
+    const warning = document.getElementById('hotkeyWarning');
+    
+    if (failed.length === 0) {
+        warning.classList.add('hidden');
+        return;
+    }
+    
+    const list = failed.map(hotkey => `${hotkey.shortcut} (${hotkey.label})`).join(', ');
+    document.getElementById('hotkeyWarningText').textContent =
+        `These shortcuts couldn't be registered, probably because another application uses them: ${list}`;
+    warning.classList.remove('hidden');
+}
+
+/**
+ * Reload which hotkeys are registered
+ */
+async function refreshHotkeyStatus() {
+    try {
+        showHotkeyStatus(await invoke('get_hotkey_status'));
+    } catch (error) {
+        console.error('❌ Failed to get hotkey status:', error);
+    }
+}
+
+/**
+ * Retry registering the hotkeys that failed
+ */
+async function handleRetryHotkeys() {
+    try {
+        showHotkeyStatus(await invoke('register_hotkeys'));
+    } catch (error) {
+        console.error('❌ Failed to register hotkeys:', error);
+    }
+}
+
+/**
+ * Handle clear action
+ */
+function handleClear() {
+    document.getElementById('inputText').value = '';
+    document.getElementById('outputText').value = '';
+    showCandidates([]);
+    updateOllamaStatus('connected', 'Connected to Ollama');
+    console.log('🧹 Text fields cleared');
+}
+
+/**
+ * Handle copy to clipboard
+ */
+async function handleCopy() {
+    const outputText = document.getElementById('outputText').value;
+    
+    if (!outputText || outputText === 'Processing...') {
+        updateOllamaStatus('error', 'No enhanced text to copy');
+        return;
+    }
+    
+    try {
+        await navigator.clipboard.writeText(outputText);
+        updateOllamaStatus('connected', 'Text copied to clipboard');
+        console.log('📋 Text copied to clipboard');
+    } catch (error) {
+        console.error('❌ Copy failed:', error);
+        updateOllamaStatus('error', 'Failed to copy text');
+    }
+}
+
+/**
+ * Load platform-specific autostart instructions
+ */
+async function loadPlatformInstructions() {
+    try {
+        console.log('🔄 Loading platform instructions...');
+        const platform = await invoke('get_platform');
+        
+        // Hide all instruction divs first
+        document.getElementById('macosInstructions').classList.add('hidden');
+        document.getElementById('windowsInstructions').classList.add('hidden');
+        document.getElementById('linuxInstructions').classList.add('hidden');
+        
+        // Show the appropriate instructions based on platform
+        switch (platform) {
+            case 'macos':
+                document.getElementById('macosInstructions').classList.remove('hidden');
+                updateAutostartStatus('connected', 'macOS detected');
+                break;
+            case 'windows':
+                document.getElementById('windowsInstructions').classList.remove('hidden');
+                updateAutostartStatus('connected', 'Windows detected');
+                break;
+            case 'linux':
+                document.getElementById('linuxInstructions').classList.remove('hidden');
+                updateAutostartStatus('connected', 'Linux detected');
+                break;
+            default:
+                updateAutostartStatus('error', 'Unknown platform');
+        }
+        
+        console.log('✅ Platform instructions loaded for:', platform);
+    } catch (error) {
+        console.error('❌ Failed to load platform instructions:', error);
+        updateAutostartStatus('error', 'Failed to detect platform');
+    }
+}
+
+/**
+ * Test Ollama connection
+ */
+async function testOllamaConnection() {
+    console.log('🔍 Testing Ollama connection...');
+    updateOllamaStatus('connecting', 'Testing connection...');
+    
+    try {
+        await invoke('test_ollama_connection');
+        updateOllamaStatus('connected', 'Connected to Ollama');
+        
+        // Hide Ollama not installed warning
+        document.getElementById('ollamaNotInstalled').classList.add('hidden');
+        
+        console.log('✅ Ollama connection successful');
+        return true;
+    } catch (error) {
+        console.error('❌ Ollama connection failed:', error);
+        updateOllamaStatus('error', `Connection failed: ${error}`);
+        
+        // Show Ollama not installed warning
+        document.getElementById('ollamaNotInstalled').classList.remove('hidden');
+        
+        return false;
+    }
+}
+
+/**
+ * Toggle install instructions visibility
+ */
+function toggleInstallInstructions() {
+    const instructions = document.getElementById('installInstructions');
+    const button = document.getElementById('toggleInstallInstructions');
+    
+    if (instructions.classList.contains('hidden')) {
+        instructions.classList.remove('hidden');
+        button.textContent = 'Hide Instructions ▲';
+    } else {
+        instructions.classList.add('hidden');
+        button.textContent = 'Show Instructions ▼';
+    }
+}
+
+/**
+ * Handle system prompt change
+ */
+async function handleSystemPromptChange() {
+    const systemPrompt = document.getElementById('systemPrompt').value;
+    
+    try {
+        await invoke('update_system_prompt', { prompt: systemPrompt });
+        console.log('✅ System prompt updated');
+    } catch (error) {
+        console.error('❌ Failed to update system prompt:', error);
+    }
+}
+
+/**
+ * Show the generation options of a model in the settings form
+ */
+async function loadGenerationOptions(model) {
+    try {
+        const options = await invoke('get_generation_options', { model });
+        document.getElementById('generationOptionsModel').textContent = model ? `for ${model}` : '';
+        document.getElementById('genTemperature').value = options.temperature ?? '';
+        document.getElementById('genNumCtx').value = options.num_ctx ?? '';
+        document.getElementById('genNumPredict').value = options.num_predict ?? '';
+        document.getElementById('genSeed').value = options.seed ?? '';
+        document.getElementById('genStop').value = (options.stop || []).join('\n');
+    } catch (error) {
+        console.error('❌ Failed to load generation options:', error);
+    }
+}
+
+/**
+ * Handle generation option edits for the selected model
+ */
+async function handleGenerationOptionsChange() {
+    const model = document.getElementById('modelSelect').value;
+    const errorText = document.getElementById('generationOptionsError');
+    if (!model) return;
+    
+    const numberOrNull = (id, parse) => {
+        const value = document.getElementById(id).value.trim();
+        return value === '' ? null : parse(value);
+    };
+    const options = {
+        temperature: numberOrNull('genTemperature', parseFloat),
+        num_ctx: numberOrNull('genNumCtx', v => parseInt(v, 10)),
+        num_predict: numberOrNull('genNumPredict', v => parseInt(v, 10)),
+        seed: numberOrNull('genSeed', v => parseInt(v, 10)),
+        stop: document.getElementById('genStop').value.split('\n').filter(line => line.length > 0)
+    };
+    
+    try {
+        await invoke('update_generation_options', { model, options });
+        errorText.classList.add('hidden');
+        console.log('✅ Generation options updated for', model);
+    } catch (error) {
+        console.error('❌ Failed to update generation options:', error);
+        errorText.textContent = error;
+        errorText.classList.remove('hidden');
+    }
+}
+
+/**
+ * Handle prompt format change
+ */
+async function handlePromptModeChange() {
+    const mode = document.getElementById('promptMode').value;
+    
+    try {
+        await invoke('update_prompt_mode', { mode });
+        console.log('✅ Prompt mode updated:', mode);
+    } catch (error) {
+        console.error('❌ Failed to update prompt mode:', error);
+    }
+}
+
+/**
+ * Add an editable few-shot example row
+ */
+function addFewShotExampleRow(example) {
+    const container = document.getElementById('fewShotExamples');
+    const row = document.createElement('div');
+    row.className = 'grid grid-cols-[1fr_1fr_auto] gap-2 items-start';
+    
+    const input = document.createElement('textarea');
+    input.className = 'input text-sm few-shot-input';
+    input.rows = 2;
+    input.placeholder = 'Example input';
+    input.value = example.input;
+    
+    const output = document.createElement('textarea');
+    output.className = 'input text-sm few-shot-output';
+    output.rows = 2;
+    output.placeholder = 'Expected enhanced output';
+    output.value = example.output;
+    
+    const remove = document.createElement('button');
+    remove.className = 'text-red-400 hover:text-red-300 px-2 py-3';
+    remove.textContent = '✕';
+    remove.addEventListener('click', () => {
+        row.remove();
+        handleFewShotExamplesChange();
+    });
+    
+    input.addEventListener('change', handleFewShotExamplesChange);
+    output.addEventListener('change', handleFewShotExamplesChange);
+    
+    row.append(input, output, remove);
+    container.appendChild(row);
+}
+
+/**
+ * Handle few-shot example edits
+ */
+async function handleFewShotExamplesChange() {
+    const examples = Array.from(document.querySelectorAll('#fewShotExamples > div')).map(row => ({
+        input: row.querySelector('.few-shot-input').value,
+        output: row.querySelector('.few-shot-output').value
+    }));
+    
+    try {
+        await invoke('update_few_shot_examples', { examples });
+        console.log('✅ Few-shot examples updated');
+    } catch (error) {
+        console.error('❌ Failed to update few-shot examples:', error);
+    }
+}
+
+/**
+ * Add an editable preset card
+ */
+function addPresetRow(preset) {
+    const container = document.getElementById('presetList');
+    const row = document.createElement('div');
+    row.className = 'space-y-2 p-3 rounded-lg border border-gray-700';
+    
+    const field = (className, placeholder, value) => {
+        const input = document.createElement('input');
+        input.className = `input text-sm ${className}`;
+        input.type = 'text';
+        input.placeholder = placeholder;
+        input.value = value ?? '';
+        input.addEventListener('change', () => handlePresetChange(row, preset));
+        return input;
+    };
+    
+    const header = document.createElement('div');
+    header.className = 'grid grid-cols-[1fr_1fr_auto] gap-2 items-start';
+    const name = field('preset-name', 'Name (e.g. Fix grammar)', preset.name);
+    const hotkey = field('preset-hotkey', 'Hotkey (optional)', preset.hotkey);
+    const remove = document.createElement('button');
+    remove.className = 'text-red-400 hover:text-red-300 px-2 py-3';
+    remove.textContent = '✕';
+    remove.addEventListener('click', () => handleDeletePreset(row, preset));
+    header.append(name, hotkey, remove);
+    
+    const settings = document.createElement('div');
+    settings.className = 'grid grid-cols-2 gap-2';
+    const model = field('preset-model', 'Model (selected model)', preset.model);
+    const temperature = field('preset-temperature', 'Temperature (model default)', preset.options?.temperature);
+    settings.append(model, temperature);
+    
+    const prompt = document.createElement('textarea');
+    prompt.className = 'input w-full text-sm preset-prompt';
+    prompt.rows = 3;
+    prompt.placeholder = 'System prompt for this preset';
+    prompt.value = preset.system_prompt;
+    prompt.addEventListener('change', () => handlePresetChange(row, preset));
+    
+    const errorText = document.createElement('p');
+    errorText.className = 'hidden text-sm text-red-400 preset-error';
+    
+    row.append(header, settings, prompt, errorText);
+    container.appendChild(row);
+}
+
+/**
+ * Save a preset after one of its fields changed
+ */
+async function handlePresetChange(row, preset) {
+    const value = selector => row.querySelector(selector).value.trim();
+    const temperature = value('.preset-temperature');
+    const updated = {
+        ...preset,
+        name: value('.preset-name'),
+        hotkey: value('.preset-hotkey') || null,
+        model: value('.preset-model'),
+        system_prompt: row.querySelector('.preset-prompt').value,
+        options: { ...preset.options, temperature: temperature === '' ? null : parseFloat(temperature) }
+    };
+    const errorText = row.querySelector('.preset-error');
+    
+    // Don't save a new preset until it has a name and a prompt
+    if (!preset.id && (!updated.name || !updated.system_prompt.trim())) return;
+    
+    try {
+        Object.assign(preset, await invoke('save_preset', { preset: updated }));
+        errorText.classList.add('hidden');
+        updatePresetSelect();
+        await refreshHotkeyStatus();
+        console.log('✅ Preset saved:', preset.name);
+    } catch (error) {
+        console.error('❌ Failed to save preset:', error);
+        errorText.textContent = error;
+        errorText.classList.remove('hidden');
+    }
+}
+
+/**
+ * Delete a preset and its card
+ */
+async function handleDeletePreset(row, preset) {
+    try {
+        if (preset.id) {
+            await invoke('delete_preset', { id: preset.id });
+        }
+        presets = presets.filter(p => p !== preset);
+        if (preset.id === activePresetId) activePresetId = null;
+        row.remove();
+        updatePresetSelect();
+        console.log('✅ Preset deleted');
+    } catch (error) {
+        console.error('❌ Failed to delete preset:', error);
+    }
+}
+
+/**
+ * Fill the test area's and the enhance hotkey's preset dropdowns with the
+ * saved presets
+ */
+function updatePresetSelect() {
+    const fill = (select, selected) => {
+        select.innerHTML = '<option value="">Default system prompt</option>';
+        presets.filter(p => p.id).forEach(preset => {
+            const option = document.createElement('option');
+            option.value = preset.id;
+            option.textContent = preset.name;
+            select.appendChild(option);
+        });
+        select.value = presets.some(p => p.id === selected) ? selected : '';
+    };
+    const presetSelect = document.getElementById('presetSelect');
+    fill(presetSelect, presetSelect.value);
+    fill(document.getElementById('activePreset'), activePresetId);
+}
+
+/**
+ * Choose the preset the enhance hotkey uses
+ */
+async function handleActivePresetChange() {
+    const id = document.getElementById('activePreset').value || null;
+    
+    try {
+        await invoke('set_active_preset', { id });
+        activePresetId = id;
+        console.log('✅ Active preset updated:', id);
+    } catch (error) {
+        console.error('❌ Failed to update active preset:', error);
+    }
+}
+
 /**
- * Handle model change
+ * Show the history, filtered by the search box
  */
-async function handleModelChange() {
-    const modelSelect = document.getElementById('modelSelect');
-    const selectedModel = modelSelect.value;
+async function loadHistory() {
+    const query = document.getElementById('historySearch').value.trim();
     
     try {
-        console.log('🔄 Changing model to:', selectedModel);
-        updateModelStatus('connecting', `Switching to ${selectedModel}...`);
+        const entries = query
+            ? await invoke('search_history', { query })
+            : await invoke('get_history');
         
-        // Save the setting
-        localStorage.setItem('selectedModel', selectedModel);
-        
-        // Update the model in the backend
-        await invoke('update_model', { model: selectedModel });
-        
-        // Test the new model with a simple request
-        await invoke('test_ollama_connection');
-        
-        updateModelStatus('connected', selectedModel);
-        console.log('✅ Model changed successfully');
+        const list = document.getElementById('historyList');
+        list.innerHTML = '';
+        entries.forEach(entry => list.appendChild(createHistoryRow(entry)));
+        document.getElementById('historyEmpty').classList.toggle('hidden', entries.length > 0);
     } catch (error) {
-        console.error('❌ Model change failed:', error);
-        updateModelStatus('error', `Failed to switch to ${selectedModel}`);
+        console.error('❌ Failed to load history:', error);
     }
 }
 
 /**
- * Handle text enhancement
+ * Build the card for one history entry
  */
-async function handleEnhance() {
-    if (isEnhancing) return;
+function createHistoryRow(entry) {
+    const row = document.createElement('div');
+    row.className = 'space-y-2 p-3 rounded-lg border border-gray-700';
     
-    const inputText = document.getElementById('inputText').value.trim();
-    const outputTextarea = document.getElementById('outputText');
-    const enhanceBtn = document.getElementById('enhanceBtn');
-    const modelSelect = document.getElementById('modelSelect');
+    const meta = document.createElement('div');
+    meta.className = 'flex justify-between items-center text-xs text-gray-400';
+    const details = [
+        new Date(entry.created_at).toLocaleString(),
+        entry.model,
+        entry.preset,
+        `${(entry.duration_ms / 1000).toFixed(1)}s`,
+        entry.eval_count ? `${entry.eval_count} tokens` : null
+    ].filter(Boolean).join(' · ');
+    const info = document.createElement('span');
+    info.textContent = (entry.pinned ? '📌 ' : '') + details;
     
-    if (!inputText) {
-        updateOllamaStatus('error', 'Please enter some text to enhance');
-        return;
-    }
+    const actions = document.createElement('div');
+    actions.className = 'flex gap-3';
+    const action = (label, handler) => {
+        const button = document.createElement('button');
+        button.className = 'text-blue-400 hover:text-blue-300';
+        button.textContent = label;
+        button.addEventListener('click', handler);
+        actions.appendChild(button);
+    };
+    action('Copy', () => invoke('copy_history_entry', { id: entry.id }));
+    action('Copy Original', () => invoke('copy_history_entry', { id: entry.id, original: true }));
+    action(entry.pinned ? 'Unpin' : 'Pin', async () => {
+        await invoke('set_history_pinned', { id: entry.id, pinned: !entry.pinned });
+        await loadHistory();
+    });
+    action('Delete', async () => {
+        await invoke('delete_history_entry', { id: entry.id });
+        await loadHistory();
+    });
+    meta.append(info, actions);
     
-    // Check if a valid model is selected
-    if (!modelSelect.value || modelSelect.value === 'No models available' || modelSelect.value === 'Failed to load models') {
-        updateOllamaStatus('error', 'No AI model available. Please check your Ollama installation.');
-        return;
-    }
+    const original = document.createElement('p');
+    original.className = 'text-sm text-gray-400 truncate';
+    original.textContent = entry.original;
     
-    isEnhancing = true;
-    enhanceBtn.textContent = 'Enhancing...';
-    enhanceBtn.disabled = true;
-    outputTextarea.value = 'Processing...';
+    const enhanced = document.createElement('p');
+    enhanced.className = 'text-sm text-white line-clamp-3 whitespace-pre-wrap';
+    enhanced.textContent = entry.enhanced;
     
-    updateOllamaStatus('connecting', 'Enhancing text...');
+    row.append(meta, original, enhanced);
+    return row;
+}
+
+/**
+ * Handle history retention setting changes
+ */
+async function handleHistorySettingsChange() {
+    const positiveOrNull = id => {
+        const value = parseInt(document.getElementById(id).value, 10);
+        return value > 0 ? value : null;
+    };
+    const settings = {
+        enabled: document.getElementById('historyEnabled').checked,
+        max_entries: positiveOrNull('historyMaxEntries'),
+        max_age_days: positiveOrNull('historyMaxAgeDays')
+    };
     
     try {
-        console.log('🤖 Enhancing text:', inputText);
-        
-        // Get the currently selected model
-        const selectedModel = modelSelect.value;
-        
-        const enhanced = await invoke('enhance_prompt', { 
-            prompt: inputText,
-            model: selectedModel
-        });
-        
-        outputTextarea.value = enhanced;
-        updateOllamaStatus('connected', 'Text enhanced successfully');
-        
-        console.log('✅ Enhancement complete');
+        await invoke('update_history_settings', { settings });
+        await loadHistory();
+        console.log('✅ History settings updated');
     } catch (error) {
-        console.error('❌ Enhancement failed:', error);
-        outputTextarea.value = '';
-        updateOllamaStatus('error', `Enhancement failed: ${error}`);
-    } finally {
-        isEnhancing = false;
-        enhanceBtn.textContent = 'Enhance';
-        enhanceBtn.disabled = false;
+        console.error('❌ Failed to update history settings:', error);
     }
 }
 
 /**
- * Handle clear action
+ * Show the clipboard settings and the backend in use
  */
-function handleClear() {
-    document.getElementById('inputText').value = '';
-    document.getElementById('outputText').value = '';
-    updateOllamaStatus('connected', 'Connected to Ollama');
-    console.log('🧹 Text fields cleared');
+function showClipboardStatus(status) {
+    const names = { 'plugin': 'built-in', 'wl-clipboard': 'wl-clipboard', 'xclip': 'xclip' };
+    document.getElementById('clipboardSource').value = status.settings.source;
+    document.getElementById('clipboardBackend').value = status.settings.backend;
+    document.getElementById('clipboardBackendInUse').textContent = names[status.backend] ?? status.backend;
 }
 
 /**
- * Handle copy to clipboard
+ * Load the clipboard settings, which only matter on Linux
  */
-async function handleCopy() {
-    const outputText = document.getElementById('outputText').value;
+async function loadClipboardSettings() {
+    try {
+        if (await invoke('get_platform') !== 'linux') return;
+        document.getElementById('clipboardSettings').classList.remove('hidden');
+        showClipboardStatus(await invoke('get_clipboard_settings'));
+    } catch (error) {
+        console.error('❌ Failed to load clipboard settings:', error);
+    }
+}
+
+/**
+ * Save where the hotkey reads text from and which clipboard tool it uses
+ */
+async function handleClipboardSettingsChange() {
+    const errorText = document.getElementById('clipboardError');
+    const settings = {
+        source: document.getElementById('clipboardSource').value,
+        backend: document.getElementById('clipboardBackend').value
+    };
     
-    if (!outputText || outputText === 'Processing...') {
-        updateOllamaStatus('error', 'No enhanced text to copy');
-        return;
+    try {
+        showClipboardStatus(await invoke('update_clipboard_settings', { settings }));
+        errorText.classList.add('hidden');
+        console.log('✅ Clipboard settings updated');
+    } catch (error) {
+        console.error('❌ Failed to update clipboard settings:', error);
+        errorText.textContent = error;
+        errorText.classList.remove('hidden');
     }
+}
+
+/**
+ * Save the models to use when the selected one isn't installed
+ */
+async function handleFallbackModelsChange() {
+    const field = document.getElementById('fallbackModels');
+    const errorText = document.getElementById('fallbackModelsError');
+    const models = field.value.split(',').map(model => model.trim()).filter(Boolean);
     
     try {
-        await navigator.clipboard.writeText(outputText);
-        updateOllamaStatus('connected', 'Text copied to clipboard');
-        console.log('📋 Text copied to clipboard');
+        const saved = await invoke('update_fallback_models', { models });
+        field.value = saved.join(', ');
+        errorText.classList.add('hidden');
+        console.log('✅ Fallback models updated');
     } catch (error) {
-        console.error('❌ Copy failed:', error);
-        updateOllamaStatus('error', 'Failed to copy text');
+        console.error('❌ Failed to update fallback models:', error);
+        errorText.textContent = error;
+        errorText.classList.remove('hidden');
     }
 }
 
 /**
- * Load platform-specific autostart instructions
+ * Show the outcome of a model loading action
  */
-async function loadPlatformInstructions() {
+function showModelLoadingResult(error, status = '') {
+    const errorText = document.getElementById('modelLoadingError');
+    const statusText = document.getElementById('modelLoadingStatus');
+    errorText.textContent = error ?? '';
+    errorText.classList.toggle('hidden', !error);
+    statusText.textContent = status;
+    statusText.classList.toggle('hidden', !status);
+}
+
+/**
+ * Save whether the model is preloaded and how long it stays loaded
+ */
+async function handleModelLoadingSettingsChange() {
+    const settings = {
+        preload: document.getElementById('preloadModel').checked,
+        keep_alive: document.getElementById('keepAlive').value.trim()
+    };
+    
     try {
-        console.log('🔄 Loading platform instructions...');
-        const platform = await invoke('get_platform');
-        
-        // Hide all instruction divs first
-        document.getElementById('macosInstructions').classList.add('hidden');
-        document.getElementById('windowsInstructions').classList.add('hidden');
-        document.getElementById('linuxInstructions').classList.add('hidden');
-        
-        // Show the appropriate instructions based on platform
-        switch (platform) {
-            case 'macos':
-                document.getElementById('macosInstructions').classList.remove('hidden');
-                updateAutostartStatus('connected', 'macOS detected');
-                break;
-            case 'windows':
-                document.getElementById('windowsInstructions').classList.remove('hidden');
-                updateAutostartStatus('connected', 'Windows detected');
-                break;
-            case 'linux':
-                document.getElementById('linuxInstructions').classList.remove('hidden');
-                updateAutostartStatus('connected', 'Linux detected');
-                break;
-            default:
-                updateAutostartStatus('error', 'Unknown platform');
-        }
-        
-        console.log('✅ Platform instructions loaded for:', platform);
+        await invoke('update_model_loading_settings', { settings });
+        showModelLoadingResult(null);
+        console.log('✅ Model loading settings updated');
     } catch (error) {
-        console.error('❌ Failed to load platform instructions:', error);
-        updateAutostartStatus('error', 'Failed to detect platform');
+        console.error('❌ Failed to update model loading settings:', error);
+        showModelLoadingResult(error);
     }
 }
 
 /**
- * Test Ollama connection
+ * Unload the models the server holds in memory
  */
-async function testOllamaConnection() {
-    console.log('🔍 Testing Ollama connection...');
-    updateOllamaStatus('connecting', 'Testing connection...');
+async function handleUnloadModels() {
+    const button = document.getElementById('unloadModelsBtn');
+    button.disabled = true;
     
     try {
-        await invoke('test_ollama_connection');
-        updateOllamaStatus('connected', 'Connected to Ollama');
-        
-        // Hide Ollama not installed warning
-        document.getElementById('ollamaNotInstalled').classList.add('hidden');
-        
-        console.log('✅ Ollama connection successful');
-        return true;
+        const models = await invoke('unload_models');
+        showModelLoadingResult(null, models.length ? `Unloaded ${models.join(', ')}` : 'No model was loaded');
+        console.log('💤 Unloaded models:', models);
     } catch (error) {
-        console.error('❌ Ollama connection failed:', error);
-        updateOllamaStatus('error', `Connection failed: ${error}`);
-        
-        // Show Ollama not installed warning
-        document.getElementById('ollamaNotInstalled').classList.remove('hidden');
-        
-        return false;
+        console.error('❌ Failed to unload models:', error);
+        showModelLoadingResult(error);
+    } finally {
+        button.disabled = false;
     }
 }
 
 /**
- * Toggle install instructions visibility
+ * Save whether hotkey results are reviewed first, and how many candidates to show
  */
-function toggleInstallInstructions() {
-    const instructions = document.getElementById('installInstructions');
-    const button = document.getElementById('toggleInstallInstructions');
+async function handleReviewSettingsChange() {
+    const errorText = document.getElementById('reviewSettingsError');
+    const settings = {
+        enabled: document.getElementById('reviewEnabled').checked,
+        candidates: parseInt(document.getElementById('reviewCandidates').value, 10) || 1,
+        models: document.getElementById('reviewModels').value.split(',').map(model => model.trim()).filter(Boolean)
+    };
     
-    if (instructions.classList.contains('hidden')) {
-        instructions.classList.remove('hidden');
-        button.textContent = 'Hide Instructions ▲';
-    } else {
-        instructions.classList.add('hidden');
-        button.textContent = 'Show Instructions ▼';
+    try {
+        await invoke('update_review_settings', { settings });
+        errorText.classList.add('hidden');
+        console.log('✅ Review settings updated');
+    } catch (error) {
+        console.error('❌ Failed to update review settings:', error);
+        errorText.textContent = error;
+        errorText.classList.remove('hidden');
     }
 }
 
 /**
- * Handle system prompt change
+ * Show the auto-paste settings and the keystroke tools that were found
  */
-async function handleSystemPromptChange() {
-    const systemPrompt = document.getElementById('systemPrompt').value;
+function showAutoPasteStatus(status) {
+    document.getElementById('autoPasteEnabled').checked = status.settings.enabled;
+    document.getElementById('autoPasteRestore').checked = status.settings.restore_clipboard;
+    document.getElementById('autoPasteBackend').value = status.settings.backend;
+    document.getElementById('autoPasteDelay').value = status.settings.delay_ms;
+    document.getElementById('autoPasteAvailable').textContent = status.available.join(', ') || 'none';
+}
+
+/**
+ * Load the auto-paste settings from the backend
+ */
+async function loadAutoPasteSettings() {
+    try {
+        showAutoPasteStatus(await invoke('get_auto_paste_settings'));
+    } catch (error) {
+        console.error('❌ Failed to load auto-paste settings:', error);
+    }
+}
+
+/**
+ * Save whether the hotkey copies and pastes by itself, and how
+ */
+async function handleAutoPasteSettingsChange() {
+    const errorText = document.getElementById('autoPasteError');
+    const settings = {
+        enabled: document.getElementById('autoPasteEnabled').checked,
+        backend: document.getElementById('autoPasteBackend').value,
+        delay_ms: parseInt(document.getElementById('autoPasteDelay').value, 10) || 0,
+        restore_clipboard: document.getElementById('autoPasteRestore').checked
+    };
     
     try {
-        await invoke('update_system_prompt', { prompt: systemPrompt });
-        console.log('✅ System prompt updated');
-        
-        // Save to localStorage
-        localStorage.setItem('systemPrompt', systemPrompt);
+        showAutoPasteStatus(await invoke('update_auto_paste_settings', { settings }));
+        errorText.classList.add('hidden');
+        console.log('✅ Auto-paste settings updated');
     } catch (error) {
-        console.error('❌ Failed to update system prompt:', error);
+        console.error('❌ Failed to update auto-paste settings:', error);
+        errorText.textContent = error;
+        errorText.classList.remove('hidden');
+    }
+}
+
+/**
+ * Show whether the HTTP API is running, its URL and its token
+ */
+function showHttpApiStatus(status) {
+    document.getElementById('httpApiEnabled').checked = status.settings.enabled;
+    document.getElementById('httpApiPort').value = status.settings.port;
+    document.getElementById('httpApiDetails').classList.toggle('hidden', !status.running);
+    document.getElementById('httpApiUrl').textContent = status.url ?? '';
+    document.getElementById('httpApiToken').value = status.token ?? '';
+}
+
+/**
+ * Load the HTTP API status from the backend
+ */
+async function loadHttpApiStatus() {
+    try {
+        showHttpApiStatus(await invoke('get_http_api_status'));
+    } catch (error) {
+        console.error('❌ Failed to load HTTP API status:', error);
+    }
+}
+
+/**
+ * Turn the HTTP API on or off, or move it to another port
+ */
+async function handleHttpApiSettingsChange() {
+    const errorText = document.getElementById('httpApiError');
+    const settings = {
+        enabled: document.getElementById('httpApiEnabled').checked,
+        port: parseInt(document.getElementById('httpApiPort').value, 10) || 0
+    };
+    
+    try {
+        showHttpApiStatus(await invoke('update_http_api_settings', { settings }));
+        errorText.classList.add('hidden');
+        console.log('✅ HTTP API settings updated');
+    } catch (error) {
+        console.error('❌ Failed to update HTTP API settings:', error);
+        errorText.textContent = error;
+        errorText.classList.remove('hidden');
+        await loadHttpApiStatus();
+    }
+}
+
+/**
+ * Copy the HTTP API token to the clipboard
+ */
+async function handleCopyHttpApiToken() {
+    try {
+        await navigator.clipboard.writeText(document.getElementById('httpApiToken').value);
+        console.log('✅ HTTP API token copied');
+    } catch (error) {
+        console.error('❌ Failed to copy HTTP API token:', error);
+    }
+}
+
+/**
+ * Replace the HTTP API token, locking out clients with the old one
+ */
+async function handleRegenerateHttpApiToken() {
+    try {
+        showHttpApiStatus(await invoke('regenerate_http_api_token'));
+        console.log('✅ HTTP API token replaced');
+    } catch (error) {
+        console.error('❌ Failed to replace HTTP API token:', error);
+    }
+}
+
+/**
+ * Delete every unpinned history entry
+ */
+async function handleClearHistory() {
+    try {
+        const deleted = await invoke('clear_history');
+        await loadHistory();
+        console.log(`✅ Cleared ${deleted} history entries`);
+    } catch (error) {
+        console.error('❌ Failed to clear history:', error);
     }
 }
 
@@ -296,9 +1764,6 @@ async function handleResetSystemPrompt() {
         await invoke('reset_system_prompt');
         console.log('✅ System prompt reset to default');
         
-        // Remove from localStorage
-        localStorage.removeItem('systemPrompt');
-        
         // Show default prompt in textarea
         try {
             const defaultPrompt = await invoke('get_system_prompt');
@@ -323,7 +1788,8 @@ async function loadAvailableModels() {
         console.log('📋 Loading available models...');
         updateModelStatus('connecting', 'Loading models...');
         
-        const models = await invoke('get_available_models');
+        const modelInfo = await invoke('get_model_info');
+        const models = modelInfo.map(info => info.name);
         
         const modelSelect = document.getElementById('modelSelect');
         
@@ -344,6 +1810,7 @@ async function loadAvailableModels() {
             option.textContent = 'No models available';
             option.disabled = true;
             modelSelect.appendChild(option);
+            showCompareModels([]);
             
             return;
         }
@@ -352,32 +1819,35 @@ async function loadAvailableModels() {
         document.getElementById('modelWarning').classList.add('hidden');
         document.getElementById('ollamaNotInstalled').classList.add('hidden');
         
-        // Add models to dropdown (models is an array of strings)
-        models.forEach(modelName => {
+        // Add models to dropdown, with their size and quantization when known
+        modelInfo.forEach(info => {
             const option = document.createElement('option');
-            option.value = modelName;
-            option.textContent = modelName;
+            option.value = info.name;
+            option.textContent = [info.name, describeModel(info)].filter(Boolean).join(' — ');
             modelSelect.appendChild(option);
         });
+        showCompareModels(models);
         
-        // Restore saved selection or use first available model
-        const savedModel = localStorage.getItem('selectedModel');
+        // Restore saved selection or let the backend choose one
+        const { model: savedModel } = await invoke('get_settings');
         let selectedModel;
+        let statusText;
         
         if (savedModel && models.includes(savedModel)) {
             // Use saved model if it's still available
             selectedModel = savedModel;
+            statusText = selectedModel;
         } else {
-            // Use first available model
-            selectedModel = models[0];
-            localStorage.setItem('selectedModel', selectedModel);
+            // The fallback list, or else the best installed model
+            const choice = await invoke('set_initial_model');
+            selectedModel = choice.model;
+            statusText = `${selectedModel} (${MODEL_RULES[choice.rule]})`;
         }
         
         modelSelect.value = selectedModel;
-        updateModelStatus('connected', selectedModel);
-        
-        // Set the initial model in the backend
-        await invoke('set_initial_model');
+        updateModelStatus('connected', statusText);
+        await loadGenerationOptions(selectedModel);
+        await loadModelDetails(selectedModel);
         
         console.log(`✅ Loaded ${models.length} models, using: ${selectedModel}`);
     } catch (error) {
@@ -506,41 +1976,103 @@ function updateModelStatus(status, message) {
 }
 
 /**
- * Load settings from localStorage
+ * Settings the webview used to keep in localStorage before they moved to the
+ * backend's settings file
+ */
+const LEGACY_SETTINGS_KEYS = [
+    'selectedModel',
+    'systemPrompt'
+];
+
+/**
+ * Move any settings left in localStorage into the backend's settings file
+ */
+async function importLegacySettings() {
+    const legacy = {};
+    for (const key of LEGACY_SETTINGS_KEYS) {
+        const value = localStorage.getItem(key);
+        if (value !== null) {
+            legacy[key] = value;
+        }
+    }
+    if (Object.keys(legacy).length === 0) return;
+    
+    console.log('📦 Importing settings from localStorage...');
+    await invoke('import_legacy_settings', { legacy });
+    
+    LEGACY_SETTINGS_KEYS.forEach(key => localStorage.removeItem(key));
+    console.log('✅ Legacy settings imported');
+}
+
+/**
+ * Load settings from the backend
  */
 async function loadSettings() {
     console.log('📱 Loading settings...');
     
     try {
+        await importLegacySettings();
+        
+        const settings = await invoke('get_settings');
+        
+        // Load model server selection
+        applyBackendConfig(settings.backend);
+        await loadOllamaUrlPlaceholder();
+        
+        // Load fallback targets
+        settings.fallback_targets.forEach(addFallbackTargetRow);
+        await loadTargetStatus();
+        
+        // Load prompt format and few-shot examples
+        document.getElementById('promptMode').value = settings.prompt_mode;
+        settings.few_shot_examples.forEach(addFewShotExampleRow);
+        
+        // Load presets
+        presets = settings.presets;
+        activePresetId = settings.active_preset;
+        presets.forEach(addPresetRow);
+        updatePresetSelect();
+        
+        // Load history
+        document.getElementById('historyEnabled').checked = settings.history.enabled;
+        document.getElementById('historyMaxEntries').value = settings.history.max_entries ?? '';
+        document.getElementById('historyMaxAgeDays').value = settings.history.max_age_days ?? '';
+        await loadHistory();
+        
+        // Load fallback models
+        document.getElementById('fallbackModels').value = settings.fallback_models.join(', ');
+        
+        // Load model loading
+        document.getElementById('preloadModel').checked = settings.model_loading.preload;
+        document.getElementById('keepAlive').value = settings.model_loading.keep_alive;
+        
+        // Load review
+        document.getElementById('reviewEnabled').checked = settings.review.enabled;
+        document.getElementById('reviewCandidates').value = settings.review.candidates;
+        document.getElementById('reviewModels').value = settings.review.models.join(', ');
+        
+        // Load clipboard, auto-paste and HTTP API
+        await loadClipboardSettings();
+        await loadAutoPasteSettings();
+        await loadHttpApiStatus();
+        
+        // Load hotkeys
+        document.getElementById('enhanceHotkey').value = settings.enhance_hotkey || '';
+        document.getElementById('enhanceHotkeyHint').textContent = settings.enhance_hotkey || 'your hotkey';
+        document.getElementById('cancelHotkey').value = settings.cancel_hotkey || '';
+        document.getElementById('undoHotkey').value = settings.undo_hotkey || '';
+        await refreshHotkeyStatus();
+        
         // Load model selection
-        const savedModel = localStorage.getItem('selectedModel');
-        if (savedModel) {
-            const modelSelect = document.getElementById('modelSelect');
-            modelSelect.value = savedModel;
-            
-            // Update the backend model to match the saved setting
-            await invoke('update_model', { model: savedModel });
+        if (settings.model) {
+            document.getElementById('modelSelect').value = settings.model;
         }
         
-        // Load system prompt
-        const savedSystemPrompt = localStorage.getItem('systemPrompt');
-        const systemPromptTextarea = document.getElementById('systemPrompt');
-        
-        if (savedSystemPrompt) {
-            // Use saved custom prompt
-            systemPromptTextarea.value = savedSystemPrompt;
-            
-            // Update the backend system prompt to match the saved setting
-            await invoke('update_system_prompt', { prompt: savedSystemPrompt });
-        } else {
-            // Show default prompt in textarea (but don't save it as custom)
-            try {
-                const defaultPrompt = await invoke('get_system_prompt');
-                systemPromptTextarea.value = defaultPrompt;
-                console.log('📝 Loaded default system prompt into textarea');
-            } catch (error) {
-                console.error('❌ Failed to load default system prompt:', error);
-            }
+        // Load system prompt (the default one if no custom prompt is set)
+        try {
+            document.getElementById('systemPrompt').value = await invoke('get_system_prompt');
+        } catch (error) {
+            console.error('❌ Failed to load system prompt:', error);
         }
         
         // Note: Autostart status is checked from system on startup
@@ -552,25 +2084,6 @@ async function loadSettings() {
     }
 }
 
-/**
- * Save settings to localStorage
- */
-function saveSettings() {
-    console.log('💾 Saving settings...');
-    
-    try {
-        // Save model selection
-        const modelSelect = document.getElementById('modelSelect');
-        localStorage.setItem('selectedModel', modelSelect.value);
-        
-        console.log('✅ Settings saved');
-        updateOllamaStatus('connected', 'Settings saved');
-    } catch (error) {
-        console.error('❌ Failed to save settings:', error);
-        updateOllamaStatus('error', 'Failed to save settings');
-    }
-}
-
 // Initialize when DOM is loaded
 if (document.readyState === 'loading') {
     document.addEventListener('DOMContentLoaded', init);
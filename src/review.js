@@ -0,0 +1,316 @@
+// Tauri 2 global API
+const { invoke } = window.__TAURI__.core;
+const { listen } = window.__TAURI__.event;
+
+// Diffs bigger than this (words × words) are skipped to keep the window responsive
+const MAX_DIFF_CELLS = 4000000;
+// How long typing has to pause before the diff follows the edits
+const EDIT_DIFF_DELAY_MS = 300;
+
+let review = null;
+let selected = 0;
+let editing = false;
+// Edited text by candidate index
+let edits = {};
+// The last diff computed, reused while its texts stay the same
+let lastDiff = null;
+let diffTimer = null;
+
+/**
+ * Initialize the review window
+ */
+async function init() {
+    document.getElementById('acceptBtn').addEventListener('click', handleAccept);
+    document.getElementById('rejectBtn').addEventListener('click', handleReject);
+    document.getElementById('regenerateBtn').addEventListener('click', handleRegenerate);
+    document.getElementById('editBtn').addEventListener('click', toggleEditing);
+    document.getElementById('enhancedEditor').addEventListener('input', () => {
+        renderStatus();
+        clearTimeout(diffTimer);
+        diffTimer = setTimeout(renderDiff, EDIT_DIFF_DELAY_MS);
+    });
+    document.addEventListener('keydown', event => {
+        if (event.key === 'Escape') {
+            handleReject();
+        } else if (event.key === 'Enter' && (event.ctrlKey || event.metaKey)) {
+            event.preventDefault();
+            handleAccept();
+        } else if (event.altKey && /^[1-9]$/.test(event.key)) {
+            event.preventDefault();
+            selectCandidate(parseInt(event.key, 10) - 1);
+        }
+    });
+
+    // Listen before asking for the current state so no update is missed
+    await listen('review-updated', event => showReview(event.payload));
+    const current = await invoke('get_review');
+    if (current) {
+        showReview(current);
+    }
+}
+
+/**
+ * Split text into words and the whitespace between them
+ */
+function tokenize(text) {
+    return text.split(/(\s+)/).filter(token => token.length > 0);
+}
+
+/**
+ * Word diff of two texts, as a list of { type: 'same' | 'removed' | 'added', text }
+ */
+function diffWords(before, after) {
+    const a = tokenize(before);
+    const b = tokenize(after);
+    if (a.length * b.length > MAX_DIFF_CELLS) {
+        return [{ type: 'removed', text: before }, { type: 'added', text: after }];
+    }
+
+    // Longest common subsequence, filled in from the end
+    const lengths = Array.from({ length: a.length + 1 }, () => new Uint32Array(b.length + 1));
+    for (let i = a.length - 1; i >= 0; i--) {
+        for (let j = b.length - 1; j >= 0; j--) {
+            lengths[i][j] = a[i] === b[j]
+                ? lengths[i + 1][j + 1] + 1
+                : Math.max(lengths[i + 1][j], lengths[i][j + 1]);
+        }
+    }
+
+    const parts = [];
+    let i = 0;
+    let j = 0;
+    while (i < a.length || j < b.length) {
+        if (i < a.length && j < b.length && a[i] === b[j]) {
+            parts.push({ type: 'same', text: a[i++] });
+            j++;
+        } else if (j < b.length && (i === a.length || lengths[i][j + 1] >= lengths[i + 1][j])) {
+            parts.push({ type: 'added', text: b[j++] });
+        } else {
+            parts.push({ type: 'removed', text: a[i++] });
+        }
+    }
+    return parts;
+}
+
+/**
+ * Fill a pane with the diff parts of one side, highlighting `highlight`
+ */
+function renderPane(pane, parts, hidden, highlight) {
+    pane.replaceChildren();
+    for (const part of parts) {
+        if (part.type === hidden) continue;
+        if (part.type === 'same' || !part.text.trim()) {
+            pane.appendChild(document.createTextNode(part.text));
+        } else {
+            const span = document.createElement('span');
+            span.className = highlight;
+            span.textContent = part.text;
+            pane.appendChild(span);
+        }
+    }
+}
+
+/**
+ * The candidate being looked at
+ */
+function selectedCandidate() {
+    return review?.candidates[selected] ?? null;
+}
+
+/**
+ * The selected candidate's text, with the user's edits
+ */
+function selectedText() {
+    if (editing) {
+        return document.getElementById('enhancedEditor').value;
+    }
+    return edits[selected] ?? selectedCandidate()?.enhanced ?? '';
+}
+
+/**
+ * The word diff of the original and `text`, computed once per pair of texts
+ */
+function cachedDiff(text) {
+    if (lastDiff?.original !== review.original || lastDiff?.text !== text) {
+        lastDiff = { original: review.original, text, parts: diffWords(review.original, text) };
+    }
+    return lastDiff.parts;
+}
+
+/**
+ * Show the original with removed words and the enhanced text with added words.
+ * While the candidate is still generating both are shown as they are, since
+ * the diff would be redone for every streamed word
+ */
+function renderDiff() {
+    if (!review) return;
+    clearTimeout(diffTimer);
+    const text = selectedText();
+    if (selectedCandidate()?.status === 'generating') {
+        document.getElementById('originalText').textContent = review.original;
+        document.getElementById('enhancedText').textContent = text;
+        return;
+    }
+    const parts = cachedDiff(text);
+    renderPane(document.getElementById('originalText'), parts, 'added', 'diff-removed');
+    renderPane(document.getElementById('enhancedText'), parts, 'removed', 'diff-added');
+}
+
+/**
+ * One button per candidate, when there is more than one
+ */
+function renderPicker() {
+    const picker = document.getElementById('candidatePicker');
+    picker.classList.toggle('hidden', review.candidates.length < 2);
+    picker.replaceChildren();
+    review.candidates.forEach((candidate, index) => {
+        const button = document.createElement('button');
+        const marker = { generating: '…', ready: '', failed: ' ✕', cancelled: ' ⏹' }[candidate.status];
+        button.className = `text-xs py-1 px-3 rounded-lg border transition-colors ${index === selected
+            ? 'bg-blue-500/20 border-blue-500 text-white'
+            : 'bg-gray-800 border-gray-600 text-gray-300 hover:border-gray-500'}`;
+        button.textContent = `${[index + 1, candidate.model].filter(Boolean).join(' · ')}${marker}`;
+        button.title = candidate.seed != null ? `Seed ${candidate.seed}` : candidate.model;
+        button.addEventListener('click', () => selectCandidate(index));
+        picker.appendChild(button);
+    });
+}
+
+/**
+ * Show the selected candidate's status and enable the buttons that apply
+ */
+function renderStatus() {
+    const candidate = selectedCandidate();
+    if (!candidate) return;
+    const generating = candidate.status === 'generating';
+
+    const source = [review.preset, candidate.model].filter(Boolean).join(' · ');
+    document.getElementById('reviewStatus').textContent = {
+        generating: `Generating... ${source}`,
+        ready: source,
+        failed: 'Enhancement failed',
+        cancelled: 'Enhancement cancelled'
+    }[candidate.status];
+
+    const errorText = document.getElementById('reviewError');
+    errorText.textContent = candidate.error ?? '';
+    errorText.classList.toggle('hidden', !candidate.error);
+
+    document.getElementById('acceptBtn').disabled = candidate.status !== 'ready' || !selectedText().trim();
+    document.getElementById('editBtn').disabled = generating;
+}
+
+/**
+ * Show a review state sent by the backend
+ */
+function showReview(state) {
+    // A different text, or new candidates for it, start from scratch
+    const restarted = !review || review.original !== state.original
+        || state.candidates.length !== review.candidates.length
+        || state.candidates.some((candidate, index) => candidate.status === 'generating' && review.candidates[index].status !== 'generating');
+    if (restarted) {
+        edits = {};
+        if (editing) {
+            setEditing(false);
+        }
+    }
+    if (!review || review.original !== state.original || selected >= state.candidates.length) {
+        selected = 0;
+    }
+    review = state;
+
+    renderPicker();
+    renderStatus();
+    renderDiff();
+}
+
+/**
+ * Look at another candidate, keeping edits made to this one
+ */
+function selectCandidate(index) {
+    if (!review || index >= review.candidates.length || index === selected) return;
+    if (editing) {
+        edits[selected] = document.getElementById('enhancedEditor').value;
+        setEditing(false);
+    }
+    selected = index;
+    renderPicker();
+    renderStatus();
+    renderDiff();
+}
+
+/**
+ * Switch the enhanced side between the diff and an editor
+ */
+function setEditing(on) {
+    const editor = document.getElementById('enhancedEditor');
+    if (on) {
+        editor.value = selectedText();
+    }
+    editing = on;
+    editor.classList.toggle('hidden', !on);
+    document.getElementById('enhancedText').classList.toggle('hidden', on);
+    document.getElementById('editBtn').textContent = on ? 'Show Changes' : 'Edit';
+    if (on) {
+        editor.focus();
+    }
+}
+
+/**
+ * Handle the edit button
+ */
+function toggleEditing() {
+    if (!selectedCandidate() || selectedCandidate().status === 'generating') return;
+    if (editing) {
+        // Keep the edits, but show what they change
+        edits[selected] = document.getElementById('enhancedEditor').value;
+    }
+    setEditing(!editing);
+    renderStatus();
+    renderDiff();
+}
+
+/**
+ * Accept the chosen (possibly edited) text, which copies it and closes the window
+ */
+async function handleAccept() {
+    if (!review || document.getElementById('acceptBtn').disabled) return;
+    const text = selectedText();
+
+    try {
+        await invoke('accept_review', { text, candidate: selected });
+    } catch (error) {
+        console.error('❌ Failed to accept review:', error);
+        const errorText = document.getElementById('reviewError');
+        errorText.textContent = error;
+        errorText.classList.remove('hidden');
+    }
+}
+
+/**
+ * Reject the enhancement, leaving the clipboard as it was
+ */
+async function handleReject() {
+    try {
+        await invoke('reject_review');
+    } catch (error) {
+        console.error('❌ Failed to reject review:', error);
+    }
+}
+
+/**
+ * Generate new candidates for the same text
+ */
+async function handleRegenerate() {
+    try {
+        await invoke('regenerate_review');
+    } catch (error) {
+        console.error('❌ Failed to regenerate:', error);
+    }
+}
+
+if (document.readyState === 'loading') {
+    document.addEventListener('DOMContentLoaded', init);
+} else {
+    init();
+}